@@ -1,5 +1,9 @@
+use crate::config_file;
+use crate::core::{fetch, EvictionPolicy};
+use crate::logging::LogFormat;
+use anyhow::Context;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -9,12 +13,83 @@ pub struct Config {
     pub upload_dir: PathBuf,
     #[serde(default = "default_max_file_size")]
     pub max_file_size: usize,
+    // 上传以外的接口允许的请求体大小，上传接口使用 max_file_size
+    #[serde(default = "default_max_body_size")]
+    pub max_body_size: usize,
     #[serde(default = "default_max_sample_size")]
     pub max_sample_size: usize,
+    // uniform 和 golden 采样的窗口数上限，超过时放大窗口，避免极小的窗口产生海量偏移和拷贝
+    #[serde(default = "default_max_windows")]
+    pub max_windows: usize,
+    // 采样结果缓存的容量
     #[serde(default = "default_cache_size")]
     pub cache_size: usize,
+    // 直方图、熵分布、渲染图各自的缓存容量，互不挤占，也不挤占采样结果
+    #[serde(default = "default_cache_size_analysis")]
+    pub cache_size_analysis: usize,
+    // 内存缓存的淘汰策略，对所有缓存生效
+    #[serde(default)]
+    pub cache_policy: EvictionPolicy,
     #[serde(default = "default_max_connections")]
     pub max_connections: usize,
+    #[serde(default)]
+    pub cache_dir: Option<PathBuf>,
+    #[serde(default = "default_cache_disk_size")]
+    pub cache_disk_size: usize,
+    // 上传文件的存活时间（秒），0 表示不自动清理
+    #[serde(default)]
+    pub file_ttl_secs: u64,
+    // 上传幂等键的保留时间（秒），0 表示忽略 Idempotency-Key
+    #[serde(default = "default_idempotency_ttl_secs")]
+    pub idempotency_ttl_secs: u64,
+    #[serde(default = "default_ws_idle_secs")]
+    pub ws_idle_secs: u64,
+    // 采样专用线程池的线程数
+    #[serde(default = "default_sampler_threads")]
+    pub sampler_threads: usize,
+    // 管理接口的访问令牌，未设置时管理接口不可用
+    #[serde(default)]
+    pub admin_token: Option<Secret>,
+    // 上传和删除文件所需的访问令牌，未设置时不校验（本地开发）
+    #[serde(default)]
+    pub api_token: Option<Secret>,
+    // 每个客户端每秒最多的采样请求数（HTTP 按 IP，WebSocket 按连接），0 表示不限制
+    #[serde(default)]
+    pub sample_rate_limit: u32,
+    // 上传表单中文件字段的名称
+    #[serde(default = "default_upload_field")]
+    pub upload_field: String,
+    // 允许跨域访问的来源，["*"] 表示不限制；为空时不返回 CORS 头，只允许同源访问
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    // /api/upload/from-url 允许拉取的 URL 协议和主机，主机列表为空时该接口不可用；
+    // 主机可以写作 *.example.com 匹配它的所有子域名
+    #[serde(default = "default_fetch_allowed_schemes")]
+    pub fetch_allowed_schemes: Vec<String>,
+    #[serde(default)]
+    pub fetch_allowed_hosts: Vec<String>,
+    // 日志格式（text 或 json）和最低日志级别（trace、debug、info、warn、error）
+    #[serde(default)]
+    pub log_format: LogFormat,
+    #[serde(default = "default_log_level")]
+    pub log_level: String,
+}
+
+// 敏感配置项，Debug 输出时隐藏内容，避免写入启动日志
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Secret(***)")
+    }
 }
 
 fn default_port() -> u16 {
@@ -26,49 +101,258 @@ fn default_upload_dir() -> PathBuf {
 fn default_max_file_size() -> usize {
     10 * 1024 * 1024 * 1024 // 10GB
 }
+fn default_max_body_size() -> usize {
+    1024 * 1024 // 1MB
+}
 fn default_max_sample_size() -> usize {
     128 * 1024 * 1024 // 128MB
 }
+fn default_max_windows() -> usize {
+    16384
+}
 fn default_cache_size() -> usize {
     512 * 1024 * 1024 // 512MB
 }
+fn default_cache_size_analysis() -> usize {
+    64 * 1024 * 1024 // 64MB
+}
 fn default_max_connections() -> usize {
     100
 }
+fn default_cache_disk_size() -> usize {
+    4 * 1024 * 1024 * 1024 // 4GB
+}
+fn default_ws_idle_secs() -> u64 {
+    60
+}
+fn default_idempotency_ttl_secs() -> u64 {
+    24 * 60 * 60
+}
+fn default_fetch_allowed_schemes() -> Vec<String> {
+    vec!["http".to_string()]
+}
+
+// 逗号分隔的列表，忽略空项
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+fn default_upload_field() -> String {
+    "file".to_string()
+}
+fn default_log_level() -> String {
+    "info".to_string()
+}
+fn default_sampler_threads() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
 
 impl Config {
     pub fn from_env() -> anyhow::Result<Self> {
         dotenv::dotenv().ok();
 
+        // 优先级：环境变量 > 配置文件 > 内置默认值
+        let base = match std::env::var("CONFIG_FILE") {
+            Ok(path) => Self::from_file(Path::new(&path))?,
+            Err(_) => Self::default(),
+        };
+
         let config = Self {
             port: std::env::var("PORT")
                 .ok()
                 .and_then(|v| v.parse().ok())
-                .unwrap_or_else(default_port),
+                .unwrap_or(base.port),
             upload_dir: std::env::var("UPLOAD_DIR")
                 .ok()
                 .map(PathBuf::from)
-                .unwrap_or_else(default_upload_dir),
+                .unwrap_or(base.upload_dir),
             max_file_size: std::env::var("MAX_FILE_SIZE")
                 .ok()
                 .and_then(|v| v.parse().ok())
-                .unwrap_or_else(default_max_file_size),
+                .unwrap_or(base.max_file_size),
+            max_body_size: std::env::var("MAX_BODY_SIZE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(base.max_body_size),
             max_sample_size: std::env::var("MAX_SAMPLE_SIZE")
                 .ok()
                 .and_then(|v| v.parse().ok())
-                .unwrap_or_else(default_max_sample_size),
-            cache_size: std::env::var("CACHE_SIZE")
+                .unwrap_or(base.max_sample_size),
+            max_windows: std::env::var("MAX_WINDOWS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(base.max_windows),
+            // CACHE_SIZE 是 CACHE_SIZE_SAMPLES 的旧名称
+            cache_size: std::env::var("CACHE_SIZE_SAMPLES")
+                .or_else(|_| std::env::var("CACHE_SIZE"))
                 .ok()
                 .and_then(|v| v.parse().ok())
-                .unwrap_or_else(default_cache_size),
+                .unwrap_or(base.cache_size),
+            cache_size_analysis: std::env::var("CACHE_SIZE_ANALYSIS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(base.cache_size_analysis),
+            cache_policy: std::env::var("CACHE_POLICY")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(base.cache_policy),
             max_connections: std::env::var("MAX_CONNECTIONS")
                 .ok()
                 .and_then(|v| v.parse().ok())
-                .unwrap_or_else(default_max_connections),
+                .unwrap_or(base.max_connections),
+            cache_dir: std::env::var("CACHE_DIR")
+                .ok()
+                .map(PathBuf::from)
+                .or(base.cache_dir),
+            cache_disk_size: std::env::var("CACHE_DISK_SIZE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(base.cache_disk_size),
+            file_ttl_secs: std::env::var("FILE_TTL_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(base.file_ttl_secs),
+            idempotency_ttl_secs: std::env::var("IDEMPOTENCY_TTL_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(base.idempotency_ttl_secs),
+            ws_idle_secs: std::env::var("WS_IDLE_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(base.ws_idle_secs),
+            sampler_threads: std::env::var("SAMPLER_THREADS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(base.sampler_threads),
+            admin_token: std::env::var("ADMIN_TOKEN")
+                .ok()
+                .map(Secret)
+                .or(base.admin_token),
+            api_token: std::env::var("API_TOKEN")
+                .ok()
+                .map(Secret)
+                .or(base.api_token),
+            sample_rate_limit: std::env::var("SAMPLE_RATE_LIMIT")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(base.sample_rate_limit),
+            upload_field: std::env::var("UPLOAD_FIELD").unwrap_or(base.upload_field),
+            allowed_origins: std::env::var("ALLOWED_ORIGINS")
+                .ok()
+                .map(|v| split_list(&v))
+                .unwrap_or(base.allowed_origins),
+            fetch_allowed_schemes: std::env::var("FETCH_ALLOWED_SCHEMES")
+                .ok()
+                .map(|v| split_list(&v))
+                .unwrap_or(base.fetch_allowed_schemes),
+            fetch_allowed_hosts: std::env::var("FETCH_ALLOWED_HOSTS")
+                .ok()
+                .map(|v| split_list(&v))
+                .unwrap_or(base.fetch_allowed_hosts),
+            log_format: std::env::var("LOG_FORMAT")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(base.log_format),
+            log_level: std::env::var("LOG_LEVEL").unwrap_or(base.log_level),
         };
 
         Ok(config)
     }
+
+    // 从 TOML 配置文件加载，未出现的字段使用内置默认值
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        let table = config_file::parse(&text)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))?;
+
+        serde_json::from_value(serde_json::Value::Object(table))
+            .with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    // 校验配置，启动时发现不合理的取值直接失败
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.port == 0 {
+            anyhow::bail!("PORT must not be 0");
+        }
+        if self.max_body_size == 0 {
+            anyhow::bail!("MAX_BODY_SIZE must be greater than 0");
+        }
+        if self.max_sample_size == 0 {
+            anyhow::bail!("MAX_SAMPLE_SIZE must be greater than 0");
+        }
+        if self.max_windows == 0 {
+            anyhow::bail!("MAX_WINDOWS must be greater than 0");
+        }
+        if self.cache_size == 0 {
+            anyhow::bail!("CACHE_SIZE_SAMPLES must be greater than 0");
+        }
+        if self.cache_size_analysis == 0 {
+            anyhow::bail!("CACHE_SIZE_ANALYSIS must be greater than 0");
+        }
+        if self.sampler_threads == 0 {
+            anyhow::bail!("SAMPLER_THREADS must be greater than 0");
+        }
+        if self
+            .admin_token
+            .as_ref()
+            .is_some_and(|token| token.expose().is_empty())
+        {
+            anyhow::bail!("ADMIN_TOKEN must not be empty");
+        }
+        if self
+            .api_token
+            .as_ref()
+            .is_some_and(|token| token.expose().is_empty())
+        {
+            anyhow::bail!("API_TOKEN must not be empty");
+        }
+        if self.upload_field.is_empty() {
+            anyhow::bail!("UPLOAD_FIELD must not be empty");
+        }
+        if self.allowed_origins.len() > 1 && self.allowed_origins.iter().any(|o| o == "*") {
+            anyhow::bail!("ALLOWED_ORIGINS must be either * or a list of origins");
+        }
+        if let Some(origin) = self
+            .allowed_origins
+            .iter()
+            .find(|origin| *origin != "*" && !origin.contains("://"))
+        {
+            anyhow::bail!("ALLOWED_ORIGINS entry {:?} is not an origin", origin);
+        }
+        if let Some(scheme) = self
+            .fetch_allowed_schemes
+            .iter()
+            .find(|scheme| !fetch::SUPPORTED_SCHEMES.contains(&scheme.as_str()))
+        {
+            anyhow::bail!(
+                "FETCH_ALLOWED_SCHEMES entry {:?} is not supported (supported: {})",
+                scheme,
+                fetch::SUPPORTED_SCHEMES.join(", ")
+            );
+        }
+        if self.log_level.parse::<tracing::Level>().is_err() {
+            anyhow::bail!(
+                "LOG_LEVEL must be one of trace, debug, info, warn, error, got {:?}",
+                self.log_level
+            );
+        }
+        if self.max_sample_size > self.max_file_size {
+            anyhow::bail!(
+                "MAX_SAMPLE_SIZE ({}) must not exceed MAX_FILE_SIZE ({})",
+                self.max_sample_size,
+                self.max_file_size
+            );
+        }
+
+        Ok(())
+    }
 }
 
 impl Default for Config {
@@ -77,9 +361,105 @@ impl Default for Config {
             port: default_port(),
             upload_dir: default_upload_dir(),
             max_file_size: default_max_file_size(),
+            max_body_size: default_max_body_size(),
             max_sample_size: default_max_sample_size(),
+            max_windows: default_max_windows(),
             cache_size: default_cache_size(),
+            cache_size_analysis: default_cache_size_analysis(),
+            cache_policy: EvictionPolicy::default(),
             max_connections: default_max_connections(),
+            cache_dir: None,
+            cache_disk_size: default_cache_disk_size(),
+            file_ttl_secs: 0,
+            idempotency_ttl_secs: default_idempotency_ttl_secs(),
+            ws_idle_secs: default_ws_idle_secs(),
+            sampler_threads: default_sampler_threads(),
+            admin_token: None,
+            api_token: None,
+            sample_rate_limit: 0,
+            upload_field: default_upload_field(),
+            allowed_origins: Vec::new(),
+            fetch_allowed_schemes: default_fetch_allowed_schemes(),
+            fetch_allowed_hosts: Vec::new(),
+            log_format: LogFormat::default(),
+            log_level: default_log_level(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 把默认配置中的一项改为非法取值
+    type Invalidate = fn(&mut Config);
+
+    #[test]
+    fn default_config_is_valid() {
+        Config::default().validate().unwrap();
+    }
+
+    #[test]
+    fn validate_rejects_each_invalid_value() {
+        // 每项只改一个取值，错误信息应指出对应的配置项
+        let cases: [(&str, Invalidate); 15] = [
+            ("PORT", |c| c.port = 0),
+            ("MAX_BODY_SIZE", |c| c.max_body_size = 0),
+            ("MAX_SAMPLE_SIZE", |c| c.max_sample_size = 0),
+            ("MAX_WINDOWS", |c| c.max_windows = 0),
+            ("CACHE_SIZE_SAMPLES", |c| c.cache_size = 0),
+            ("CACHE_SIZE_ANALYSIS", |c| c.cache_size_analysis = 0),
+            ("SAMPLER_THREADS", |c| c.sampler_threads = 0),
+            ("ADMIN_TOKEN", |c| {
+                c.admin_token = Some(Secret(String::new()))
+            }),
+            ("API_TOKEN", |c| c.api_token = Some(Secret(String::new()))),
+            ("UPLOAD_FIELD", |c| c.upload_field = String::new()),
+            ("ALLOWED_ORIGINS", |c| {
+                c.allowed_origins = vec!["*".to_string(), "https://example.com".to_string()]
+            }),
+            ("ALLOWED_ORIGINS", |c| {
+                c.allowed_origins = vec!["example.com".to_string()]
+            }),
+            ("FETCH_ALLOWED_SCHEMES", |c| {
+                c.fetch_allowed_schemes = vec!["http".to_string(), "ftp".to_string()]
+            }),
+            ("LOG_LEVEL", |c| c.log_level = "verbose".to_string()),
+            ("MAX_SAMPLE_SIZE", |c| {
+                c.max_sample_size = c.max_file_size + 1
+            }),
+        ];
+
+        for (name, invalidate) in cases {
+            let mut config = Config::default();
+            invalidate(&mut config);
+            let error = config.validate().unwrap_err().to_string();
+            assert!(
+                error.contains(name),
+                "expected {} error, got {:?}",
+                name,
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn validate_accepts_boundary_values() {
+        let config = Config {
+            max_sample_size: 1024,
+            max_file_size: 1024,
+            allowed_origins: vec!["*".to_string()],
+            ..Default::default()
+        };
+        config.validate().unwrap();
+
+        let config = Config {
+            allowed_origins: vec![
+                "https://example.com".to_string(),
+                "http://localhost:5173".to_string(),
+            ],
+            ..Default::default()
+        };
+        config.validate().unwrap();
+    }
+}
@@ -0,0 +1,164 @@
+use anyhow::{anyhow, bail, Result};
+use serde_json::{Map, Number, Value};
+
+// 配置文件解析：Config 是平铺结构，只需支持 TOML 的一个子集，
+// 即顶层的 `key = value`，值可以是字符串、整数、浮点数、布尔值和单行数组
+pub fn parse(text: &str) -> Result<Map<String, Value>> {
+    let mut table = Map::new();
+
+    for (index, raw_line) in text.lines().enumerate() {
+        let line_no = index + 1;
+        let line = strip_comment(raw_line).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if line.starts_with('[') {
+            bail!("line {}: tables are not supported", line_no);
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow!("line {}: expected `key = value`", line_no))?;
+
+        let key = parse_key(key.trim()).ok_or_else(|| anyhow!("line {}: invalid key", line_no))?;
+        let value =
+            parse_value(value.trim()).map_err(|e| anyhow!("line {}: {}", line_no, e))?;
+
+        if table.insert(key.clone(), value).is_some() {
+            bail!("line {}: duplicate key `{}`", line_no, key);
+        }
+    }
+
+    Ok(table)
+}
+
+// 去掉字符串之外的 `#` 注释
+fn strip_comment(line: &str) -> &str {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some('"') if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' => return &line[..i],
+            None => {}
+        }
+    }
+
+    line
+}
+
+fn parse_key(key: &str) -> Option<String> {
+    if let Some(inner) = key.strip_prefix('"').and_then(|k| k.strip_suffix('"')) {
+        return Some(inner.to_string());
+    }
+
+    let valid = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    valid.then(|| key.to_string())
+}
+
+fn parse_value(value: &str) -> Result<Value> {
+    if let Some(inner) = value.strip_prefix('"') {
+        let inner = inner
+            .strip_suffix('"')
+            .ok_or_else(|| anyhow!("unterminated string"))?;
+        return Ok(Value::String(unescape(inner)?));
+    }
+
+    if let Some(inner) = value.strip_prefix('\'') {
+        let inner = inner
+            .strip_suffix('\'')
+            .ok_or_else(|| anyhow!("unterminated string"))?;
+        return Ok(Value::String(inner.to_string()));
+    }
+
+    if let Some(inner) = value.strip_prefix('[') {
+        let inner = inner
+            .strip_suffix(']')
+            .ok_or_else(|| anyhow!("unterminated array"))?;
+        return split_array(inner)
+            .into_iter()
+            .map(|item| parse_value(item.trim()))
+            .collect::<Result<Vec<_>>>()
+            .map(Value::Array);
+    }
+
+    match value {
+        "true" => return Ok(Value::Bool(true)),
+        "false" => return Ok(Value::Bool(false)),
+        _ => {}
+    }
+
+    // 数字允许使用下划线分隔，如 10_737_418_240
+    let digits = value.replace('_', "");
+    if let Ok(n) = digits.parse::<u64>() {
+        return Ok(Value::Number(n.into()));
+    }
+    if let Ok(n) = digits.parse::<i64>() {
+        return Ok(Value::Number(n.into()));
+    }
+    if let Some(n) = digits.parse::<f64>().ok().and_then(Number::from_f64) {
+        return Ok(Value::Number(n));
+    }
+
+    bail!("unsupported value `{}`", value)
+}
+
+// 按顶层逗号拆分数组元素，忽略字符串内的逗号
+fn split_array(inner: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut start = 0;
+
+    for (i, c) in inner.char_indices() {
+        match quote {
+            Some('"') if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == ',' => {
+                items.push(&inner[start..i]);
+                start = i + 1;
+            }
+            None => {}
+        }
+    }
+    items.push(&inner[start..]);
+
+    // 允许末尾逗号和空数组
+    items.retain(|item| !item.trim().is_empty());
+    items
+}
+
+fn unescape(s: &str) -> Result<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('"') => out.push('"'),
+            Some('\\') => out.push('\\'),
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            other => bail!("unsupported escape sequence `\\{}`", other.unwrap_or(' ')),
+        }
+    }
+
+    Ok(out)
+}
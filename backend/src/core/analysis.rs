@@ -0,0 +1,447 @@
+use rayon::prelude::*;
+
+// 并行扫描时每块的大小
+const SCAN_CHUNK: usize = 4 * 1024 * 1024;
+
+// 提取的字符串超过该长度时截断
+const MAX_STRING_LEN: usize = 1024;
+
+// 自相关分析从文件中取出的每个连续块的大小
+const AUTOCORR_BLOCK: usize = 64 * 1024;
+
+// 256 桶字节直方图
+pub fn histogram(data: &[u8]) -> [u64; 256] {
+    data.par_chunks(SCAN_CHUNK)
+        .map(|chunk| {
+            let mut counts = [0u64; 256];
+            for &byte in chunk {
+                counts[byte as usize] += 1;
+            }
+            counts
+        })
+        .reduce(
+            || [0u64; 256],
+            |mut total, counts| {
+                for (sum, count) in total.iter_mut().zip(counts) {
+                    *sum += count;
+                }
+                total
+            },
+        )
+}
+
+// 由直方图计算香农熵，单位为比特/字节（0–8）
+// 整个文件的熵直接由缓存的直方图得出，只有按窗口的熵分布需要重新扫描
+pub fn entropy_from_histogram(counts: &[u64; 256]) -> f32 {
+    let total: u64 = counts.iter().sum();
+    if total == 0 {
+        return 0.0;
+    }
+
+    let total = total as f64;
+    let entropy: f64 = counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / total;
+            -p * p.log2()
+        })
+        .sum();
+    entropy as f32
+}
+
+// 由直方图计算字节值的分位数：对每个 q（0–1）返回累计计数首次达到 q × 总数的字节值
+// 直方图为空时全部返回 0
+pub fn quantiles_from_histogram(counts: &[u64; 256], qs: &[f32]) -> Vec<u8> {
+    let total: u64 = counts.iter().sum();
+    qs.iter()
+        .map(|&q| {
+            // q 只有 f32 精度，按该精度容差取整，避免 0.4 × 10 因表示误差变成 5；
+            // 至少取到一个字节，q = 0 时返回出现过的最小字节值
+            let product = q as f64 * total as f64;
+            let target = ((product - product * f32::EPSILON as f64).ceil() as u64).max(1);
+            let mut cumulative = 0;
+            counts
+                .iter()
+                .position(|&count| {
+                    cumulative += count;
+                    cumulative >= target
+                })
+                .unwrap_or(0) as u8
+        })
+        .collect()
+}
+
+// 每 window_size 字节计算一个熵值，最后一个窗口可能不足 window_size
+// with_highbit 时同时返回每个窗口中高位字节（>= 0x80）的比例，与熵一一对应：
+// 文本和二进制的熵可能相近，但文本几乎没有高位字节
+pub fn entropy_profile(
+    data: &[u8],
+    window_size: usize,
+    with_highbit: bool,
+) -> (Vec<f32>, Option<Vec<f32>>) {
+    let (entropy_values, highbit): (Vec<f32>, Vec<f32>) = data
+        .par_chunks(window_size)
+        .map(|window| {
+            let mut counts = [0u64; 256];
+            for &byte in window {
+                counts[byte as usize] += 1;
+            }
+
+            let highbit = if with_highbit {
+                counts[0x80..].iter().sum::<u64>() as f32 / window.len() as f32
+            } else {
+                0.0
+            };
+            (entropy_from_histogram(&counts), highbit)
+        })
+        .unzip();
+
+    (entropy_values, with_highbit.then_some(highbit))
+}
+
+// 文件区段的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SectionKind {
+    Zero,
+    Text,
+    Code,
+    Compressed,
+}
+
+impl SectionKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Zero => "zero",
+            Self::Text => "text",
+            Self::Code => "code",
+            Self::Compressed => "compressed",
+        }
+    }
+}
+
+// 区段分类的阈值：熵不低于 compressed_entropy 视为压缩/加密数据；
+// 熵不高于 text_entropy 且高位字节比例不高于 text_highbit 视为文本；其余视为代码等结构化二进制
+#[derive(Debug, Clone, Copy)]
+pub struct SectionThresholds {
+    pub compressed_entropy: f32,
+    pub text_entropy: f32,
+    pub text_highbit: f32,
+}
+
+// 按窗口分类后合并相邻的同类窗口，返回覆盖整个数据的 (start, end, kind)，按 start 升序
+// 全零窗口单独分为 zero；最后一个窗口可能不足 window_size
+pub fn sections(
+    data: &[u8],
+    window_size: usize,
+    thresholds: &SectionThresholds,
+) -> Vec<(usize, usize, SectionKind)> {
+    let kinds: Vec<SectionKind> = data
+        .par_chunks(window_size)
+        .map(|window| {
+            let mut counts = [0u64; 256];
+            for &byte in window {
+                counts[byte as usize] += 1;
+            }
+            if counts[0] == window.len() as u64 {
+                return SectionKind::Zero;
+            }
+
+            let entropy = entropy_from_histogram(&counts);
+            let highbit = counts[0x80..].iter().sum::<u64>() as f32 / window.len() as f32;
+            if entropy >= thresholds.compressed_entropy {
+                SectionKind::Compressed
+            } else if entropy <= thresholds.text_entropy && highbit <= thresholds.text_highbit {
+                SectionKind::Text
+            } else {
+                SectionKind::Code
+            }
+        })
+        .collect();
+
+    let mut sections: Vec<(usize, usize, SectionKind)> = Vec::new();
+    for (index, kind) in kinds.into_iter().enumerate() {
+        let start = index * window_size;
+        let end = (start + window_size).min(data.len());
+        match sections.last_mut() {
+            Some((_, last_end, last_kind)) if *last_kind == kind => *last_end = end,
+            _ => sections.push((start, end, kind)),
+        }
+    }
+    sections
+}
+
+// 字节分类，供前端直接按类别着色：0 = 空字节，1 = 可打印 ASCII，2 = 空白/控制字符，3 = 高位字节
+pub fn classify(data: &[u8]) -> Vec<u8> {
+    data.par_iter().map(|&byte| byte_class(byte)).collect()
+}
+
+fn byte_class(byte: u8) -> u8 {
+    match byte {
+        0x00 => 0,
+        0x20..=0x7e => 1,
+        0x01..=0x1f | 0x7f => 2,
+        _ => 3,
+    }
+}
+
+// 把数据均分为 cells 个连续区间，每个区间聚合为一个字节：字节均值，或 dominant_class 时出现最多的字节分类
+// 数据少于 cells 字节时，多出的空区间为 0
+pub fn thumbnail(data: &[u8], cells: usize, dominant_class: bool) -> Vec<u8> {
+    (0..cells)
+        .into_par_iter()
+        .map(|cell| {
+            let start = cell * data.len() / cells;
+            let end = (cell + 1) * data.len() / cells;
+            let bytes = &data[start..end];
+            if bytes.is_empty() {
+                return 0;
+            }
+
+            if dominant_class {
+                dominant_class_of(bytes)
+            } else {
+                let sum: u64 = bytes.iter().map(|&byte| byte as u64).sum();
+                (sum / bytes.len() as u64) as u8
+            }
+        })
+        .collect()
+}
+
+// 每 window_size 字节取出现最多的字节分类，最后一个窗口可能不足 window_size
+pub fn class_summary(data: &[u8], window_size: usize) -> Vec<u8> {
+    data.par_chunks(window_size)
+        .map(dominant_class_of)
+        .collect()
+}
+
+// 数量相同时取编号小的分类
+fn dominant_class_of(bytes: &[u8]) -> u8 {
+    let mut counts = [0usize; 4];
+    for &byte in bytes {
+        counts[byte_class(byte) as usize] += 1;
+    }
+    (0..4u8)
+        .max_by_key(|&class| (counts[class as usize], std::cmp::Reverse(class)))
+        .unwrap_or(0)
+}
+
+// 查找长度不小于 min_run 的全零区间，返回 (offset, length)，按 offset 升序
+pub fn zero_runs(data: &[u8], min_run: usize) -> Vec<(usize, usize)> {
+    byte_runs(data, min_run, usize::MAX, |byte| byte == 0)
+        .into_iter()
+        .map(|(start, end)| (start, end - start))
+        .collect()
+}
+
+// 提取不短于 min_len 的可打印 ASCII 字符串（0x20–0x7E 及制表符），最多 limit 个
+// 返回 (offset, 字符串)，超长的字符串只保留前 MAX_STRING_LEN 个字符
+pub fn extract_strings(data: &[u8], min_len: usize, limit: usize) -> Vec<(usize, String)> {
+    byte_runs(data, min_len, limit, |byte| {
+        byte == b'\t' || (0x20..=0x7e).contains(&byte)
+    })
+    .into_iter()
+    .map(|(start, end)| {
+        let end = end.min(start + MAX_STRING_LEN);
+        // 只包含 ASCII 字节，转换不会失败
+        let text = String::from_utf8_lossy(&data[start..end]).into_owned();
+        (start, text)
+    })
+    .collect()
+}
+
+// 字节序列在延迟 1..=max_lag 上的归一化自相关系数（-1–1），记录长度、步长等周期结构表现为峰值
+// 数据超过 sample 字节时只取均匀分布的若干连续块，共约 sample 字节，延迟不跨越块边界；
+// 每个延迟上的系数为块内字节对的协方差均值除以取样字节的方差，数据为常量时全部为 0
+pub fn autocorrelation(data: &[u8], max_lag: usize, sample: usize) -> Vec<f32> {
+    let blocks = autocorrelation_blocks(data, max_lag, sample);
+
+    let count: usize = blocks.iter().map(|block| block.len()).sum();
+    let sum: u64 = blocks
+        .iter()
+        .map(|block| block.iter().map(|&byte| byte as u64).sum::<u64>())
+        .sum();
+    let mean = sum as f64 / count.max(1) as f64;
+    let centered: Vec<Vec<f64>> = blocks
+        .iter()
+        .map(|block| block.iter().map(|&byte| byte as f64 - mean).collect())
+        .collect();
+    let variance =
+        centered.iter().flatten().map(|value| value * value).sum::<f64>() / count.max(1) as f64;
+    if variance == 0.0 {
+        return vec![0.0; max_lag];
+    }
+
+    (1..=max_lag)
+        .into_par_iter()
+        .map(|lag| {
+            let mut products = 0.0;
+            let mut pairs = 0;
+            for block in centered.iter().filter(|block| block.len() > lag) {
+                products += block
+                    .iter()
+                    .zip(&block[lag..])
+                    .map(|(a, b)| a * b)
+                    .sum::<f64>();
+                pairs += block.len() - lag;
+            }
+            if pairs == 0 {
+                return 0.0;
+            }
+            (products / pairs as f64 / variance) as f32
+        })
+        .collect()
+}
+
+// 自相关的取样：数据不超过 sample 字节时整体作为一块；
+// 否则把数据均分为 sample / block 段，每段开头取一块，块长至少为 2 × max_lag
+fn autocorrelation_blocks(data: &[u8], max_lag: usize, sample: usize) -> Vec<&[u8]> {
+    if data.len() <= sample {
+        return vec![data];
+    }
+
+    let block = AUTOCORR_BLOCK.max(2 * max_lag).min(sample);
+    let count = (sample / block).max(1);
+    // data.len() > sample >= count * block，每段都放得下一块
+    (0..count)
+        .map(|i| {
+            let start = i * data.len() / count;
+            &data[start..start + block]
+        })
+        .collect()
+}
+
+// 查找连续满足 pred 的字节区间 [start, end)，只保留长度不小于 min_len 的前 limit 个
+// 各块并行扫描出块内的区间，再串行拼接跨越块边界的区间；
+// 按批处理若干块，凑够 limit 个后不再扫描剩余部分
+fn byte_runs<P>(data: &[u8], min_len: usize, limit: usize, pred: P) -> Vec<(usize, usize)>
+where
+    P: Fn(u8) -> bool + Sync,
+{
+    let chunk_count = data.len().div_ceil(SCAN_CHUNK);
+    let batch = rayon::current_num_threads().max(1);
+
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for batch_start in (0..chunk_count).step_by(batch) {
+        let batch_end = (batch_start + batch).min(chunk_count);
+
+        let per_chunk: Vec<Vec<(usize, usize)>> = (batch_start..batch_end)
+            .into_par_iter()
+            .map(|index| {
+                let base = index * SCAN_CHUNK;
+                let chunk = &data[base..(base + SCAN_CHUNK).min(data.len())];
+                scan_chunk(chunk, base, min_len, limit, &pred)
+            })
+            .collect();
+
+        for (start, end) in per_chunk.into_iter().flatten() {
+            match merged.last_mut() {
+                Some((_, last_end)) if *last_end == start => *last_end = end,
+                _ => {
+                    // 新区间开始时，上一个区间已经完整，不够长的直接丢弃
+                    if merged
+                        .last()
+                        .is_some_and(|&(last_start, last_end)| last_end - last_start < min_len)
+                    {
+                        merged.pop();
+                    }
+                    merged.push((start, end));
+                }
+            }
+        }
+
+        // 最后一个区间之前的都已完整，多于 limit 个时后面的不会再进入结果
+        if merged.len() > limit {
+            break;
+        }
+    }
+
+    merged.retain(|&(start, end)| end - start >= min_len);
+    merged.truncate(limit);
+    merged
+}
+
+// 扫描单个块，返回绝对偏移的区间
+// 贴着块开头或延伸到块末尾的区间可能与相邻块相连，不论长短都保留，拼接后再过滤
+fn scan_chunk<P>(
+    chunk: &[u8],
+    base: usize,
+    min_len: usize,
+    limit: usize,
+    pred: &P,
+) -> Vec<(usize, usize)>
+where
+    P: Fn(u8) -> bool,
+{
+    let mut runs = Vec::new();
+    let mut start = None;
+
+    for (i, &byte) in chunk.iter().enumerate() {
+        match (pred(byte), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                if i - s >= min_len || s == 0 {
+                    runs.push((base + s, base + i));
+                }
+                start = None;
+
+                // 除去可能与上一块相连的第一个区间，本块已有超过 limit 个完整区间
+                if runs.len() > limit.saturating_add(1) {
+                    return runs;
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        runs.push((base + s, base + chunk.len()));
+    }
+
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_runs_are_stitched_across_chunk_boundaries() {
+        let mut data = vec![1u8; 3 * SCAN_CHUNK + 100];
+        // 跨越第一个块边界，两边各 10 字节，单独看都短于 min_run
+        data[SCAN_CHUNK - 10..SCAN_CHUNK + 10].fill(0);
+        // 从第二个块的末尾一直延伸到第四个块，中间完整覆盖第三个块
+        data[2 * SCAN_CHUNK - 5..3 * SCAN_CHUNK + 7].fill(0);
+        // 第一个块内部的区间，一个够长一个不够
+        data[100..116].fill(0);
+        data[200..205].fill(0);
+
+        assert_eq!(
+            zero_runs(&data, 16),
+            vec![
+                (100, 16),
+                (SCAN_CHUNK - 10, 20),
+                (2 * SCAN_CHUNK - 5, SCAN_CHUNK + 12),
+            ]
+        );
+    }
+
+    #[test]
+    fn zero_runs_touching_a_boundary_are_not_merged_with_the_next_run() {
+        let mut data = vec![1u8; 2 * SCAN_CHUNK];
+        // 恰好结束在块边界上的短区间，下一块开头不是 0，不与下一块的区间拼接
+        data[SCAN_CHUNK - 3..SCAN_CHUNK].fill(0);
+        data[SCAN_CHUNK + 1..SCAN_CHUNK + 4].fill(0);
+        // 延伸到数据末尾的区间
+        data[2 * SCAN_CHUNK - 8..].fill(0);
+
+        assert_eq!(zero_runs(&data, 4), vec![(2 * SCAN_CHUNK - 8, 8)]);
+        assert_eq!(
+            zero_runs(&data, 3),
+            vec![
+                (SCAN_CHUNK - 3, 3),
+                (SCAN_CHUNK + 1, 3),
+                (2 * SCAN_CHUNK - 8, 8)
+            ]
+        );
+    }
+}
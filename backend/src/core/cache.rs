@@ -1,107 +1,642 @@
-use std::collections::hash_map::DefaultHasher;
-use std::collections::{HashMap, VecDeque};
-use std::hash::{Hash, Hasher};
-use std::sync::Mutex;
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
 
-pub struct Cache {
-    capacity: usize,
-    store: Mutex<CacheStore>,
+use super::disk_cache::DiskCache;
+use super::sampler::SampleOptions;
+
+// 可缓存的值类型：提供用于容量统计的字节大小
+pub trait CacheSized: Clone + Send + Sync {
+    fn byte_size(&self) -> usize;
+
+    // 磁盘二级缓存的序列化，返回 None 表示该类型不落盘
+    fn to_disk_bytes(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn from_disk_bytes(_bytes: &[u8]) -> Option<Self> {
+        None
+    }
+
+    // 计算该值时源文件的长度；返回 Some 时 get_current 在文件长度改变（如追加写入）后把它视为过期
+    // 上传的文件按内容寻址、不会改变，记录长度只是为了可变文件的正确性
+    fn source_len(&self) -> Option<usize> {
+        None
+    }
+}
+
+// 采样数据
+impl CacheSized for Vec<u8> {
+    fn byte_size(&self) -> usize {
+        self.len()
+    }
+
+    fn to_disk_bytes(&self) -> Option<Vec<u8>> {
+        Some(self.clone())
+    }
+
+    fn from_disk_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(bytes.to_vec())
+    }
+}
+
+// 256 桶字节直方图
+impl CacheSized for [u64; 256] {
+    fn byte_size(&self) -> usize {
+        std::mem::size_of::<Self>()
+    }
+
+    fn to_disk_bytes(&self) -> Option<Vec<u8>> {
+        Some(self.iter().flat_map(|count| count.to_le_bytes()).collect())
+    }
+
+    fn from_disk_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != std::mem::size_of::<Self>() {
+            return None;
+        }
+
+        let mut histogram = [0u64; 256];
+        for (count, chunk) in histogram.iter_mut().zip(bytes.chunks_exact(8)) {
+            *count = u64::from_le_bytes(chunk.try_into().ok()?);
+        }
+        Some(histogram)
+    }
+}
+
+// 按窗口计算的熵分布
+impl CacheSized for Vec<f32> {
+    fn byte_size(&self) -> usize {
+        self.len() * std::mem::size_of::<f32>()
+    }
+
+    fn to_disk_bytes(&self) -> Option<Vec<u8>> {
+        Some(self.iter().flat_map(|value| value.to_le_bytes()).collect())
+    }
+
+    fn from_disk_bytes(bytes: &[u8]) -> Option<Self> {
+        bytes
+            .chunks_exact(4)
+            .map(|chunk| chunk.try_into().ok().map(f32::from_le_bytes))
+            .collect()
+    }
+}
+
+// 缓存键：直接保存影响结果的全部参数并按字段比较，不同的请求不会因哈希碰撞拿到别人的结果
+// 保留文件 ID 以便按文件失效
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub file_id: String,
+    // 采样方法，或分析结果的类型（"histogram"、"entropy"、"render-viridis-webp" 等）
+    pub method: String,
+    // 采样大小，或分析结果的参数（如熵分布的窗口大小）
+    pub sample_size: usize,
+    // 采样范围 [start, end)，整个文件时为空；分析结果为计算时的整个文件 [0, 文件长度)
+    pub range: Option<(usize, usize)>,
+    pub options: SampleOptions,
+    // 渲染图像的宽和高，其它结果为空
+    pub dimensions: Option<(usize, usize)>,
+}
+
+impl CacheKey {
+    // 磁盘缓存中的文件名，与键一一对应（方法名和分析类型只含字母、数字、- 和 _）
+    pub fn disk_name(&self) -> String {
+        let range = match self.range {
+            Some((start, end)) => format!("{}_{}", start, end),
+            None => "all".to_string(),
+        };
+        let name = format!(
+            "{}-{}-{}-{:08x}",
+            self.method,
+            self.sample_size,
+            range,
+            self.options.overlap.to_bits()
+        );
+        // 未对齐、逐字节采样时保持原来的文件名，已有的磁盘缓存仍然有效
+        let name = match self.options.alignment {
+            Some(alignment) => format!("{}-{}", name, alignment),
+            None => name,
+        };
+        let name = match self.options.element_size {
+            Some(element_size) => format!("{}-e{}", name, element_size),
+            None => name,
+        };
+        let name = match (self.options.row_width, self.options.rows) {
+            (None, None) => name,
+            (row_width, rows) => {
+                format!("{}-g{}x{}", name, row_width.unwrap_or(0), rows.unwrap_or(0))
+            }
+        };
+        match self.dimensions {
+            Some((width, height)) => format!("{}-d{}x{}", name, width, height),
+            None => name,
+        }
+    }
+}
+
+// 生成采样结果的缓存键
+pub fn make_key(
+    file_id: &str,
+    sample_size: usize,
+    method: &str,
+    range: Option<&Range<usize>>,
+    options: &SampleOptions,
+) -> CacheKey {
+    CacheKey {
+        file_id: file_id.to_string(),
+        method: method.to_string(),
+        sample_size,
+        range: range.map(|range| (range.start, range.end)),
+        options: options.clone(),
+        dimensions: None,
+    }
 }
 
-struct CacheStore {
-    map: HashMap<u64, CacheEntry>,
-    order: VecDeque<u64>,
+// 生成文件级分析结果（直方图、熵分布）的缓存键
+// 键中带有计算时的文件长度，follow 的文件追加数据后按新的长度重新计算，不会取到追加前的结果
+pub fn make_analysis_key(file_id: &str, analysis: &str, param: usize, file_len: usize) -> CacheKey {
+    CacheKey {
+        file_id: file_id.to_string(),
+        method: analysis.to_string(),
+        sample_size: param,
+        range: Some((0, file_len)),
+        options: SampleOptions::default(),
+        dimensions: None,
+    }
+}
+
+// 生成渲染图像的缓存键，render 为包含颜色映射和图像格式的结果类型
+pub fn make_render_key(
+    file_id: &str,
+    render: &str,
+    width: usize,
+    height: usize,
+    file_len: usize,
+) -> CacheKey {
+    CacheKey {
+        dimensions: Some((width, height)),
+        ..make_analysis_key(file_id, render, 0, file_len)
+    }
+}
+
+// 淘汰策略
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EvictionPolicy {
+    // 淘汰最久未访问的条目
+    #[default]
+    Lru,
+    // GDSF：优先级 = 膨胀值 + 重新计算耗时 / 大小，淘汰优先级最低的条目，
+    // 膨胀值取最近一次被淘汰条目的优先级，使长期未访问的高代价条目最终也会被淘汰
+    Cost,
+}
+
+impl std::str::FromStr for EvictionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "lru" => Ok(Self::Lru),
+            "cost" => Ok(Self::Cost),
+            other => Err(format!("Unknown cache policy: {}", other)),
+        }
+    }
+}
+
+pub struct Cache<V: CacheSized = Vec<u8>> {
+    // 可在运行时调整，见 set_capacity；只在持有 store 锁时修改
+    capacity: AtomicUsize,
+    store: Mutex<CacheStore<V>>,
+    disk: Option<DiskCache>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+// 条目保存在 slots 中，按访问顺序串成双向链表（head 最久未访问，tail 最近访问），
+// 命中时移动到链表尾部是 O(1)，不需要扫描访问顺序
+struct CacheStore<V> {
+    index: HashMap<CacheKey, usize>,
+    slots: Vec<Option<Node<V>>>,
+    // 已释放、可复用的 slot
+    free: Vec<usize>,
+    head: Option<usize>,
+    tail: Option<usize>,
     total_size: usize,
+    // GDSF 的膨胀值
+    inflation: f64,
+    // 仅 Cost 策略：按 (优先级, 访问序号, slot) 排序的全部条目，最小的即下一个被淘汰的，
+    // 插入、命中和淘汰都是 O(log n)，不必每次淘汰都扫描所有条目
+    ranked: Option<BTreeSet<(u64, u64, usize)>>,
+    // 每次插入或命中时递增，优先级相同时先淘汰序号小（最久未访问）的条目
+    clock: u64,
+}
+
+struct Node<V> {
+    key: CacheKey,
+    entry: CacheEntry<V>,
+    prev: Option<usize>,
+    next: Option<usize>,
 }
 
-struct CacheEntry {
-    data: Vec<u8>,
+struct CacheEntry<V> {
+    data: V,
     size: usize,
+    // 插入时由 CacheSized::source_len 得到
+    source_len: Option<usize>,
+    // 每字节的重新计算耗时（秒）
+    cost_per_byte: f64,
+    // GDSF 优先级，只在 Cost 策略下使用
+    priority: f64,
+    // 最近一次插入或命中时的访问序号
+    accessed: u64,
+}
+
+impl<V> CacheEntry<V> {
+    // 在 ranked 中的排序键；优先级不为负，非负浮点数的位模式与数值同序
+    fn rank(&self, slot: usize) -> (u64, u64, usize) {
+        (self.priority.to_bits(), self.accessed, slot)
+    }
+}
+
+impl<V> CacheStore<V> {
+    fn new(policy: EvictionPolicy) -> Self {
+        Self {
+            index: HashMap::new(),
+            slots: Vec::new(),
+            free: Vec::new(),
+            head: None,
+            tail: None,
+            total_size: 0,
+            inflation: 0.0,
+            ranked: (policy == EvictionPolicy::Cost).then(BTreeSet::new),
+            clock: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    fn node(&self, slot: usize) -> &Node<V> {
+        self.slots[slot].as_ref().expect("linked slot is occupied")
+    }
+
+    fn node_mut(&mut self, slot: usize) -> &mut Node<V> {
+        self.slots[slot].as_mut().expect("linked slot is occupied")
+    }
+
+    // 从链表中摘下 slot，不释放
+    fn unlink(&mut self, slot: usize) {
+        let (prev, next) = {
+            let node = self.node(slot);
+            (node.prev, node.next)
+        };
+        match prev {
+            Some(prev) => self.node_mut(prev).next = next,
+            None => self.head = next,
+        }
+        match next {
+            Some(next) => self.node_mut(next).prev = prev,
+            None => self.tail = prev,
+        }
+    }
+
+    // 把 slot 接到链表尾部（最近访问）
+    fn push_back(&mut self, slot: usize) {
+        let tail = self.tail;
+        {
+            let node = self.node_mut(slot);
+            node.prev = tail;
+            node.next = None;
+        }
+        match tail {
+            Some(tail) => self.node_mut(tail).next = Some(slot),
+            None => self.head = Some(slot),
+        }
+        self.tail = Some(slot);
+    }
+
+    // 命中的条目移动到链表尾部
+    fn touch(&mut self, key: &CacheKey) -> Option<&mut CacheEntry<V>> {
+        let slot = *self.index.get(key)?;
+        if self.tail != Some(slot) {
+            self.unlink(slot);
+            self.push_back(slot);
+        }
+        Some(&mut self.node_mut(slot).entry)
+    }
+
+    // 命中后更新条目的优先级和访问序号
+    fn reprioritize(&mut self, key: &CacheKey, priority: f64) {
+        let Some(&slot) = self.index.get(key) else {
+            return;
+        };
+        self.clock += 1;
+        let accessed = self.clock;
+        let entry = &mut self.node_mut(slot).entry;
+        let old_rank = entry.rank(slot);
+        entry.priority = priority;
+        entry.accessed = accessed;
+        let new_rank = entry.rank(slot);
+        if let Some(ranked) = &mut self.ranked {
+            ranked.remove(&old_rank);
+            ranked.insert(new_rank);
+        }
+    }
+
+    fn insert(&mut self, key: CacheKey, mut entry: CacheEntry<V>) {
+        self.total_size += entry.size;
+        self.clock += 1;
+        entry.accessed = self.clock;
+        let node = Node {
+            key: key.clone(),
+            entry,
+            prev: None,
+            next: None,
+        };
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.slots[slot] = Some(node);
+                slot
+            }
+            None => {
+                self.slots.push(Some(node));
+                self.slots.len() - 1
+            }
+        };
+        self.index.insert(key, slot);
+        self.push_back(slot);
+        let rank = self.node(slot).entry.rank(slot);
+        if let Some(ranked) = &mut self.ranked {
+            ranked.insert(rank);
+        }
+    }
+
+    fn remove(&mut self, key: &CacheKey) -> Option<CacheEntry<V>> {
+        let slot = self.index.remove(key)?;
+        self.unlink(slot);
+        let node = self.slots[slot].take().expect("indexed slot is occupied");
+        self.free.push(slot);
+        if let Some(ranked) = &mut self.ranked {
+            ranked.remove(&node.entry.rank(slot));
+        }
+        self.total_size -= node.entry.size;
+        Some(node.entry)
+    }
+
+    fn clear(&mut self) {
+        let policy = match self.ranked {
+            Some(_) => EvictionPolicy::Cost,
+            None => EvictionPolicy::Lru,
+        };
+        *self = Self::new(policy);
+    }
+
+    // 选出下一个被淘汰的条目：LRU 取链表头，Cost 取优先级最低的，优先级相同时淘汰最久未访问的
+    fn eviction_candidate(&self) -> Option<CacheKey> {
+        let slot = match &self.ranked {
+            None => self.head?,
+            Some(ranked) => ranked.first()?.2,
+        };
+        Some(self.node(slot).key.clone())
+    }
 }
 
-impl Cache {
+impl<V: CacheSized> Cache<V> {
     pub fn new(capacity: usize) -> Self {
         Self {
-            capacity,
-            store: Mutex::new(CacheStore {
-                map: HashMap::new(),
-                order: VecDeque::new(),
-                total_size: 0,
-            }),
+            capacity: AtomicUsize::new(capacity),
+            store: Mutex::new(CacheStore::new(EvictionPolicy::default())),
+            disk: None,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
         }
     }
 
-    // 生成缓存键
-    pub fn make_key(file_id: &str, sample_size: usize) -> u64 {
-        let mut hasher = DefaultHasher::new();
-        file_id.hash(&mut hasher);
-        sample_size.hash(&mut hasher);
-        hasher.finish()
+    pub fn with_policy(mut self, policy: EvictionPolicy) -> Self {
+        self.store = Mutex::new(CacheStore::new(policy));
+        self
+    }
+
+    // 启用磁盘二级缓存
+    pub fn with_disk(mut self, disk: DiskCache) -> Self {
+        self.disk = Some(disk);
+        self
     }
 
     // 获取缓存
-    pub fn get(&self, key: u64) -> Option<Vec<u8>> {
-        let mut store = self.store.lock().unwrap();
+    pub fn get(&self, key: &CacheKey) -> Option<V> {
+        self.lookup(key, None)
+    }
 
-        if let Some(entry) = store.map.get(&key) {
-            let data = entry.data.clone();
+    // 获取缓存，记录的源文件长度与 current_len 不同的条目已经过期：删除后按未命中处理，由调用方重新计算
+    pub fn get_current(&self, key: &CacheKey, current_len: usize) -> Option<V> {
+        self.lookup(key, Some(current_len))
+    }
 
-            // 更新访问顺序
-            store.order.retain(|&k| k != key);
-            store.order.push_back(key);
+    fn lookup(&self, key: &CacheKey, current_len: Option<usize>) -> Option<V> {
+        let is_stale = |source_len: Option<usize>| match source_len.zip(current_len) {
+            Some((recorded, current)) if recorded != current => {
+                tracing::info!(
+                    "Cache entry for {} is stale: source length changed from {} to {}",
+                    key.file_id,
+                    recorded,
+                    current
+                );
+                true
+            }
+            _ => false,
+        };
 
-            Some(data)
-        } else {
-            None
+        {
+            let mut store = self.lock_store();
+
+            let inflation = store.inflation;
+            if let Some(entry) = store.touch(key) {
+                if is_stale(entry.source_len) {
+                    store.remove(key);
+                    self.misses.fetch_add(1, Ordering::Relaxed);
+                    return None;
+                }
+
+                let data = entry.data.clone();
+                let priority = inflation + entry.cost_per_byte;
+                store.reprioritize(key, priority);
+
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Some(data);
+            }
+        }
+
+        // 内存未命中，回退到磁盘缓存并提升回内存
+        let from_disk = self
+            .disk
+            .as_ref()
+            .and_then(|disk| disk.get(key))
+            .and_then(|bytes| V::from_disk_bytes(&bytes));
+        if from_disk.as_ref().is_some_and(|data| is_stale(data.source_len())) {
+            if let Some(disk) = &self.disk {
+                disk.remove(key);
+            }
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        if let Some(data) = from_disk {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            if let Some(disk) = &self.disk {
+                disk.remove(key);
+            }
+            // 从磁盘读回的代价很低，不沿用原来的计算耗时
+            self.put(key.clone(), data.clone(), Duration::ZERO);
+            return Some(data);
         }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        None
     }
 
-    // 插入缓存
-    pub fn put(&self, key: u64, data: Vec<u8>) {
-        let mut store = self.store.lock().unwrap();
-        let size = data.len();
+    // 插入缓存，cost_hint 为得到该结果的计算耗时，Cost 策略据此决定淘汰顺序
+    pub fn put(&self, key: CacheKey, data: V, cost_hint: Duration) {
+        let evicted = self.put_in_memory(key, data, cost_hint);
+        self.spill_to_disk(evicted);
+    }
 
-        // 如果已存在，先删除旧的
-        if let Some(old_entry) = store.map.remove(&key) {
-            store.total_size -= old_entry.size;
-            store.order.retain(|&k| k != key);
+    // 在锁外把淘汰的条目写入磁盘
+    fn spill_to_disk(&self, evicted: Vec<(CacheKey, V)>) {
+        if let Some(disk) = &self.disk {
+            for (evict_key, evict_data) in evicted {
+                if let Some(bytes) = evict_data.to_disk_bytes() {
+                    disk.put(&evict_key, &bytes);
+                }
+            }
         }
+    }
+
+    fn put_in_memory(&self, key: CacheKey, data: V, cost_hint: Duration) -> Vec<(CacheKey, V)> {
+        let mut store = self.lock_store();
+        let size = data.byte_size();
+        let source_len = data.source_len();
+        let cost_per_byte = cost_hint.as_secs_f64() / size.max(1) as f64;
+        let capacity = self.capacity.load(Ordering::Relaxed);
+
+        // 如果已存在，先删除旧的
+        store.remove(&key);
 
         // 清理空间直到能容纳新数据
-        while store.total_size + size > self.capacity && !store.order.is_empty() {
-            if let Some(evict_key) = store.order.pop_front() {
-                if let Some(entry) = store.map.remove(&evict_key) {
-                    store.total_size -= entry.size;
-                }
+        let mut evicted = self.evict_until(&mut store, capacity.saturating_sub(size));
+
+        // 插入新数据（内存放不下时直接落盘）
+        if store.total_size + size <= capacity {
+            let priority = store.inflation + cost_per_byte;
+            store.insert(
+                key,
+                CacheEntry {
+                    data,
+                    size,
+                    source_len,
+                    cost_per_byte,
+                    priority,
+                    accessed: 0,
+                },
+            );
+        } else {
+            evicted.push((key, data));
+        }
+
+        evicted
+    }
+
+    // 按淘汰策略淘汰条目，直到占用不超过 limit，返回被淘汰的条目
+    fn evict_until(&self, store: &mut CacheStore<V>, limit: usize) -> Vec<(CacheKey, V)> {
+        let mut evicted = Vec::new();
+        while store.total_size > limit {
+            let Some(evict_key) = store.eviction_candidate() else {
+                break;
+            };
+            if let Some(entry) = store.remove(&evict_key) {
+                store.inflation = store.inflation.max(entry.priority);
+                evicted.push((evict_key, entry.data));
             }
         }
+        evicted
+    }
+
+    // 运行时调整容量；缩小时立即按淘汰策略（默认最久未访问的在先）淘汰到新容量以内，
+    // 被淘汰的条目与插入时一样写入磁盘缓存。返回淘汰的条目数
+    pub fn set_capacity(&self, new_capacity: usize) -> usize {
+        let evicted = {
+            let mut store = self.lock_store();
+            self.capacity.store(new_capacity, Ordering::Relaxed);
+            self.evict_until(&mut store, new_capacity)
+        };
+
+        let count = evicted.len();
+        self.spill_to_disk(evicted);
+        count
+    }
 
-        // 插入新数据
-        if store.total_size + size <= self.capacity {
-            store.map.insert(key, CacheEntry { data, size });
-            store.order.push_back(key);
-            store.total_size += size;
+    // 使某个文件的所有缓存条目失效（内存和磁盘）
+    pub fn invalidate_file(&self, file_id: &str) {
+        {
+            let mut store = self.lock_store();
+            let keys: Vec<CacheKey> = store
+                .index
+                .keys()
+                .filter(|key| key.file_id == file_id)
+                .cloned()
+                .collect();
+            for key in keys {
+                store.remove(&key);
+            }
+        }
+
+        if let Some(disk) = &self.disk {
+            disk.remove_file_entries(file_id);
         }
     }
 
     // 清空缓存
-    #[allow(dead_code)]
     pub fn clear(&self) {
-        let mut store = self.store.lock().unwrap();
-        store.map.clear();
-        store.order.clear();
-        store.total_size = 0;
+        self.lock_store().clear();
+
+        if let Some(disk) = &self.disk {
+            disk.clear();
+        }
     }
 
     // 获取缓存统计
     pub fn stats(&self) -> CacheStats {
-        let store = self.store.lock().unwrap();
+        let store = self.lock_store();
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let lookups = hits + misses;
+
         CacheStats {
-            entries: store.map.len(),
+            entries: store.len(),
             total_size: store.total_size,
-            capacity: self.capacity,
+            capacity: self.capacity.load(Ordering::Relaxed),
+            hits,
+            misses,
+            hit_rate: if lookups == 0 {
+                0.0
+            } else {
+                hits as f64 / lookups as f64
+            },
         }
     }
+
+    // 持锁时发生 panic 会毒化锁，直接 unwrap 会让之后每个请求都跟着 panic
+    // panic 可能发生在链表更新到一半时，恢复后清空内存中的条目（缓存可以重新计算），并清除毒化标记
+    fn lock_store(&self) -> MutexGuard<'_, CacheStore<V>> {
+        self.store.lock().unwrap_or_else(|poisoned| {
+            tracing::warn!("Cache lock was poisoned by a panic, discarding in-memory entries");
+            let mut store = poisoned.into_inner();
+            store.clear();
+            self.store.clear_poison();
+            store
+        })
+    }
 }
 
 #[derive(Debug, Clone, serde::Serialize)]
@@ -109,4 +644,102 @@ pub struct CacheStats {
     pub entries: usize,
     pub total_size: usize,
     pub capacity: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub hit_rate: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(i: usize) -> CacheKey {
+        make_analysis_key("file", "test", i, 1024)
+    }
+
+    const EXPENSIVE: Duration = Duration::from_secs(2);
+    const CHEAP: Duration = Duration::from_millis(5);
+
+    // 先放入一个昂贵的条目，再放入一串廉价条目，容量只够三个条目
+    fn fill_under_pressure(policy: EvictionPolicy, cheap_entries: usize) -> Cache {
+        let cache = Cache::new(300).with_policy(policy);
+        cache.put(key(0), vec![0; 100], EXPENSIVE);
+        for i in 1..=cheap_entries {
+            cache.put(key(i), vec![0; 100], CHEAP);
+        }
+        cache
+    }
+
+    #[test]
+    fn cost_policy_keeps_expensive_entry_under_pressure() {
+        let lru = fill_under_pressure(EvictionPolicy::Lru, 10);
+        assert!(lru.get(&key(0)).is_none());
+
+        let cost = fill_under_pressure(EvictionPolicy::Cost, 10);
+        assert!(cost.get(&key(0)).is_some());
+        // 廉价条目之间仍按访问先后淘汰
+        assert!(cost.get(&key(8)).is_none());
+        assert!(cost.get(&key(9)).is_some());
+        assert!(cost.get(&key(10)).is_some());
+        assert_eq!(cost.stats().entries, 3);
+    }
+
+    #[test]
+    fn cost_policy_eventually_evicts_unused_expensive_entry() {
+        // 每淘汰一个廉价条目膨胀值增加一点，长期未访问的昂贵条目最终会被淘汰；
+        // 直接查看索引，get 会刷新它的优先级
+        let cache = Cache::new(300).with_policy(EvictionPolicy::Cost);
+        cache.put(key(0), vec![0; 100], EXPENSIVE);
+        let evicted_after = (1..100_000).find(|&i| {
+            cache.put(key(i), vec![0; 100], CHEAP);
+            !cache.lock_store().index.contains_key(&key(0))
+        });
+        assert!(evicted_after.is_some(), "expensive entry was never evicted");
+    }
+
+    #[test]
+    fn shrinking_capacity_evicts_least_recently_used_first() {
+        let cache: Cache = Cache::new(1000);
+        for i in 1..=4 {
+            cache.put(key(i), vec![0; 100], CHEAP);
+        }
+        // 访问后 key(1) 成为最近使用的条目
+        assert!(cache.get(&key(1)).is_some());
+
+        assert_eq!(cache.set_capacity(250), 2);
+        let stats = cache.stats();
+        assert_eq!(stats.capacity, 250);
+        assert_eq!(stats.entries, 2);
+        assert_eq!(stats.total_size, 200);
+        assert!(cache.get(&key(2)).is_none());
+        assert!(cache.get(&key(3)).is_none());
+        assert!(cache.get(&key(1)).is_some());
+        assert!(cache.get(&key(4)).is_some());
+
+        // 放大容量不淘汰任何条目
+        assert_eq!(cache.set_capacity(1000), 0);
+        assert_eq!(cache.stats().entries, 2);
+    }
+
+    #[test]
+    fn render_keys_keep_width_and_height_apart() {
+        let keys = [
+            make_render_key("file", "render-viridis-webp", 2, 1, 1024),
+            make_render_key("file", "render-viridis-webp", 1, 2, 1024),
+            make_render_key("file", "render-viridis-webp", 1, 4098, 1024),
+            make_analysis_key("file", "render-viridis-webp", 0, 1024),
+            // 文件追加数据之后
+            make_render_key("file", "render-viridis-webp", 2, 1, 2048),
+        ];
+        for (i, a) in keys.iter().enumerate() {
+            for b in &keys[i + 1..] {
+                assert_ne!(a, b);
+                assert_ne!(a.disk_name(), b.disk_name());
+            }
+        }
+        assert_eq!(
+            keys[0].disk_name(),
+            "render-viridis-webp-0-0_1024-00000000-d2x1"
+        );
+    }
 }
@@ -0,0 +1,427 @@
+// 上传压缩文件时透明解压，保存和计算指纹的都是解压后的字节
+// 不依赖压缩库：gzip（deflate）和 zstd 的解码器分别在 gzip、zstd 模块中实现，只支持解压
+use super::{gzip, zstd};
+use std::io::{self, Read};
+
+// 上传数据的压缩格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub const ALL: [Self; 2] = [Self::Gzip, Self::Zstd];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "gzip" => Some(Self::Gzip),
+            "zstd" => Some(Self::Zstd),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Zstd => "zstd",
+        }
+    }
+
+    // 解压整个输入，解压后的数据分段交给 sink，返回解压后的总字节数
+    // 数据损坏时返回 InvalidData；sink 返回错误时立即停止；
+    // 解压后超过 limit 字节时把已解压的数据（多于 limit 字节）交给 sink 后返回 FileTooLarge，
+    // 高压缩比的恶意数据不会无限解压下去
+    pub fn decompress(
+        self,
+        input: impl Read,
+        limit: u64,
+        sink: &mut dyn FnMut(&[u8]) -> io::Result<()>,
+    ) -> io::Result<u64> {
+        let mut input = Input::new(input);
+        match self {
+            Self::Gzip => gzip::decompress(&mut input, limit, sink),
+            Self::Zstd => zstd::decompress(&mut input, limit, sink),
+        }
+    }
+}
+
+pub(super) fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+// 带缓冲的按字节输入
+pub(super) struct Input<R> {
+    inner: R,
+    buf: Box<[u8]>,
+    pos: usize,
+    len: usize,
+}
+
+impl<R: Read> Input<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            buf: vec![0; INPUT_BUFFER_SIZE].into_boxed_slice(),
+            pos: 0,
+            len: 0,
+        }
+    }
+
+    // 缓冲区读完时从输入补充，输入结束时返回 false
+    fn fill(&mut self) -> io::Result<bool> {
+        if self.pos < self.len {
+            return Ok(true);
+        }
+        self.pos = 0;
+        self.len = loop {
+            match self.inner.read(&mut self.buf) {
+                Ok(n) => break n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        };
+        Ok(self.len > 0)
+    }
+
+    // 输入是否已经结束
+    pub fn at_end(&mut self) -> io::Result<bool> {
+        Ok(!self.fill()?)
+    }
+
+    // 输入结束时返回 None
+    pub fn try_byte(&mut self) -> io::Result<Option<u8>> {
+        if !self.fill()? {
+            return Ok(None);
+        }
+        let byte = self.buf[self.pos];
+        self.pos += 1;
+        Ok(Some(byte))
+    }
+
+    pub fn byte(&mut self) -> io::Result<u8> {
+        self.try_byte()?.ok_or_else(unexpected_end)
+    }
+
+    pub fn read_exact(&mut self, mut out: &mut [u8]) -> io::Result<()> {
+        while !out.is_empty() {
+            if !self.fill()? {
+                return Err(unexpected_end());
+            }
+            let n = out.len().min(self.len - self.pos);
+            out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+            self.pos += n;
+            out = &mut out[n..];
+        }
+        Ok(())
+    }
+
+    // 小端整数
+    pub fn le(&mut self, bytes: usize) -> io::Result<u64> {
+        let mut value = 0;
+        for i in 0..bytes {
+            value |= (self.byte()? as u64) << (8 * i);
+        }
+        Ok(value)
+    }
+
+    pub fn skip(&mut self, mut count: u64) -> io::Result<()> {
+        while count > 0 {
+            if !self.fill()? {
+                return Err(unexpected_end());
+            }
+            let n = count.min((self.len - self.pos) as u64) as usize;
+            self.pos += n;
+            count -= n as u64;
+        }
+        Ok(())
+    }
+}
+
+pub(super) fn unexpected_end() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "compressed data ends unexpectedly",
+    )
+}
+
+const INPUT_BUFFER_SIZE: usize = 64 * 1024;
+
+// 解压输出的滑动窗口：保留最近 window 字节供回溯复制，更早的数据分段交给 sink
+// 每个 gzip 成员或 zstd 帧使用一个新的窗口，不能引用之前成员的数据
+pub(super) struct OutputWindow<'a> {
+    buf: Vec<u8>,
+    window: usize,
+    // 缓冲达到该长度时交出 window 之前的数据，每次交出的字节数不少于保留的字节数，拷贝开销均摊为常数
+    flush_at: usize,
+    total: u64,
+    limit: u64,
+    sink: &'a mut dyn FnMut(&[u8]) -> io::Result<()>,
+}
+
+impl<'a> OutputWindow<'a> {
+    // limit 为这个窗口最多输出的字节数
+    pub fn new(
+        window: usize,
+        limit: u64,
+        sink: &'a mut dyn FnMut(&[u8]) -> io::Result<()>,
+    ) -> Self {
+        let flush_at = window + window.max(MIN_FLUSH_SIZE);
+        Self {
+            buf: Vec::with_capacity(flush_at.min(INITIAL_OUTPUT_CAPACITY)),
+            window,
+            flush_at,
+            total: 0,
+            limit,
+            sink,
+        }
+    }
+
+    pub fn push(&mut self, byte: u8) -> io::Result<()> {
+        self.buf.push(byte);
+        self.total += 1;
+        self.maybe_flush()
+    }
+
+    pub fn extend(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.buf.extend_from_slice(bytes);
+        self.total += bytes.len() as u64;
+        self.maybe_flush()
+    }
+
+    // 复制 distance 字节之前的 length 个字节，两者可以重叠（distance < length 时重复最近的数据）
+    pub fn copy(&mut self, distance: usize, length: usize) -> io::Result<()> {
+        if distance == 0 || distance > self.buf.len() {
+            return Err(invalid_data(format!(
+                "back-reference distance {} exceeds the {} bytes of history",
+                distance,
+                self.buf.len()
+            )));
+        }
+
+        // 输出以 distance 为周期重复，已复制的部分始终是 distance 的整数倍，
+        // 每轮可以从 start 开始复制全部已有字节，重叠的长复制只需对数轮
+        let start = self.buf.len() - distance;
+        let mut remaining = length;
+        while remaining > 0 {
+            let n = remaining.min(self.buf.len() - start);
+            self.buf.extend_from_within(start..start + n);
+            remaining -= n;
+        }
+        self.total += length as u64;
+        self.maybe_flush()
+    }
+
+    fn maybe_flush(&mut self) -> io::Result<()> {
+        // 每次追加的数据不超过一个块，超过上限时缓冲的数据有界
+        if self.total > self.limit {
+            (self.sink)(&self.buf)?;
+            self.buf.clear();
+            return Err(io::Error::new(
+                io::ErrorKind::FileTooLarge,
+                format!("decompressed data exceeds the {} byte limit", self.limit),
+            ));
+        }
+        if self.buf.len() < self.flush_at {
+            return Ok(());
+        }
+        let keep_from = self.buf.len() - self.window;
+        (self.sink)(&self.buf[..keep_from])?;
+        self.buf.drain(..keep_from);
+        Ok(())
+    }
+
+    // 交出剩余的全部数据，返回输出的总字节数
+    pub fn finish(self) -> io::Result<u64> {
+        if !self.buf.is_empty() {
+            (self.sink)(&self.buf)?;
+        }
+        Ok(self.total)
+    }
+}
+
+const MIN_FLUSH_SIZE: usize = 1024 * 1024;
+const INITIAL_OUTPUT_CAPACITY: usize = 4 * 1024 * 1024;
+
+// 测试数据由 gzip 1.12 和 zstd 1.5.7 命令行生成，原始内容见下面的生成函数：
+//   gzip -9 -n -c <name> > testdata/<name>.gz
+//   zstd -19 --check -c text|zeros，zstd -3 --check -c random，zstd -1 --check -c short
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    pub const TEXT_GZ: &[u8] = include_bytes!("../../testdata/text.gz");
+    pub const SHORT_GZ: &[u8] = include_bytes!("../../testdata/short.gz");
+    pub const RANDOM_GZ: &[u8] = include_bytes!("../../testdata/random.gz");
+    pub const ZEROS_GZ: &[u8] = include_bytes!("../../testdata/zeros.gz");
+    pub const TEXT_ZST: &[u8] = include_bytes!("../../testdata/text.zst");
+    pub const SHORT_ZST: &[u8] = include_bytes!("../../testdata/short.zst");
+    pub const RANDOM_ZST: &[u8] = include_bytes!("../../testdata/random.zst");
+    pub const ZEROS_ZST: &[u8] = include_bytes!("../../testdata/zeros.zst");
+
+    // 每行为 "<i> <i*i % 997>"
+    pub fn text() -> Vec<u8> {
+        (0..3000)
+            .flat_map(|i| format!("{} {}\n", i, i * i % 997).into_bytes())
+            .collect()
+    }
+
+    pub fn short() -> Vec<u8> {
+        b"hello, hello, hello\n".to_vec()
+    }
+
+    // 线性同余生成的不可压缩数据，压缩后为原始块
+    pub fn random() -> Vec<u8> {
+        let mut x: u32 = 1;
+        (0..8192)
+            .map(|_| {
+                x = x.wrapping_mul(1103515245).wrapping_add(12345) & 0x7fff_ffff;
+                (x >> 16) as u8
+            })
+            .collect()
+    }
+
+    pub fn zeros() -> Vec<u8> {
+        vec![0; 8 * 1024 * 1024]
+    }
+
+    pub fn decode(compression: Compression, data: &[u8], limit: u64) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        let size = compression.decompress(data, limit, &mut |chunk| {
+            out.extend_from_slice(chunk);
+            Ok(())
+        })?;
+        assert_eq!(size, out.len() as u64);
+        Ok(out)
+    }
+
+    // 全 0 的数据解压较慢，只在需要大量输出的测试中使用
+    fn fixtures() -> Vec<(Compression, &'static [u8], Vec<u8>)> {
+        vec![
+            (Compression::Gzip, TEXT_GZ, text()),
+            (Compression::Gzip, SHORT_GZ, short()),
+            (Compression::Gzip, RANDOM_GZ, random()),
+            (Compression::Zstd, TEXT_ZST, text()),
+            (Compression::Zstd, SHORT_ZST, short()),
+            (Compression::Zstd, RANDOM_ZST, random()),
+        ]
+    }
+
+    #[test]
+    fn fixtures_decode_to_the_original_data() {
+        let large = [
+            (Compression::Gzip, ZEROS_GZ, zeros()),
+            (Compression::Zstd, ZEROS_ZST, zeros()),
+        ];
+        for (compression, data, expected) in fixtures().into_iter().chain(large) {
+            let out = decode(compression, data, u64::MAX).unwrap();
+            assert!(
+                out == expected,
+                "{} fixture of {} bytes",
+                compression.name(),
+                data.len()
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_input_is_rejected() {
+        for (compression, data, _) in fixtures() {
+            // 约 200 个截断位置，包括头部内的每一个字节
+            let step = (data.len() / 200).max(1);
+            let cuts = (0..data.len().min(32)).chain((32..data.len()).step_by(step));
+            for cut in cuts {
+                let result = decode(compression, &data[..cut], u64::MAX);
+                assert!(result.is_err(), "{} cut at {}", compression.name(), cut);
+            }
+        }
+    }
+
+    #[test]
+    fn corrupted_input_fails_or_decodes_unchanged() {
+        // 测试数据都带校验和，压缩数据中任意位置的损坏都不能导致 panic 或错误的输出；
+        // 头部中不影响内容的字段（如 gzip 的修改时间）被改动时仍然解压出原始数据
+        for (compression, data, expected) in fixtures() {
+            let step = (data.len() / 200).max(1);
+            for pos in (0..data.len()).step_by(step) {
+                for mask in [0x01, 0x80, 0xff] {
+                    let mut corrupted = data.to_vec();
+                    corrupted[pos] ^= mask;
+                    if let Ok(out) = decode(compression, &corrupted, 64 * 1024 * 1024) {
+                        assert!(
+                            out == expected,
+                            "{} byte {} ^ {:#x}",
+                            compression.name(),
+                            pos,
+                            mask
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn garbage_input_is_rejected() {
+        let mut x: u64 = 7;
+        for len in [0, 1, 3, 4, 17, 1000] {
+            let garbage: Vec<u8> = (0..len)
+                .map(|_| {
+                    x = x
+                        .wrapping_mul(6364136223846793005)
+                        .wrapping_add(1442695040888963407);
+                    (x >> 56) as u8
+                })
+                .collect();
+            for compression in Compression::ALL {
+                assert!(decode(compression, &garbage, u64::MAX).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn output_beyond_the_limit_stops_decoding() {
+        // 8MB 的 0 压缩后只有几 KB，解压在超过上限后停止，交出的数据不会远超上限
+        for (compression, data) in [
+            (Compression::Gzip, ZEROS_GZ),
+            (Compression::Zstd, ZEROS_ZST),
+        ] {
+            let mut received = 0;
+            let result = compression.decompress(data, 1024 * 1024, &mut |chunk| {
+                received += chunk.len();
+                Ok(())
+            });
+            let error = result.unwrap_err();
+            assert_eq!(
+                error.kind(),
+                io::ErrorKind::FileTooLarge,
+                "{}",
+                compression.name()
+            );
+            assert!(received > 1024 * 1024 && received < 2 * 1024 * 1024);
+
+            // 恰好等于上限时正常解压
+            assert_eq!(
+                decode(compression, data, 8 * 1024 * 1024).unwrap().len(),
+                8 * 1024 * 1024
+            );
+        }
+    }
+
+    #[test]
+    fn limit_applies_across_concatenated_members() {
+        for (compression, data) in [
+            (Compression::Gzip, SHORT_GZ),
+            (Compression::Zstd, SHORT_ZST),
+        ] {
+            let twice = [data, data].concat();
+            let len = short().len() as u64;
+            assert_eq!(
+                decode(compression, &twice, 2 * len).unwrap(),
+                short().repeat(2)
+            );
+            let error = decode(compression, &twice, 2 * len - 1).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::FileTooLarge);
+        }
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::SystemTime;
+
+use super::cache::CacheKey;
+
+// 磁盘二级缓存：内存 LRU 淘汰的条目落盘，按最近访问顺序做 LRU
+// 布局为 <cache_dir>/<file_id>/<key>.bin（见 CacheKey::disk_name），便于按文件整体失效
+pub struct DiskCache {
+    dir: PathBuf,
+    capacity: usize,
+    // 条目大小和访问顺序的内存索引，启动时扫描一次目录建立，之后随读写更新；
+    // 同时串行化淘汰，避免并发删除同一批文件；被毒化时索引仍然可用
+    index: Mutex<DiskIndex>,
+}
+
+// 每个条目记录访问序号和大小，order 按序号排列，最旧的条目最先淘汰
+#[derive(Default)]
+struct DiskIndex {
+    entries: HashMap<PathBuf, (u64, usize)>,
+    order: BTreeMap<u64, PathBuf>,
+    total: usize,
+    clock: u64,
+}
+
+impl DiskIndex {
+    // 记录一次写入或访问，size 为 None 时沿用已记录的大小
+    fn touch(&mut self, path: &Path, size: Option<usize>) {
+        let old = self.remove(path);
+        let Some(size) = size.or(old) else {
+            return;
+        };
+
+        self.clock += 1;
+        self.entries.insert(path.to_path_buf(), (self.clock, size));
+        self.order.insert(self.clock, path.to_path_buf());
+        self.total += size;
+    }
+
+    fn remove(&mut self, path: &Path) -> Option<usize> {
+        let (seq, size) = self.entries.remove(path)?;
+        self.order.remove(&seq);
+        self.total -= size;
+        Some(size)
+    }
+
+    fn pop_oldest(&mut self) -> Option<PathBuf> {
+        let (_, path) = self.order.pop_first()?;
+        if let Some((_, size)) = self.entries.remove(&path) {
+            self.total -= size;
+        }
+        Some(path)
+    }
+}
+
+impl DiskCache {
+    pub fn new(dir: PathBuf, capacity: usize) -> std::io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+
+        let cache = Self {
+            dir,
+            capacity,
+            index: Mutex::new(DiskIndex::default()),
+        };
+
+        // 按 mtime 从旧到新建立索引，重启后沿用上次的访问顺序；容量调小时在这里淘汰多出的条目
+        let mut entries = cache.scan();
+        entries.sort_by_key(|(mtime, _, _)| *mtime);
+        let mut index = cache.lock_index();
+        for (_, size, path) in entries {
+            index.touch(&path, Some(size));
+        }
+        cache.evict_to_fit(&mut index);
+        drop(index);
+
+        Ok(cache)
+    }
+
+    fn lock_index(&self) -> MutexGuard<'_, DiskIndex> {
+        self.index.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn path_for(&self, key: &CacheKey) -> PathBuf {
+        self.dir
+            .join(&key.file_id)
+            .join(format!("{}.bin", key.disk_name()))
+    }
+
+    // 读取磁盘缓存，命中时刷新访问顺序和 mtime（mtime 用于重启后重建顺序）
+    pub fn get(&self, key: &CacheKey) -> Option<Vec<u8>> {
+        let path = self.path_for(key);
+        let Ok(data) = fs::read(&path) else {
+            // 文件已被外部删除时同步索引
+            self.lock_index().remove(&path);
+            return None;
+        };
+
+        self.lock_index().touch(&path, None);
+        if let Ok(file) = fs::File::options().write(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+
+        Some(data)
+    }
+
+    // 写入磁盘缓存（先写临时文件再重命名，避免读到半个文件）
+    pub fn put(&self, key: &CacheKey, data: &[u8]) {
+        if data.len() > self.capacity {
+            return;
+        }
+
+        let path = self.path_for(key);
+        let tmp_path = path.with_extension("tmp");
+
+        let result = fs::create_dir_all(self.dir.join(&key.file_id))
+            .and_then(|_| fs::write(&tmp_path, data))
+            .and_then(|_| fs::rename(&tmp_path, &path));
+        if let Err(e) = result {
+            tracing::warn!("Failed to write disk cache entry {:?}: {}", key, e);
+            let _ = fs::remove_file(&tmp_path);
+            return;
+        }
+
+        let mut index = self.lock_index();
+        index.touch(&path, Some(data.len()));
+        self.evict_to_fit(&mut index);
+    }
+
+    // 删除单个条目
+    pub fn remove(&self, key: &CacheKey) {
+        let path = self.path_for(key);
+        let mut index = self.lock_index();
+        let _ = fs::remove_file(&path);
+        index.remove(&path);
+    }
+
+    // 删除某个文件的全部条目
+    pub fn remove_file_entries(&self, file_id: &str) {
+        let file_dir = self.dir.join(file_id);
+        let mut index = self.lock_index();
+        let _ = fs::remove_dir_all(&file_dir);
+
+        let paths: Vec<PathBuf> = index
+            .entries
+            .keys()
+            .filter(|path| path.starts_with(&file_dir))
+            .cloned()
+            .collect();
+        for path in paths {
+            index.remove(&path);
+        }
+    }
+
+    // 清空磁盘缓存
+    pub fn clear(&self) {
+        let mut index = self.lock_index();
+        *index = DiskIndex::default();
+
+        let Ok(dir) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        for entry in dir.filter_map(|entry| entry.ok()) {
+            let _ = fs::remove_dir_all(entry.path());
+        }
+    }
+
+    // 按访问顺序从旧到新删除，直到总大小不超过容量
+    // 删除失败（文件已不存在）时同样移出索引，避免反复重试
+    fn evict_to_fit(&self, index: &mut DiskIndex) {
+        while index.total > self.capacity {
+            let Some(path) = index.pop_oldest() else {
+                break;
+            };
+            let _ = fs::remove_file(&path);
+        }
+    }
+
+    // 扫描目录中的全部条目，只在启动时调用
+    fn scan(&self) -> Vec<(SystemTime, usize, PathBuf)> {
+        let Ok(dir) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+
+        dir.filter_map(|entry| entry.ok())
+            .filter_map(|entry| fs::read_dir(entry.path()).ok())
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "bin"))
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                let mtime = metadata.modified().ok()?;
+                Some((mtime, metadata.len() as usize, entry.path()))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::cache::make_analysis_key;
+
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new() -> Self {
+            Self(std::env::temp_dir().join(format!("binaryvis-disk-{}", uuid::Uuid::new_v4())))
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn key(i: usize) -> CacheKey {
+        make_analysis_key("file", "test", i, 1024)
+    }
+
+    #[test]
+    fn evicts_least_recently_used_entries() {
+        let dir = TestDir::new();
+        let cache = DiskCache::new(dir.0.clone(), 300).unwrap();
+
+        cache.put(&key(0), &[0; 100]);
+        cache.put(&key(1), &[1; 100]);
+        cache.put(&key(2), &[2; 100]);
+        assert!(cache.get(&key(0)).is_some());
+        cache.put(&key(3), &[3; 100]);
+
+        assert!(cache.get(&key(1)).is_none());
+        for i in [0, 2, 3] {
+            assert_eq!(cache.get(&key(i)), Some(vec![i as u8; 100]));
+        }
+        assert_eq!(cache.lock_index().total, 300);
+    }
+
+    #[test]
+    fn overwriting_an_entry_replaces_its_size() {
+        let dir = TestDir::new();
+        let cache = DiskCache::new(dir.0.clone(), 300).unwrap();
+
+        cache.put(&key(0), &[0; 100]);
+        cache.put(&key(0), &[0; 50]);
+        assert_eq!(cache.lock_index().total, 50);
+
+        cache.remove(&key(0));
+        assert_eq!(cache.lock_index().total, 0);
+    }
+
+    #[test]
+    fn index_is_rebuilt_from_existing_entries() {
+        let dir = TestDir::new();
+        let cache = DiskCache::new(dir.0.clone(), 300).unwrap();
+        cache.put(&key(0), &[0; 100]);
+        cache.put(&key(1), &[1; 100]);
+        drop(cache);
+
+        // 容量调小后重启，只保留能放下的条目
+        let cache = DiskCache::new(dir.0.clone(), 150).unwrap();
+        assert_eq!(cache.lock_index().total, 100);
+        assert_eq!(cache.lock_index().entries.len(), 1);
+
+        cache.remove_file_entries("file");
+        assert_eq!(cache.lock_index().total, 0);
+        assert!(cache.get(&key(0)).is_none() && cache.get(&key(1)).is_none());
+    }
+
+    #[test]
+    fn entries_deleted_outside_the_cache_leave_the_index() {
+        let dir = TestDir::new();
+        let cache = DiskCache::new(dir.0.clone(), 300).unwrap();
+        cache.put(&key(0), &[0; 100]);
+
+        std::fs::remove_dir_all(dir.0.join("file")).unwrap();
+        assert!(cache.get(&key(0)).is_none());
+        assert_eq!(cache.lock_index().total, 0);
+    }
+}
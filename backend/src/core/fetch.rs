@@ -0,0 +1,633 @@
+// 拉取远程文件，供 /api/upload/from-url 像直接上传一样保存
+// 不依赖 HTTP 客户端库：只实现 HTTP/1.1 的 GET，响应体支持 Content-Length、chunked 和读到连接关闭，暂不支持 https
+use crate::error::{AppError, Result};
+use axum::http::Uri;
+use bytes::Bytes;
+use futures::Stream;
+use std::io;
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+// 客户端实现了的协议
+pub const SUPPORTED_SCHEMES: [&str; 1] = ["http"];
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// 发出请求后等待响应头的时间
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
+// 接收响应体时两次收到数据之间的最长间隔
+const READ_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_REDIRECTS: usize = 5;
+const MAX_LINE_LEN: usize = 8 * 1024;
+const MAX_HEADERS: usize = 100;
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+// 允许拉取的 URL：协议和主机都必须在列表中，主机为空时不允许拉取任何 URL。
+// 主机按名称匹配（不区分大小写），`*.example.com` 匹配它的所有子域名；重定向的目标同样要满足限制
+pub struct FetchPolicy {
+    schemes: Vec<String>,
+    hosts: Vec<String>,
+}
+
+impl FetchPolicy {
+    pub fn new(schemes: Vec<String>, hosts: Vec<String>) -> Self {
+        Self {
+            schemes,
+            hosts: hosts
+                .into_iter()
+                .map(|host| host.to_ascii_lowercase())
+                .collect(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.hosts.is_empty()
+    }
+
+    fn allows_host(&self, host: &str) -> bool {
+        let host = host.to_ascii_lowercase();
+        self.hosts
+            .iter()
+            .any(|allowed| match allowed.strip_prefix("*.") {
+                Some(domain) => host
+                    .strip_suffix(domain)
+                    .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')),
+                None => *allowed == host,
+            })
+    }
+
+    // 解析并检查 URL
+    fn check(&self, url: &str) -> Result<Target> {
+        let uri: Uri = url
+            .parse()
+            .map_err(|_| AppError::BadRequest(format!("Invalid URL: {}", url)))?;
+        let scheme = uri
+            .scheme_str()
+            .ok_or_else(|| AppError::BadRequest(format!("URL has no scheme: {}", url)))?
+            .to_ascii_lowercase();
+        if !self.schemes.contains(&scheme) {
+            return Err(AppError::BadRequest(format!(
+                "URL scheme {} is not allowed",
+                scheme
+            )));
+        }
+        let authority = uri
+            .authority()
+            .ok_or_else(|| AppError::BadRequest(format!("URL has no host: {}", url)))?;
+        if authority.as_str().contains('@') {
+            return Err(AppError::BadRequest(
+                "URLs with credentials are not supported".to_string(),
+            ));
+        }
+        let host = authority.host();
+        if !self.allows_host(host) {
+            return Err(AppError::BadRequest(format!(
+                "Host {} is not allowed",
+                host
+            )));
+        }
+
+        Ok(Target {
+            url: uri.to_string(),
+            connect_host: host
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .to_string(),
+            port: authority.port_u16().unwrap_or(80),
+            host_header: authority.as_str().to_string(),
+            path: uri
+                .path_and_query()
+                .map_or("/", |path| path.as_str())
+                .to_string(),
+        })
+    }
+
+    // 发起 GET 请求并跟随重定向，返回 2xx 响应；响应体在 RemoteFile::into_stream 中按需读取
+    pub async fn open(&self, url: &str) -> Result<RemoteFile> {
+        let mut target = self.check(url)?;
+        for _ in 0..=MAX_REDIRECTS {
+            let (head, reader) = request(&target).await?;
+            match head.status {
+                200..=299 => {
+                    let body = if head.chunked {
+                        BodyKind::Chunked { remaining: 0 }
+                    } else if let Some(len) = head.content_length {
+                        BodyKind::Length(len)
+                    } else {
+                        BodyKind::UntilClose
+                    };
+                    return Ok(RemoteFile {
+                        url: target.url,
+                        content_length: head.content_length.filter(|_| !head.chunked),
+                        body: BodyReader {
+                            reader,
+                            kind: body,
+                            done: false,
+                        },
+                    });
+                }
+                301 | 302 | 303 | 307 | 308 => {
+                    let location = head.location.ok_or_else(|| {
+                        remote_error(format!(
+                            "redirect {} without a Location header",
+                            head.status
+                        ))
+                    })?;
+                    target = self.check(&resolve_location(&target, &location))?;
+                }
+                status => {
+                    return Err(remote_error(format!(
+                        "{} returned HTTP {}",
+                        target.url, status
+                    )))
+                }
+            }
+        }
+        Err(remote_error(format!(
+            "more than {} redirects",
+            MAX_REDIRECTS
+        )))
+    }
+}
+
+struct Target {
+    url: String,
+    connect_host: String,
+    port: u16,
+    host_header: String,
+    path: String,
+}
+
+// 按 RFC 3986 第 5 节把 Location 解析为绝对 URL：带协议的地址原样使用，
+// 其余（//host/…、/path、file.bin、../dir/、?query）基于当前 URL；片段不参与请求，直接去掉
+fn resolve_location(base: &Target, location: &str) -> String {
+    let location = location.split('#').next().unwrap_or_default();
+    let has_scheme = location.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+    });
+    if has_scheme {
+        return location.to_string();
+    }
+    if location.starts_with("//") {
+        return format!("http:{}", location);
+    }
+
+    let (base_path, base_query) = match base.path.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (base.path.as_str(), None),
+    };
+    let (path, query) = match location.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (location, None),
+    };
+    let (path, query) = if path.is_empty() {
+        (base_path.to_string(), query.or(base_query))
+    } else if path.starts_with('/') {
+        (remove_dot_segments(path), query)
+    } else {
+        // 当前路径（总以 / 开头）的最后一段换成相对路径
+        let dir = &base_path[..base_path.rfind('/').map_or(0, |i| i + 1)];
+        (remove_dot_segments(&format!("{}{}", dir, path)), query)
+    };
+
+    match query {
+        Some(query) => format!("http://{}{}?{}", base.host_header, path, query),
+        None => format!("http://{}{}", base.host_header, path),
+    }
+}
+
+// 处理以 / 开头的路径中的 . 和 ..，.. 不会越过根目录
+fn remove_dot_segments(path: &str) -> String {
+    let segments: Vec<&str> = path.split('/').skip(1).collect();
+    let mut output: Vec<&str> = Vec::new();
+    for (i, &segment) in segments.iter().enumerate() {
+        let last = i + 1 == segments.len();
+        match segment {
+            "." => {}
+            ".." => {
+                output.pop();
+            }
+            _ => output.push(segment),
+        }
+        // 以 . 或 .. 结尾的路径指向目录
+        if last && (segment == "." || segment == "..") {
+            output.push("");
+        }
+    }
+    format!("/{}", output.join("/"))
+}
+
+struct ResponseHead {
+    status: u16,
+    content_length: Option<u64>,
+    chunked: bool,
+    location: Option<String>,
+}
+
+fn remote_error(message: impl Into<String>) -> AppError {
+    AppError::RemoteFetch(message.into())
+}
+
+async fn request(target: &Target) -> Result<(ResponseHead, BufReader<TcpStream>)> {
+    let stream = tokio::time::timeout(
+        CONNECT_TIMEOUT,
+        TcpStream::connect((target.connect_host.as_str(), target.port)),
+    )
+    .await
+    .map_err(|_| remote_error(format!("connecting to {} timed out", target.host_header)))?
+    .map_err(|e| remote_error(format!("cannot connect to {}: {}", target.host_header, e)))?;
+
+    let mut reader = BufReader::new(stream);
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: binaryvis-backend/{}\r\nAccept: */*\r\nAccept-Encoding: identity\r\nConnection: close\r\n\r\n",
+        target.path,
+        target.host_header,
+        env!("CARGO_PKG_VERSION")
+    );
+    let head = tokio::time::timeout(RESPONSE_TIMEOUT, async {
+        reader.get_mut().write_all(request.as_bytes()).await?;
+        read_head(&mut reader).await
+    })
+    .await
+    .map_err(|_| remote_error(format!("{} did not respond in time", target.host_header)))?
+    .map_err(|e| {
+        remote_error(format!(
+            "invalid response from {}: {}",
+            target.host_header, e
+        ))
+    })?;
+
+    Ok((head, reader))
+}
+
+// 读到 \n 为止的一行，去掉行尾的 \r\n；超过 MAX_LINE_LEN 或连接提前关闭时返回错误
+async fn read_line<R: AsyncRead + Unpin>(reader: &mut BufReader<R>) -> io::Result<String> {
+    let mut line = Vec::new();
+    let n = reader
+        .take(MAX_LINE_LEN as u64)
+        .read_until(b'\n', &mut line)
+        .await?;
+    if n == 0 || line.last() != Some(&b'\n') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "line too long or connection closed",
+        ));
+    }
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "non-UTF-8 header"))
+}
+
+async fn read_head<R: AsyncRead + Unpin>(reader: &mut BufReader<R>) -> io::Result<ResponseHead> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+    let status_line = read_line(reader).await?;
+    let mut parts = status_line.splitn(3, ' ');
+    if !parts
+        .next()
+        .is_some_and(|version| version.starts_with("HTTP/1."))
+    {
+        return Err(invalid("not an HTTP/1.x response"));
+    }
+    let status = parts
+        .next()
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| invalid("invalid status code"))?;
+
+    let mut head = ResponseHead {
+        status,
+        content_length: None,
+        chunked: false,
+        location: None,
+    };
+    for _ in 0..=MAX_HEADERS {
+        let line = read_line(reader).await?;
+        if line.is_empty() {
+            return Ok(head);
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| invalid("invalid header"))?;
+        let value = value.trim();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-length" => {
+                head.content_length = Some(
+                    value
+                        .parse()
+                        .map_err(|_| invalid("invalid Content-Length"))?,
+                )
+            }
+            "transfer-encoding" => {
+                head.chunked = value
+                    .split(',')
+                    .any(|coding| coding.trim().eq_ignore_ascii_case("chunked"))
+            }
+            "location" => head.location = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    Err(invalid("too many headers"))
+}
+
+// 远程文件的响应体
+pub struct RemoteFile {
+    // 跟随重定向后的最终地址
+    pub url: String,
+    // 响应声明的长度，chunked 或读到连接关闭时未知
+    pub content_length: Option<u64>,
+    body: BodyReader,
+}
+
+impl RemoteFile {
+    // URL 路径的最后一段作为文件名
+    pub fn filename(&self) -> Option<String> {
+        let uri: Uri = self.url.parse().ok()?;
+        uri.path()
+            .rsplit('/')
+            .next()
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+    }
+
+    // 按块读取响应体的流，供 save_stream_hashed 边接收边保存
+    pub fn into_stream(self) -> Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send>> {
+        Box::pin(futures::stream::try_unfold(
+            self.body,
+            |mut body| async move {
+                let chunk = tokio::time::timeout(READ_IDLE_TIMEOUT, body.next_chunk())
+                    .await
+                    .map_err(|_| {
+                        io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            "remote server stopped sending data",
+                        )
+                    })??;
+                Ok(chunk.map(|chunk| (chunk, body)))
+            },
+        ))
+    }
+}
+
+enum BodyKind {
+    // 剩余的字节数
+    Length(u64),
+    // 当前分块剩余的字节数，为 0 时先读下一个分块的长度
+    Chunked { remaining: u64 },
+    UntilClose,
+}
+
+struct BodyReader {
+    reader: BufReader<TcpStream>,
+    kind: BodyKind,
+    done: bool,
+}
+
+impl BodyReader {
+    // 读取下一段数据，响应体结束时返回 None
+    async fn next_chunk(&mut self) -> io::Result<Option<Bytes>> {
+        if self.done {
+            return Ok(None);
+        }
+        let limit = match &mut self.kind {
+            BodyKind::Length(0) => {
+                self.done = true;
+                return Ok(None);
+            }
+            BodyKind::Length(remaining) => *remaining,
+            BodyKind::Chunked { remaining: 0 } => {
+                let line = read_line(&mut self.reader).await?;
+                let size = line.split(';').next().unwrap_or("").trim();
+                let size = u64::from_str_radix(size, 16).map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, "invalid chunk size")
+                })?;
+                if size == 0 {
+                    // 跳过 trailer，直到空行
+                    while !read_line(&mut self.reader).await?.is_empty() {}
+                    self.done = true;
+                    return Ok(None);
+                }
+                self.kind = BodyKind::Chunked { remaining: size };
+                size
+            }
+            BodyKind::Chunked { remaining } => *remaining,
+            BodyKind::UntilClose => u64::MAX,
+        };
+
+        let mut buf = vec![0; limit.min(READ_CHUNK_SIZE as u64) as usize];
+        let n = self.reader.read(&mut buf).await?;
+        if n == 0 {
+            if matches!(self.kind, BodyKind::UntilClose) {
+                self.done = true;
+                return Ok(None);
+            }
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "remote response ended early",
+            ));
+        }
+        buf.truncate(n);
+
+        match &mut self.kind {
+            BodyKind::Length(remaining) => *remaining -= n as u64,
+            BodyKind::Chunked { remaining } => {
+                *remaining -= n as u64;
+                // 分块数据之后是 \r\n
+                if *remaining == 0 && !read_line(&mut self.reader).await?.is_empty() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "missing chunk terminator",
+                    ));
+                }
+            }
+            BodyKind::UntilClose => {}
+        }
+        Ok(Some(Bytes::from(buf)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::testing::serve_http;
+    use futures::TryStreamExt;
+
+    fn policy() -> FetchPolicy {
+        FetchPolicy::new(vec!["http".to_string()], vec!["127.0.0.1".to_string()])
+    }
+
+    fn response(head: &str, body: &[u8]) -> Vec<u8> {
+        [format!("HTTP/1.1 {}\r\n\r\n", head).as_bytes(), body].concat()
+    }
+
+    fn redirect(location: &str) -> Vec<u8> {
+        response(&format!("302 Found\r\nLocation: {}", location), b"")
+    }
+
+    struct Fetched {
+        url: String,
+        content_length: Option<u64>,
+        body: io::Result<Vec<u8>>,
+    }
+
+    // 拉取 URL 并读完响应体
+    async fn fetch(url: &str) -> Result<Fetched> {
+        let remote = policy().open(url).await?;
+        let (url, content_length) = (remote.url.clone(), remote.content_length);
+        let body = remote
+            .into_stream()
+            .try_collect::<Vec<Bytes>>()
+            .await
+            .map(|chunks| chunks.concat());
+        Ok(Fetched {
+            url,
+            content_length,
+            body,
+        })
+    }
+
+    // 响应体按固定内容返回的服务器
+    async fn serve_body(head: &'static str, body: &'static [u8]) -> String {
+        let (addr, _) = serve_http(move |_| response(head, body)).await;
+        format!("http://{}/data.bin", addr)
+    }
+
+    #[tokio::test]
+    async fn content_length_body_is_read_exactly() {
+        let url = serve_body("200 OK\r\nContent-Length: 5", b"helloEXTRA").await;
+        let fetched = fetch(&url).await.unwrap();
+        assert_eq!(fetched.content_length, Some(5));
+        assert_eq!(fetched.body.unwrap(), b"hello");
+    }
+
+    #[tokio::test]
+    async fn chunked_body_is_decoded() {
+        // 分块长度带扩展参数，结尾有 trailer；同时出现的 Content-Length 被忽略
+        let url = serve_body(
+            "200 OK\r\nContent-Length: 3\r\nTransfer-Encoding: chunked",
+            b"5;name=value\r\nhello\r\nA\r\n, chunked!\r\n0\r\nChecksum: x\r\n\r\n",
+        )
+        .await;
+        let fetched = fetch(&url).await.unwrap();
+        assert_eq!(fetched.content_length, None);
+        assert_eq!(fetched.body.unwrap(), b"hello, chunked!");
+    }
+
+    #[tokio::test]
+    async fn body_without_length_is_read_until_close() {
+        let url = serve_body("200 OK", b"until the end").await;
+        let fetched = fetch(&url).await.unwrap();
+        assert_eq!(fetched.content_length, None);
+        assert_eq!(fetched.body.unwrap(), b"until the end");
+    }
+
+    #[tokio::test]
+    async fn malformed_bodies_are_rejected() {
+        let chunked = "200 OK\r\nTransfer-Encoding: chunked";
+        let cases: [(&'static str, &'static [u8]); 4] = [
+            (chunked, b"zz\r\nhello\r\n0\r\n\r\n"),
+            (chunked, b"5\r\nhelloXX\r\n0\r\n\r\n"),
+            (chunked, b"5\r\nhello\r\n"),
+            ("200 OK\r\nContent-Length: 10", b"short"),
+        ];
+        for (head, body) in cases {
+            let url = serve_body(head, body).await;
+            let fetched = fetch(&url).await.unwrap();
+            assert!(fetched.body.is_err(), "{:?}", String::from_utf8_lossy(body));
+        }
+    }
+
+    #[tokio::test]
+    async fn oversized_response_heads_are_rejected() {
+        let long_header = format!("200 OK\r\nX-Long: {}", "a".repeat(MAX_LINE_LEN));
+        let many_headers = format!("200 OK{}", "\r\nX-Header: 1".repeat(MAX_HEADERS + 1));
+        for head in [long_header, many_headers] {
+            let (addr, _) = serve_http(move |_| response(&head, b"")).await;
+            let result = fetch(&format!("http://{}/", addr)).await;
+            assert!(matches!(result, Err(AppError::RemoteFetch(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn relative_redirects_resolve_against_the_current_url() {
+        let (addr, paths) = serve_http(|path| match path {
+            "/dir/start?x=1" => redirect("file.bin"),
+            "/dir/file.bin" => redirect("../other/./next.bin?y=2#part"),
+            "/other/next.bin?y=2" => redirect("/abs/final.bin"),
+            "/abs/final.bin" => response("200 OK\r\nContent-Length: 2", b"ok"),
+            _ => response("404 Not Found\r\nContent-Length: 0", b""),
+        })
+        .await;
+
+        let fetched = fetch(&format!("http://{}/dir/start?x=1", addr))
+            .await
+            .unwrap();
+        assert_eq!(fetched.url, format!("http://{}/abs/final.bin", addr));
+        assert_eq!(fetched.body.unwrap(), b"ok");
+        assert_eq!(
+            *paths.lock().unwrap(),
+            [
+                "/dir/start?x=1",
+                "/dir/file.bin",
+                "/other/next.bin?y=2",
+                "/abs/final.bin"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn redirect_targets_are_checked_against_the_policy() {
+        for location in [
+            "http://example.com/file.bin",
+            "//example.com/file.bin",
+            "ftp://127.0.0.1/file.bin",
+        ] {
+            let (addr, paths) = serve_http(move |_| redirect(location)).await;
+            let result = fetch(&format!("http://{}/start", addr)).await;
+            assert!(
+                matches!(result, Err(AppError::BadRequest(_))),
+                "{}",
+                location
+            );
+            assert_eq!(paths.lock().unwrap().len(), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn redirect_loops_stop_after_the_limit() {
+        let (addr, paths) = serve_http(|_| redirect("again")).await;
+        let result = fetch(&format!("http://{}/again", addr)).await;
+        assert!(matches!(result, Err(AppError::RemoteFetch(_))));
+        assert_eq!(paths.lock().unwrap().len(), MAX_REDIRECTS + 1);
+    }
+
+    #[test]
+    fn locations_resolve_like_a_browser() {
+        let policy = FetchPolicy::new(vec!["http".to_string()], vec!["files.test".to_string()]);
+        let base = policy
+            .check("http://files.test:8080/a/b/c.bin?q=1")
+            .unwrap();
+        let cases = [
+            ("d.bin", "http://files.test:8080/a/b/d.bin"),
+            ("./d.bin", "http://files.test:8080/a/b/d.bin"),
+            ("../d.bin", "http://files.test:8080/a/d.bin"),
+            ("../../../../d.bin", "http://files.test:8080/d.bin"),
+            ("..", "http://files.test:8080/a/"),
+            ("sub/", "http://files.test:8080/a/b/sub/"),
+            ("/x/../y.bin", "http://files.test:8080/y.bin"),
+            ("?r=2", "http://files.test:8080/a/b/c.bin?r=2"),
+            ("#frag", "http://files.test:8080/a/b/c.bin?q=1"),
+            ("//other.test/e.bin", "http://other.test/e.bin"),
+            ("HTTP://other.test/e.bin", "HTTP://other.test/e.bin"),
+        ];
+        for (location, expected) in cases {
+            assert_eq!(resolve_location(&base, location), expected, "{}", location);
+        }
+    }
+}
@@ -1,14 +1,82 @@
+use super::decompress::Compression;
+use super::{analysis, magic};
 use crate::error::{AppError, Result};
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
 use memmap2::{Mmap, MmapOptions};
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
 use std::fs::File;
+use std::io::Read;
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, SystemTime};
 use tokio::fs;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 
 pub struct FileManager {
     upload_dir: PathBuf,
     max_file_size: usize,
+    // 每个指纹一把写锁，防止同一指纹的并发上传交错写入
+    upload_locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+    // 进行中的流式上传，记录每个指纹已写入磁盘的字节数
+    uploads: Arc<Mutex<HashMap<String, Arc<AtomicU64>>>>,
+    // 可续传上传，按上传 ID 索引
+    resumable: Mutex<HashMap<String, Arc<ResumableUpload>>>,
+    // 共享的内存映射，只保存弱引用，没有采样器使用时映射随之释放
+    mmaps: Mutex<HashMap<String, Weak<MappedFile>>>,
+}
+
+// 文件的内存映射，同时持有打开的文件以便检查文件是否在映射后被截断
+// 服务器自身从不截断已上传的文件（见 FileManager::mmap_file），但外部进程可能这样做：
+// 读取超出新长度的映射页会触发 SIGBUS 使整个进程退出，因此在读取映射之前调用 ensure_intact；
+// 检查与读取之间仍有很短的窗口，无法完全避免
+pub struct MappedFile {
+    mmap: Mmap,
+    file: File,
+}
+
+impl MappedFile {
+    // 文件当前长度小于映射长度时返回 FileAccess
+    pub fn ensure_intact(&self) -> Result<()> {
+        let len = self.file.metadata().map_err(AppError::FileAccess)?.len() as usize;
+        if len < self.mmap.len() {
+            return Err(AppError::FileAccess(std::io::Error::other(format!(
+                "file truncated from {} to {} bytes while mapped",
+                self.mmap.len(),
+                len
+            ))));
+        }
+        Ok(())
+    }
+
+    // 文件当前长度大于映射长度，即映射之后有追加的数据
+    fn is_stale(&self) -> bool {
+        self.file
+            .metadata()
+            .is_ok_and(|metadata| metadata.len() as usize > self.mmap.len())
+    }
+}
+
+impl std::ops::Deref for MappedFile {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.mmap
+    }
+}
+
+// 可续传上传的状态，数据追加写入 <upload_id>.tmp
+// 状态同时以 JSON 保存在 <upload_id>.tmp.meta 中，服务器重启后客户端仍可以继续上传
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ResumableUpload {
+    fingerprint: String,
+    filename: Option<String>,
+    size: usize,
+    // 串行化同一上传的分块写入
+    #[serde(skip)]
+    lock: tokio::sync::Mutex<()>,
 }
 
 impl FileManager {
@@ -16,53 +84,540 @@ impl FileManager {
         Self {
             upload_dir,
             max_file_size,
+            upload_locks: Mutex::new(HashMap::new()),
+            uploads: Arc::new(Mutex::new(HashMap::new())),
+            resumable: Mutex::new(HashMap::new()),
+            mmaps: Mutex::new(HashMap::new()),
         }
     }
 
-    // 检查指纹对应的文件是否存在
-    pub async fn check_fingerprint(&self, fingerprint: &str) -> bool {
-        let path = self.upload_dir.join(fingerprint);
-        path.exists()
+    // 文件 ID 和指纹直接用作上传目录中的文件名，只允许字母、数字、`_` 和 `-`，
+    // 排除 `.`、`/`、`\` 等能拼出上传目录之外路径的字符
+    pub fn validate_file_id(file_id: &str) -> Result<()> {
+        let valid = !file_id.is_empty()
+            && file_id.len() <= MAX_FILE_ID_LEN
+            && file_id
+                .bytes()
+                .all(|byte| byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'-');
+        if !valid {
+            return Err(AppError::BadRequest(format!("Invalid file ID: {:?}", file_id)));
+        }
+        Ok(())
     }
 
-    // 保存上传的文件（使用指纹作为文件名）
-    pub async fn save_file(&self, data: &[u8], fingerprint: &str) -> Result<String> {
-        // 检查大小限制
-        if data.len() > self.max_file_size {
-            return Err(AppError::FileTooLarge(data.len()));
+    // 校验文件 ID 后拼出它在上传目录中的路径，并确认路径没有离开上传目录
+    fn file_path(&self, file_id: &str) -> Result<PathBuf> {
+        Self::validate_file_id(file_id)?;
+        let path = self.upload_dir.join(file_id);
+        if path.parent() != Some(self.upload_dir.as_path()) {
+            return Err(AppError::BadRequest(format!("Invalid file ID: {:?}", file_id)));
         }
+        Ok(path)
+    }
 
-        // 使用指纹作为文件名（天然去重）
-        let file_id = fingerprint.to_string();
-        let path = self.upload_dir.join(&file_id);
+    // 获取指纹对应的写锁
+    fn upload_lock(&self, fingerprint: &str) -> Arc<tokio::sync::Mutex<()>> {
+        let mut locks = self.upload_locks.lock().unwrap();
+        locks
+            .entry(fingerprint.to_string())
+            .or_insert_with(|| Arc::new(tokio::sync::Mutex::new(())))
+            .clone()
+    }
+
+    // 没有其他写者持有时移除写锁，避免锁表无限增长
+    fn release_upload_lock(&self, fingerprint: &str) {
+        let mut locks = self.upload_locks.lock().unwrap();
+        if locks
+            .get(fingerprint)
+            .is_some_and(|lock| Arc::strong_count(lock) == 1)
+        {
+            locks.remove(fingerprint);
+        }
+    }
 
-        // 如果文件已存在，直接返回file_id（去重）
+    // 查找指纹对应的已上传文件，返回文件 ID：指纹本身是文件 ID（内容 SHA-1）时直接检查该文件，
+    // 否则查找上传时由 record_fingerprint 记录的映射；映射指向的文件已删除时顺便删除映射
+    pub async fn check_fingerprint(&self, fingerprint: &str) -> Option<String> {
+        let path = self.file_path(fingerprint).ok()?;
         if path.exists() {
-            tracing::info!("File with fingerprint {} already exists, skipping write", fingerprint);
-            return Ok(file_id);
+            return Some(fingerprint.to_string());
+        }
+
+        let alias_path = self.alias_path(fingerprint);
+        let file_id = fs::read_to_string(&alias_path).await.ok()?;
+        let file_id = file_id.trim();
+        if self.file_path(file_id).is_ok_and(|path| path.exists()) {
+            return Some(file_id.to_string());
+        }
+        let _ = fs::remove_file(&alias_path).await;
+        None
+    }
+
+    // 记录客户端指纹（如前端按文件名、大小和修改时间生成的指纹）对应的文件 ID，写入 <fingerprint>.fp，
+    // 之后 check_fingerprint 和上传状态查询可以用同一个指纹找到文件；同一指纹再次上传时以最后一次为准
+    pub async fn record_fingerprint(&self, fingerprint: &str, file_id: &str) {
+        if fingerprint == file_id
+            || Self::validate_file_id(fingerprint).is_err()
+            || Self::validate_file_id(file_id).is_err()
+        {
+            return;
+        }
+        if let Err(e) = fs::write(self.alias_path(fingerprint), file_id).await {
+            tracing::warn!("Failed to record fingerprint {}: {}", fingerprint, e);
+        }
+    }
+
+    // 刷新文件的修改时间。定期清理按修改时间判断文件是否过期，
+    // 去重命中或 /check 找到已有文件时调用，客户端刚确认要用的文件不会在下一轮清理中被删除
+    pub fn touch(&self, file_id: &str) {
+        let Ok(path) = self.file_path(file_id) else {
+            return;
+        };
+        let result =
+            std::fs::File::open(&path).and_then(|file| file.set_modified(SystemTime::now()));
+        if let Err(e) = result {
+            tracing::warn!("Failed to refresh mtime of {}: {}", file_id, e);
         }
+    }
+
+    // fingerprint 须已通过 validate_file_id 或来自上传目录的扫描
+    fn alias_path(&self, fingerprint: &str) -> PathBuf {
+        self.upload_dir.join(format!("{}.fp", fingerprint))
+    }
 
-        // 确保上传目录存在
+    // 流式上传：边写盘边在阻塞线程中计算 SHA-1，两者并行，都完成后以摘要作为文件 ID，
+    // 客户端声明的指纹不能决定文件 ID，否则可以把任意内容存到别人将要去重的 ID 下。
+    // 数据先写入随机命名的临时文件，完成后再提升为正式文件；
+    // progress_key 为客户端的指纹时按它记录已写入的字节数，供 upload_progress 查询
+    pub async fn save_stream_hashed<S, E>(
+        &self,
+        mut stream: S,
+        progress_key: Option<&str>,
+    ) -> Result<(String, usize)>
+    where
+        S: Stream<Item = std::result::Result<Bytes, E>> + Unpin,
+        E: std::fmt::Display,
+    {
         fs::create_dir_all(&self.upload_dir)
             .await
             .map_err(AppError::FileAccess)?;
 
-        // 写入文件
-        let mut file = fs::File::create(&path)
+        let tmp_path = self
+            .upload_dir
+            .join(format!("{}.tmp", uuid::Uuid::new_v4().simple()));
+        let tmp_guard = TmpFileGuard::new(tmp_path.clone());
+        let progress =
+            progress_key.map(|key| UploadProgressGuard::new(self.uploads.clone(), key));
+
+        // Bytes 的克隆只增加引用计数，哈希线程和写盘使用同一份数据
+        let (chunk_tx, mut chunk_rx) = tokio::sync::mpsc::channel::<Bytes>(HASH_QUEUE_LEN);
+        let hasher = tokio::task::spawn_blocking(move || {
+            let mut hasher = Sha1::new();
+            while let Some(chunk) = chunk_rx.blocking_recv() {
+                hasher.update(&chunk);
+            }
+            hex_digest(hasher)
+        });
+
+        let mut file = fs::File::create(&tmp_path)
             .await
             .map_err(AppError::FileAccess)?;
 
-        file.write_all(data)
+        let mut written = 0;
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+            written += chunk.len();
+            if written > self.max_file_size {
+                return Err(AppError::FileTooLarge(written));
+            }
+
+            chunk_tx
+                .send(chunk.clone())
+                .await
+                .map_err(|_| AppError::Internal(anyhow::anyhow!("Hashing task stopped")))?;
+            file.write_all(&chunk)
+                .await
+                .map_err(AppError::FileAccess)?;
+            if let Some(progress) = &progress {
+                progress.set(written as u64);
+            }
+        }
+        drop(chunk_tx);
+        if written == 0 {
+            return Err(AppError::BadRequest("Empty file".to_string()));
+        }
+
+        file.flush().await.map_err(AppError::FileAccess)?;
+        file.sync_all().await.map_err(AppError::FileAccess)?;
+        drop(file);
+
+        let fingerprint = hasher.await.map_err(|e| AppError::Internal(e.into()))?;
+
+        let lock = self.upload_lock(&fingerprint);
+        let result = {
+            let _guard = lock.lock().await;
+            self.promote_tmp(&tmp_path, &fingerprint).await
+        };
+        drop(lock);
+        self.release_upload_lock(&fingerprint);
+        let file_id = result?;
+        tmp_guard.disarm();
+        drop(progress);
+
+        tracing::info!("File streamed with computed fingerprint: {} ({} bytes)", file_id, written);
+        Ok((file_id, written))
+    }
+
+    // 上传的数据经过压缩时，先把压缩数据写入临时文件，再在阻塞线程中解压，
+    // 返回解压后的数据流交给 save_stream_hashed 保存；数据损坏时流中返回错误。
+    // 保存方提前停止读取（如超过 max_file_size）时解压随之停止；解压本身也在输出超过 max_file_size 后停止，
+    // 此前的输出已交给保存方，由保存方按文件过大拒绝。压缩数据的临时文件在解压结束后删除
+    pub async fn decompress_stream<S, E>(
+        &self,
+        mut stream: S,
+        compression: Compression,
+    ) -> Result<impl Stream<Item = std::result::Result<Bytes, String>> + Unpin>
+    where
+        S: Stream<Item = std::result::Result<Bytes, E>> + Unpin,
+        E: std::fmt::Display,
+    {
+        fs::create_dir_all(&self.upload_dir)
+            .await
+            .map_err(AppError::FileAccess)?;
+
+        let tmp_path = self
+            .upload_dir
+            .join(format!("{}.tmp", uuid::Uuid::new_v4().simple()));
+        let tmp_guard = TmpFileGuard::new(tmp_path.clone());
+
+        let mut file = fs::File::create(&tmp_path)
             .await
             .map_err(AppError::FileAccess)?;
+        let mut written = 0;
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+            written += chunk.len();
+            if written > self.max_file_size {
+                return Err(AppError::FileTooLarge(written));
+            }
+
+            file.write_all(&chunk)
+                .await
+                .map_err(AppError::FileAccess)?;
+        }
+        file.flush().await.map_err(AppError::FileAccess)?;
+        drop(file);
+        if written == 0 {
+            return Err(AppError::BadRequest("Empty file".to_string()));
+        }
+        tracing::info!("Decompressing {} bytes of {} upload", written, compression.name());
+
+        let file = File::open(&tmp_path).map_err(AppError::FileAccess)?;
+        let (chunk_tx, mut chunk_rx) = tokio::sync::mpsc::channel(HASH_QUEUE_LEN);
+        let limit = self.max_file_size as u64;
+        tokio::task::spawn_blocking(move || {
+            let _tmp_guard = tmp_guard;
+            let mut send = |data: &[u8]| {
+                for chunk in data.chunks(DECOMPRESS_CHUNK_SIZE) {
+                    chunk_tx
+                        .blocking_send(Ok(Bytes::copy_from_slice(chunk)))
+                        .map_err(|_| std::io::Error::from(std::io::ErrorKind::BrokenPipe))?;
+                }
+                Ok(())
+            };
+            // BrokenPipe 表示保存方已经停止读取，不必再报告
+            if let Err(e) = compression.decompress(file, limit, &mut send) {
+                if e.kind() != std::io::ErrorKind::BrokenPipe {
+                    let _ = chunk_tx.blocking_send(Err(format!(
+                        "Invalid {} data: {}",
+                        compression.name(),
+                        e
+                    )));
+                }
+            }
+        });
+
+        Ok(futures::stream::poll_fn(move |cx| chunk_rx.poll_recv(cx)))
+    }
+
+    // 查询进行中的上传已写入的字节数，没有进行中的上传时返回 None
+    pub fn upload_progress(&self, fingerprint: &str) -> Option<u64> {
+        self.uploads
+            .lock()
+            .unwrap()
+            .get(fingerprint)
+            .map(|written| written.load(Ordering::Relaxed))
+    }
+
+    // 记录原始文件名和上传时计算的内容 SHA-1 等元数据，写入 <file_id>.meta
+    // 文件按指纹去重，已有元数据时保留首次上传的记录；写入失败不影响已保存的文件
+    pub async fn write_meta(
+        &self,
+        file_id: &str,
+        filename: Option<&str>,
+        size: usize,
+        sha1: Option<&str>,
+    ) {
+        if Self::validate_file_id(file_id).is_err() {
+            return;
+        }
+        let path = self.meta_path(file_id);
+        if path.exists() {
+            return;
+        }
+
+        let meta = FileMeta {
+            filename: filename.map(str::to_string),
+            uploaded_at: SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            size,
+            sha1: sha1.map(str::to_string),
+        };
+        let result = match serde_json::to_vec(&meta) {
+            Ok(json) => fs::write(&path, json).await.map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        if let Err(e) = result {
+            tracing::warn!("Failed to write metadata for {}: {}", file_id, e);
+        }
+    }
+
+    // 读取元数据，没有或无法解析时返回 None
+    async fn read_meta(&self, file_id: &str) -> Option<FileMeta> {
+        Self::validate_file_id(file_id).ok()?;
+        let json = fs::read(self.meta_path(file_id)).await.ok()?;
+        serde_json::from_slice(&json).ok()
+    }
+
+    // file_id 须已通过 validate_file_id 或来自上传目录的扫描
+    fn meta_path(&self, file_id: &str) -> PathBuf {
+        self.upload_dir.join(format!("{}.meta", file_id))
+    }
+
+    // 创建可续传上传，返回上传 ID
+    pub async fn create_upload(
+        &self,
+        fingerprint: &str,
+        filename: Option<String>,
+        size: usize,
+    ) -> Result<String> {
+        Self::validate_file_id(fingerprint)?;
+        if size == 0 {
+            return Err(AppError::BadRequest("Empty file".to_string()));
+        }
+        if size > self.max_file_size {
+            return Err(AppError::FileTooLarge(size));
+        }
+
+        fs::create_dir_all(&self.upload_dir)
+            .await
+            .map_err(AppError::FileAccess)?;
+
+        let upload_id = uuid::Uuid::new_v4().to_string();
+        let upload = ResumableUpload {
+            fingerprint: fingerprint.to_string(),
+            filename,
+            size,
+            lock: tokio::sync::Mutex::new(()),
+        };
+        // 先创建临时文件：写入状态前中断时，没有状态的临时文件按一次性上传的残留清理
+        fs::File::create(self.resumable_path(&upload_id))
+            .await
+            .map_err(AppError::FileAccess)?;
+        let json = serde_json::to_vec(&upload).map_err(|e| AppError::Internal(e.into()))?;
+        fs::write(self.resumable_meta_path(&upload_id), json)
+            .await
+            .map_err(AppError::FileAccess)?;
+
+        self.resumable
+            .lock()
+            .unwrap()
+            .insert(upload_id.clone(), Arc::new(upload));
 
-        tracing::info!("File saved with fingerprint: {}", fingerprint);
+        tracing::info!(
+            "Resumable upload {} created for fingerprint {} ({} bytes)",
+            upload_id,
+            fingerprint,
+            size
+        );
+        Ok(upload_id)
+    }
+
+    // 在 offset 处写入一段数据，返回已接收的字节数
+    // 已接收过的范围直接忽略，因此重发同一分块是无操作
+    pub async fn append_upload(&self, upload_id: &str, offset: usize, data: &[u8]) -> Result<usize> {
+        let upload = self.resumable_upload(upload_id).await?;
+        let _guard = upload.lock.lock().await;
+
+        let path = self.resumable_path(upload_id);
+        let received = fs::metadata(&path)
+            .await
+            .map_err(|_| AppError::FileNotFound(upload_id.to_string()))?
+            .len() as usize;
+
+        if offset > received {
+            return Err(AppError::BadRequest(format!(
+                "Chunk offset {} is past received length {}",
+                offset, received
+            )));
+        }
+
+        let end = offset + data.len();
+        if end > upload.size {
+            return Err(AppError::BadRequest(format!(
+                "Chunk ends at {} beyond declared size {}",
+                end, upload.size
+            )));
+        }
+        if end <= received {
+            return Ok(received);
+        }
+
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .await
+            .map_err(AppError::FileAccess)?;
+        file.write_all(&data[received - offset..])
+            .await
+            .map_err(AppError::FileAccess)?;
+        file.flush().await.map_err(AppError::FileAccess)?;
+
+        Ok(end)
+    }
+
+    // 校验长度和指纹后计算临时文件的 SHA-1，以摘要为文件 ID 提升为正式文件，返回 (file_id, 文件名, 大小)
+    // 与普通上传一样，客户端的指纹不能决定文件 ID；SHA-1 形式的指纹视为声明的摘要，与内容不符时丢弃这次上传
+    pub async fn complete_upload(
+        &self,
+        upload_id: &str,
+        fingerprint: &str,
+    ) -> Result<(String, Option<String>, usize)> {
+        let upload = self.resumable_upload(upload_id).await?;
+        let _guard = upload.lock.lock().await;
+
+        if upload.fingerprint != fingerprint {
+            return Err(AppError::BadRequest(
+                "Fingerprint does not match upload".to_string(),
+            ));
+        }
+
+        let tmp_path = self.resumable_path(upload_id);
+        let file = fs::File::open(&tmp_path)
+            .await
+            .map_err(|_| AppError::FileNotFound(upload_id.to_string()))?;
+        let received = file.metadata().await.map_err(AppError::FileAccess)?.len() as usize;
+        if received != upload.size {
+            return Err(AppError::BadRequest(format!(
+                "Upload incomplete: received {} of {} bytes",
+                received, upload.size
+            )));
+        }
+        file.sync_all().await.map_err(AppError::FileAccess)?;
+        drop(file);
+
+        let hash_path = tmp_path.clone();
+        let digest = tokio::task::spawn_blocking(move || sha1_file(&hash_path))
+            .await
+            .map_err(|e| AppError::Internal(e.into()))?
+            .map_err(AppError::FileAccess)?;
+        if is_sha1_hex(fingerprint) && !fingerprint.eq_ignore_ascii_case(&digest) {
+            self.resumable.lock().unwrap().remove(upload_id);
+            let _ = fs::remove_file(&tmp_path).await;
+            let _ = fs::remove_file(self.resumable_meta_path(upload_id)).await;
+            return Err(AppError::BadRequest(format!(
+                "Fingerprint {} does not match content ({})",
+                fingerprint, digest
+            )));
+        }
+
+        // 与普通上传共用写锁和去重逻辑
+        let lock = self.upload_lock(&digest);
+        let result = {
+            let _guard = lock.lock().await;
+            self.promote_tmp(&tmp_path, &digest).await
+        };
+        drop(lock);
+        self.release_upload_lock(&digest);
+
+        let file_id = result?;
+        self.resumable.lock().unwrap().remove(upload_id);
+        let _ = fs::remove_file(self.resumable_meta_path(upload_id)).await;
+        self.record_fingerprint(fingerprint, &file_id).await;
+
+        tracing::info!("Resumable upload {} completed as {}", upload_id, file_id);
+        Ok((file_id, upload.filename.clone(), upload.size))
+    }
+
+    // 查找进行中的可续传上传；重启前创建的上传不在内存中，从保存的状态恢复
+    async fn resumable_upload(&self, upload_id: &str) -> Result<Arc<ResumableUpload>> {
+        if let Some(upload) = self.resumable.lock().unwrap().get(upload_id) {
+            return Ok(upload.clone());
+        }
+
+        let not_found = || AppError::FileNotFound(upload_id.to_string());
+        // 上传 ID 来自请求路径，拼接路径前先校验
+        Self::validate_file_id(upload_id).map_err(|_| not_found())?;
+        if !self.resumable_path(upload_id).exists() {
+            return Err(not_found());
+        }
+        let json = fs::read(self.resumable_meta_path(upload_id))
+            .await
+            .map_err(|_| not_found())?;
+        let upload: ResumableUpload = serde_json::from_slice(&json).map_err(|_| not_found())?;
+
+        let mut resumable = self.resumable.lock().unwrap();
+        let upload = resumable
+            .entry(upload_id.to_string())
+            .or_insert_with(|| Arc::new(upload));
+        Ok(upload.clone())
+    }
+
+    // upload_id 须来自 create_upload 或已通过 validate_file_id
+    fn resumable_path(&self, upload_id: &str) -> PathBuf {
+        self.upload_dir.join(format!("{}.tmp", upload_id))
+    }
+
+    fn resumable_meta_path(&self, upload_id: &str) -> PathBuf {
+        self.upload_dir.join(format!("{}.tmp.meta", upload_id))
+    }
+
+    // 把完整的临时文件重命名为指纹文件，指纹文件已存在时丢弃临时文件
+    async fn promote_tmp(&self, tmp_path: &std::path::Path, fingerprint: &str) -> Result<String> {
+        let file_id = fingerprint.to_string();
+        let path = self.file_path(&file_id)?;
+
+        if path.exists() {
+            tracing::info!("File with fingerprint {} already exists, skipping write", fingerprint);
+            let _ = fs::remove_file(tmp_path).await;
+            self.touch(&file_id);
+            return Ok(file_id);
+        }
+
+        fs::rename(tmp_path, &path)
+            .await
+            .map_err(AppError::FileAccess)?;
         Ok(file_id)
     }
 
-    // 内存映射文件
-    pub fn mmap_file(&self, file_id: &str) -> Result<Arc<Mmap>> {
-        let path = self.upload_dir.join(file_id);
+    // 内存映射文件，同一文件的并发采样共享一个映射
+    // 映射在最后一个引用释放前一直有效：上传的文件写入临时文件后整体改名，之后不再原地修改，
+    // 删除只移除目录项，已有的映射（包括流式发送中的零拷贝采样结果）继续引用原来的数据
+    // 复用已有映射前检查文件是否被外部截断，长时间运行的读取还应在读取前调用 MappedFile::ensure_intact
+    pub fn mmap_file(&self, file_id: &str) -> Result<Arc<MappedFile>> {
+        let path = self.file_path(file_id)?;
+        let mut mmaps = self.mmaps.lock().unwrap();
+        if let Some(mmap) = mmaps.get(file_id).and_then(Weak::upgrade) {
+            mmap.ensure_intact()?;
+            // 文件在映射后被追加时重新映射，新的采样能看到追加的数据；旧映射由现有的引用继续持有
+            if !mmap.is_stale() {
+                return Ok(mmap);
+            }
+        }
 
         if !path.exists() {
             return Err(AppError::FileNotFound(file_id.to_string()));
@@ -70,18 +625,173 @@ impl FileManager {
 
         let file = File::open(&path).map_err(AppError::FileAccess)?;
 
+        // 服务器不会在映射期间截断或原地改写文件（见上），外部截断由 MappedFile::ensure_intact 检查
         let mmap = unsafe {
             MmapOptions::new()
                 .map(&file)
                 .map_err(AppError::FileAccess)?
         };
+        let mmap = Arc::new(MappedFile { mmap, file });
+
+        // 顺便清理已经释放的映射
+        mmaps.retain(|_, mmap| mmap.strong_count() > 0);
+        mmaps.insert(file_id.to_string(), Arc::downgrade(&mmap));
+
+        Ok(mmap)
+    }
+
+    fn forget_mmap(&self, file_id: &str) {
+        self.mmaps.lock().unwrap().remove(file_id);
+    }
+
+    // 通过魔数识别文件类型，只映射文件头部
+    pub fn detect_type(&self, file_id: &str) -> Result<Option<String>> {
+        let path = self.file_path(file_id)?;
+        let file = File::open(&path).map_err(|_| AppError::FileNotFound(file_id.to_string()))?;
+
+        let len = file.metadata().map_err(AppError::FileAccess)?.len() as usize;
+        if len == 0 {
+            return Ok(None);
+        }
+
+        let header = unsafe {
+            MmapOptions::new()
+                .len(len.min(magic::SNIFF_LEN))
+                .map(&file)
+                .map_err(AppError::FileAccess)?
+        };
+
+        Ok(magic::detect(&header).map(str::to_string))
+    }
+
+    // 分块读取已存储的文件并重新计算内容的 SHA-1，与 recorded_digest 比较即可发现静默损坏
+    // 读取大文件耗时较长，需要在阻塞线程中调用
+    pub fn content_digest(&self, file_id: &str) -> Result<String> {
+        let path = self.file_path(file_id)?;
+        sha1_file(&path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => AppError::FileNotFound(file_id.to_string()),
+            _ => AppError::FileAccess(e),
+        })
+    }
+
+    // 上传时记录的内容 SHA-1，可续传上传和旧版本保存的文件没有记录
+    pub async fn recorded_digest(&self, file_id: &str) -> Option<String> {
+        self.read_meta(file_id).await.and_then(|meta| meta.sha1)
+    }
+
+    // 缩略图：每个单元格聚合文件中的一段连续字节
+    pub fn thumbnail(&self, file_id: &str, cells: usize, dominant_class: bool) -> Result<Vec<u8>> {
+        let mmap = self.mmap_file(file_id)?;
+        Ok(analysis::thumbnail(&mmap, cells, dominant_class))
+    }
+
+    // 整个文件的字节直方图，熵和分位数都由它得出，调用方应通过直方图缓存复用
+    pub fn histogram(&self, file_id: &str) -> Result<[u64; 256]> {
+        let mmap = self.mmap_file(file_id)?;
+        Ok(analysis::histogram(&mmap))
+    }
+
+    // 查找长度不小于 min_run 的全零区间，返回 (offset, length)
+    pub fn zero_runs(&self, file_id: &str, min_run: usize) -> Result<Vec<(usize, usize)>> {
+        let mmap = self.mmap_file(file_id)?;
+        Ok(analysis::zero_runs(&mmap, min_run))
+    }
+
+    // 按窗口熵和高位字节比例把文件划分为 zero、text、code、compressed 区段
+    pub fn sections(
+        &self,
+        file_id: &str,
+        window_size: usize,
+        thresholds: &analysis::SectionThresholds,
+    ) -> Result<Vec<(usize, usize, analysis::SectionKind)>> {
+        let mmap = self.mmap_file(file_id)?;
+        Ok(analysis::sections(&mmap, window_size, thresholds))
+    }
+
+    // 提取可打印 ASCII 字符串，返回 (offset, 字符串)，最多 limit 个
+    pub fn extract_strings(
+        &self,
+        file_id: &str,
+        min_len: usize,
+        limit: usize,
+    ) -> Result<Vec<(usize, String)>> {
+        let mmap = self.mmap_file(file_id)?;
+        Ok(analysis::extract_strings(&mmap, min_len, limit))
+    }
+
+    // 延迟 1..=max_lag 的归一化自相关，大文件只取约 sample 字节
+    pub fn autocorrelation(&self, file_id: &str, max_lag: usize, sample: usize) -> Result<Vec<f32>> {
+        let mmap = self.mmap_file(file_id)?;
+        Ok(analysis::autocorrelation(&mmap, max_lag, sample))
+    }
+
+    // 获取文件大小，文件不存在时返回 FileNotFound
+    pub async fn file_size(&self, file_id: &str) -> Result<usize> {
+        let metadata = fs::metadata(self.file_path(file_id)?)
+            .await
+            .map_err(|_| AppError::FileNotFound(file_id.to_string()))?;
+
+        Ok(metadata.len() as usize)
+    }
+
+    // 文件当前在磁盘上的长度
+    // 内存映射的长度在映射时就固定了，跟随追加写入时必须用它来发现新数据
+    pub async fn current_len(&self, file_id: &str) -> Result<usize> {
+        self.file_size(file_id).await
+    }
+
+    // 读取文件中 [offset, offset + len) 的字节
+    pub async fn read_range(&self, file_id: &str, offset: usize, len: usize) -> Result<Vec<u8>> {
+        let mut file = fs::File::open(self.file_path(file_id)?)
+            .await
+            .map_err(|_| AppError::FileNotFound(file_id.to_string()))?;
+
+        file.seek(std::io::SeekFrom::Start(offset as u64))
+            .await
+            .map_err(AppError::FileAccess)?;
+
+        let mut data = vec![0u8; len];
+        file.read_exact(&mut data)
+            .await
+            .map_err(AppError::FileAccess)?;
+        Ok(data)
+    }
+
+    // 上传文件在磁盘上的路径，目录视为不存在；file_path 已排除临时文件和元数据文件的名称
+    pub async fn stored_path(&self, file_id: &str) -> Result<PathBuf> {
+        let not_found = || AppError::FileNotFound(file_id.to_string());
+        let path = self.file_path(file_id)?;
+        let metadata = fs::metadata(&path).await.map_err(|_| not_found())?;
+        if !metadata.is_file() {
+            return Err(not_found());
+        }
+        Ok(path)
+    }
 
-        Ok(Arc::new(mmap))
+    // 在上传目录写入并删除一个临时文件，确认磁盘可写；临时文件以 .tmp 结尾，不会出现在文件列表中
+    pub async fn check_writable(&self) -> Result<()> {
+        fs::create_dir_all(&self.upload_dir)
+            .await
+            .map_err(AppError::FileAccess)?;
+
+        let path = self
+            .upload_dir
+            .join(format!("health-{}.tmp", uuid::Uuid::new_v4().simple()));
+        let guard = TmpFileGuard::new(path.clone());
+        fs::write(&path, b"ok").await.map_err(AppError::FileAccess)?;
+        fs::remove_file(&path).await.map_err(AppError::FileAccess)?;
+        guard.disarm();
+        Ok(())
+    }
+
+    // 上传时记录的原始文件名
+    pub async fn original_filename(&self, file_id: &str) -> Option<String> {
+        self.read_meta(file_id).await.and_then(|meta| meta.filename)
     }
 
     // 获取文件信息
     pub async fn get_file_info(&self, file_id: &str) -> Result<FileInfo> {
-        let path = self.upload_dir.join(file_id);
+        let path = self.file_path(file_id)?;
 
         let metadata = fs::metadata(&path)
             .await
@@ -90,30 +800,686 @@ impl FileManager {
         Ok(FileInfo {
             id: file_id.to_string(),
             size: metadata.len() as usize,
-            created: metadata
-                .created()
-                .ok()
-                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-                .map(|d| d.as_secs())
-                .unwrap_or(0),
+            created: created_secs(&metadata),
+            file_type: self.detect_type(file_id)?,
+            filename: self.read_meta(file_id).await.and_then(|meta| meta.filename),
         })
     }
 
-    // 删除文件
+    // 按 options 筛选、排序后列出上传文件，返回 [offset, offset + limit) 范围内的文件和筛选后的总数
+    // 目录扫描只读取元数据；按类型筛选时识别每个文件的魔数，否则只识别返回的这一页
+    pub async fn list_files(&self, options: &FileListOptions) -> Result<(Vec<FileInfo>, usize)> {
+        let mut dir = match fs::read_dir(&self.upload_dir).await {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((Vec::new(), 0)),
+            Err(e) => return Err(AppError::FileAccess(e)),
+        };
+
+        let mut entries = Vec::new();
+        while let Some(entry) = dir.next_entry().await.map_err(AppError::FileAccess)? {
+            // 临时文件、元数据文件和其它不是文件 ID 的名称都不列出
+            let name = entry.file_name().to_string_lossy().to_string();
+            if Self::validate_file_id(&name).is_err() {
+                continue;
+            }
+
+            let Ok(metadata) = entry.metadata().await else {
+                continue;
+            };
+            if !metadata.is_file() {
+                continue;
+            }
+            if let Some(file_type) = &options.file_type {
+                if self.detect_type(&name).ok().flatten().as_ref() != Some(file_type) {
+                    continue;
+                }
+            }
+
+            let key = match options.sort {
+                FileSort::Created => created_secs(&metadata),
+                FileSort::Size => metadata.len(),
+            };
+            entries.push((key, name));
+        }
+
+        // 排序键相同时按 ID 排序，保证分页稳定
+        entries.sort_by(|a, b| {
+            let order = if options.descending {
+                b.0.cmp(&a.0)
+            } else {
+                a.0.cmp(&b.0)
+            };
+            order.then_with(|| a.1.cmp(&b.1))
+        });
+        let total = entries.len();
+
+        let mut files = Vec::new();
+        for (_, file_id) in entries.into_iter().skip(options.offset).take(options.limit) {
+            // 扫描之后被删除的文件直接跳过
+            match self.get_file_info(&file_id).await {
+                Ok(info) => files.push(info),
+                Err(AppError::FileNotFound(_)) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok((files, total))
+    }
+
+    // 删除超过 max_age 的上传文件，返回被删除的文件 ID（供调用方失效缓存）
+    pub async fn cleanup_older_than(&self, max_age: Duration) -> Result<Vec<String>> {
+        let mut removed = Vec::new();
+
+        let mut dir = match fs::read_dir(&self.upload_dir).await {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(removed),
+            Err(e) => return Err(AppError::FileAccess(e)),
+        };
+
+        let now = SystemTime::now();
+        let mut aliases = Vec::new();
+        while let Some(entry) = dir.next_entry().await.map_err(AppError::FileAccess)? {
+            let Ok(metadata) = entry.metadata().await else {
+                continue;
+            };
+            // 元数据随对应的文件一起删除，指纹映射在扫描结束后按指向的文件是否存在处理
+            let name = entry.file_name().to_string_lossy().to_string();
+            if let Some(fingerprint) = name.strip_suffix(".fp") {
+                aliases.push(fingerprint.to_string());
+                continue;
+            }
+            if !metadata.is_file() || name.ends_with(".meta") {
+                continue;
+            }
+            // 只处理上传产生的文件，.gitkeep 等不是文件 ID 的名字保留不动
+            let id = name.strip_suffix(".tmp").unwrap_or(&name);
+            if Self::validate_file_id(id).is_err() {
+                continue;
+            }
+
+            let age = metadata
+                .modified()
+                .ok()
+                .and_then(|mtime| now.duration_since(mtime).ok())
+                .unwrap_or_default();
+            if age < max_age {
+                continue;
+            }
+
+            if fs::remove_file(entry.path()).await.is_err() {
+                continue;
+            }
+
+            // 残留的临时文件只删除，不计入结果；可续传上传的状态随临时文件一起删除
+            if let Some(upload_id) = name.strip_suffix(".tmp") {
+                let _ = fs::remove_file(self.resumable_meta_path(upload_id)).await;
+            } else {
+                let _ = fs::remove_file(self.meta_path(&name)).await;
+                self.forget_mmap(&name);
+                removed.push(name);
+            }
+        }
+
+        // check_fingerprint 删除指向已删除文件的映射
+        for fingerprint in aliases {
+            self.check_fingerprint(&fingerprint).await;
+        }
+
+        // 临时文件已过期删除的可续传上传一并丢弃
+        self.resumable
+            .lock()
+            .unwrap()
+            .retain(|upload_id, _| self.resumable_path(upload_id).exists());
+
+        Ok(removed)
+    }
+
+    // 删除被中断的一次性上传留下的临时文件（关闭服务器时调用）
+    // 可续传上传的临时文件有保存的状态，保留下来，重启后客户端可以继续上传；
+    // 放弃的可续传上传由 FILE_TTL_SECS 的定期清理删除
+    pub async fn cleanup_tmp_files(&self) -> usize {
+        let Ok(mut dir) = fs::read_dir(&self.upload_dir).await else {
+            return 0;
+        };
+
+        let mut removed = 0;
+        while let Ok(Some(entry)) = dir.next_entry().await {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Some(upload_id) = name.strip_suffix(".tmp") else {
+                continue;
+            };
+            if self.resumable_meta_path(upload_id).exists() {
+                continue;
+            }
+            if fs::remove_file(entry.path()).await.is_ok() {
+                removed += 1;
+            }
+        }
+
+        removed
+    }
+
+    // 删除所有上传文件（进行中的上传临时文件除外），返回 (删除数量, 释放字节数)
+    pub async fn delete_all(&self) -> Result<(usize, u64)> {
+        let mut dir = match fs::read_dir(&self.upload_dir).await {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((0, 0)),
+            Err(e) => return Err(AppError::FileAccess(e)),
+        };
+
+        let mut removed = 0;
+        let mut freed = 0;
+        while let Some(entry) = dir.next_entry().await.map_err(AppError::FileAccess)? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.ends_with(".tmp") || name.ends_with(".meta") {
+                continue;
+            }
+            // 指纹映射随文件一起删除，不计入删除数量
+            if name.ends_with(".fp") {
+                let _ = fs::remove_file(entry.path()).await;
+                continue;
+            }
+
+            let Ok(metadata) = entry.metadata().await else {
+                continue;
+            };
+            if !metadata.is_file() {
+                continue;
+            }
+
+            if fs::remove_file(entry.path()).await.is_ok() {
+                let _ = fs::remove_file(self.meta_path(&name)).await;
+                self.forget_mmap(&name);
+                removed += 1;
+                freed += metadata.len();
+            }
+        }
+
+        Ok((removed, freed))
+    }
+
+    // 删除文件，进行中的采样和发送继续使用已有的映射，之后的请求返回文件不存在
+    // 只能 unlink，不能截断：截断会让仍在读取映射的请求收到 SIGBUS
     pub async fn delete_file(&self, file_id: &str) -> Result<()> {
-        let path = self.upload_dir.join(file_id);
+        let path = self.file_path(file_id)?;
 
-        fs::remove_file(&path)
-            .await
-            .map_err(|_| AppError::FileNotFound(file_id.to_string()))?;
+        // 不允许删除仍被映射的文件的平台上（Windows）返回访问错误，而不是文件不存在
+        fs::remove_file(&path).await.map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => AppError::FileNotFound(file_id.to_string()),
+            _ => AppError::FileAccess(e),
+        })?;
+        let _ = fs::remove_file(self.meta_path(file_id)).await;
+        self.forget_mmap(file_id);
 
         Ok(())
     }
 }
 
+// 文件创建时间（Unix 秒），文件系统不支持时为 0
+fn created_secs(metadata: &std::fs::Metadata) -> u64 {
+    metadata
+        .created()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// 文件 ID 的最大长度
+const MAX_FILE_ID_LEN: usize = 128;
+
+// 流式上传时等待哈希的数据块数，哈希跟不上写盘时限制内存占用
+const HASH_QUEUE_LEN: usize = 16;
+
+// 解压后的数据按这个大小分块交给保存方
+const DECOMPRESS_CHUNK_SIZE: usize = 256 * 1024;
+
+// 校验文件时每次读取的字节数
+const VERIFY_BUFFER_SIZE: usize = 1024 * 1024;
+
+// SHA-1 的十六进制摘要，客户端未提供指纹时用作文件 ID
+// 读取整个文件计算 SHA-1（阻塞）
+fn sha1_file(path: &std::path::Path) -> std::io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha1::new();
+    let mut buffer = vec![0u8; VERIFY_BUFFER_SIZE];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex_digest(hasher))
+}
+
+// 40 位十六进制，即 SHA-1 摘要的形式
+pub fn is_sha1_hex(value: &str) -> bool {
+    value.len() == 40 && value.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+fn hex_digest(hasher: Sha1) -> String {
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+// 临时文件守卫：未 disarm 就被丢弃时删除临时文件
+struct TmpFileGuard {
+    path: Option<PathBuf>,
+}
+
+impl TmpFileGuard {
+    fn new(path: PathBuf) -> Self {
+        Self { path: Some(path) }
+    }
+
+    fn disarm(mut self) {
+        self.path = None;
+    }
+}
+
+impl Drop for TmpFileGuard {
+    fn drop(&mut self) {
+        if let Some(path) = self.path.take() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+// 上传进度守卫：登记进行中的上传，结束（包括出错和取消）时移除
+struct UploadProgressGuard {
+    uploads: Arc<Mutex<HashMap<String, Arc<AtomicU64>>>>,
+    fingerprint: String,
+    written: Arc<AtomicU64>,
+}
+
+impl UploadProgressGuard {
+    fn new(uploads: Arc<Mutex<HashMap<String, Arc<AtomicU64>>>>, fingerprint: &str) -> Self {
+        let written = Arc::new(AtomicU64::new(0));
+        uploads
+            .lock()
+            .unwrap()
+            .insert(fingerprint.to_string(), written.clone());
+
+        Self {
+            uploads,
+            fingerprint: fingerprint.to_string(),
+            written,
+        }
+    }
+
+    fn set(&self, written: u64) {
+        self.written.store(written, Ordering::Relaxed);
+    }
+}
+
+impl Drop for UploadProgressGuard {
+    fn drop(&mut self) {
+        self.uploads.lock().unwrap().remove(&self.fingerprint);
+    }
+}
+
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct FileInfo {
     pub id: String,
     pub size: usize,
     pub created: u64,
+    pub file_type: Option<String>,
+    // 上传时的原始文件名，旧文件或未提供文件名时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+}
+
+// 文件列表的排序键
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileSort {
+    Created,
+    Size,
+}
+
+// 文件列表的筛选、排序和分页参数
+#[derive(Debug, Clone)]
+pub struct FileListOptions {
+    pub sort: FileSort,
+    pub descending: bool,
+    // 只保留魔数识别为该类型的文件（如 "elf"、"png"）
+    pub file_type: Option<String>,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+// 文件元数据，以 JSON 保存在 <file_id>.meta 中
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct FileMeta {
+    filename: Option<String>,
+    uploaded_at: u64,
+    size: usize,
+    // 上传时边接收边计算的内容 SHA-1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sha1: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 每个测试使用独立的上传目录，结束时删除
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("binaryvis-test-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        fn manager(&self) -> FileManager {
+            FileManager::new(self.0.clone(), 1024 * 1024)
+        }
+
+        fn entries(&self) -> Vec<String> {
+            let mut names: Vec<String> = std::fs::read_dir(&self.0)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+                .collect();
+            names.sort();
+            names
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn validate_file_id_rejects_path_characters() {
+        for id in ["", "..", "../x", "a/b", "a\\b", "x.tmp", "x.meta", "a b", &"a".repeat(129)] {
+            assert!(FileManager::validate_file_id(id).is_err(), "{:?}", id);
+        }
+        for id in ["abc", "1a2b_1024_1700000000000", "0123456789abcdef-ABC"] {
+            assert!(FileManager::validate_file_id(id).is_ok(), "{:?}", id);
+        }
+    }
+
+    #[tokio::test]
+    async fn create_upload_rejects_traversal_fingerprint() {
+        let dir = TestDir::new();
+        let manager = dir.manager();
+
+        let result = manager.create_upload("../../outside", None, 4).await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+        assert!(dir.entries().is_empty());
+    }
+
+    #[tokio::test]
+    async fn entry_points_reject_ids_outside_upload_dir() {
+        let dir = TestDir::new();
+        let manager = dir.manager();
+        // 路径参数解码 %2F 之后得到的 ID
+        let outside = dir.0.with_extension("outside");
+        std::fs::write(&outside, b"secret").unwrap();
+        let id = format!("../{}", outside.file_name().unwrap().to_string_lossy());
+
+        assert!(manager.stored_path(&id).await.is_err());
+        assert!(manager.mmap_file(&id).is_err());
+        assert!(manager.content_digest(&id).is_err());
+        assert!(manager.file_size(&id).await.is_err());
+        assert!(manager.read_range(&id, 0, 1).await.is_err());
+        assert!(manager.get_file_info(&id).await.is_err());
+        assert!(manager.check_fingerprint(&id).await.is_none());
+        assert!(manager.delete_file(&id).await.is_err());
+        manager.write_meta(&id, Some("x"), 6, None).await;
+
+        assert!(outside.exists());
+        assert!(!dir.0.with_extension("outside.meta").exists());
+        std::fs::remove_file(outside).unwrap();
+    }
+
+    fn chunks(data: &[&'static [u8]]) -> impl Stream<Item = std::io::Result<Bytes>> + Unpin {
+        futures::stream::iter(
+            data.iter()
+                .map(|chunk| Ok(Bytes::from_static(chunk)))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    #[tokio::test]
+    async fn save_stream_hashed_keys_by_content_not_declared_fingerprint() {
+        let dir = TestDir::new();
+        let manager = dir.manager();
+
+        let (file_id, size) = manager
+            .save_stream_hashed(chunks(&[b"hello ", b"world"]), Some("victim_11_0"))
+            .await
+            .unwrap();
+        // SHA-1("hello world")
+        assert_eq!(file_id, "2aae6c35c94fcfb415dbe95f408b9ce91ee846ed");
+        assert_eq!(size, 11);
+        assert!(manager.check_fingerprint("victim_11_0").await.is_none());
+        assert_eq!(manager.upload_progress("victim_11_0"), None);
+        assert_eq!(dir.entries(), vec![file_id]);
+    }
+
+    #[tokio::test]
+    async fn recorded_digest_detects_corruption_independent_of_file_id() {
+        let dir = TestDir::new();
+        let manager = dir.manager();
+        // 前端指纹形式的文件 ID，不是内容摘要
+        let file_id = "5d41402a_11_1700000000000";
+        std::fs::write(dir.0.join(file_id), b"hello world").unwrap();
+        manager
+            .write_meta(file_id, None, 11, Some("2aae6c35c94fcfb415dbe95f408b9ce91ee846ed"))
+            .await;
+
+        let recorded = manager.recorded_digest(file_id).await.unwrap();
+        assert_eq!(manager.content_digest(file_id).unwrap(), recorded);
+
+        std::fs::write(dir.0.join(file_id), b"hello w0rld").unwrap();
+        assert_ne!(manager.content_digest(file_id).unwrap(), recorded);
+
+        std::fs::write(dir.0.join("other"), b"x").unwrap();
+        manager.write_meta("other", None, 1, None).await;
+        assert_eq!(manager.recorded_digest("other").await, None);
+    }
+
+    #[tokio::test]
+    async fn failed_write_leaves_no_partial_file() {
+        let dir = TestDir::new();
+        let manager = dir.manager();
+
+        let stream = futures::stream::iter(vec![
+            Ok(Bytes::from_static(b"partial ")),
+            Err(std::io::Error::other("connection reset")),
+        ]);
+        let result = manager.save_stream_hashed(stream, Some("fp_8_0")).await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+
+        // 既没有正式文件也没有 .tmp，之后同一内容或指纹都不会命中去重
+        assert!(dir.entries().is_empty());
+        assert!(manager.check_fingerprint("fp_8_0").await.is_none());
+        // SHA-1("partial ")
+        assert!(manager
+            .check_fingerprint("338008501ecab36379bf0406e12fdc8c863a8ee8")
+            .await
+            .is_none());
+        assert_eq!(manager.upload_progress("fp_8_0"), None);
+    }
+
+    #[tokio::test]
+    async fn oversized_write_leaves_no_partial_file() {
+        let dir = TestDir::new();
+        let manager = FileManager::new(dir.0.clone(), 8);
+
+        let result = manager
+            .save_stream_hashed(chunks(&[b"12345", b"67890"]), None)
+            .await;
+        assert!(matches!(result, Err(AppError::FileTooLarge(10))));
+        assert!(dir.entries().is_empty());
+    }
+
+    #[tokio::test]
+    async fn empty_upload_is_rejected() {
+        let dir = TestDir::new();
+        let manager = dir.manager();
+
+        for stream in [chunks(&[]), chunks(&[b"", b""])] {
+            let result = manager.save_stream_hashed(stream, None).await;
+            assert!(
+                matches!(result, Err(AppError::BadRequest(message)) if message == "Empty file")
+            );
+        }
+        let result = manager.create_upload("fp_0_0", None, 0).await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+        assert!(dir.entries().is_empty());
+    }
+
+    #[test]
+    fn mmap_of_zero_length_file_is_empty() {
+        // 上传接口不再产生空文件，但上传目录里仍可能有旧的或外部放入的空文件
+        let dir = TestDir::new();
+        let manager = dir.manager();
+        std::fs::write(dir.0.join("empty"), b"").unwrap();
+
+        let mmap = manager.mmap_file("empty").unwrap();
+        assert!(mmap.is_empty());
+        mmap.ensure_intact().unwrap();
+    }
+
+    #[tokio::test]
+    async fn shutdown_cleanup_keeps_resumable_uploads() {
+        let dir = TestDir::new();
+        let manager = dir.manager();
+
+        let upload_id = manager
+            .create_upload("fp_10_0", Some("a.bin".to_string()), 10)
+            .await
+            .unwrap();
+        manager.append_upload(&upload_id, 0, b"01234").await.unwrap();
+        // 被中断的一次性上传留下的临时文件
+        std::fs::write(dir.0.join("0123456789abcdef.tmp"), b"partial").unwrap();
+
+        assert_eq!(manager.cleanup_tmp_files().await, 1);
+        assert!(!dir.0.join("0123456789abcdef.tmp").exists());
+
+        // 重启后从保存的状态继续上传
+        let restarted = dir.manager();
+        assert_eq!(
+            restarted.append_upload(&upload_id, 5, b"56789").await.unwrap(),
+            10
+        );
+        let (file_id, filename, size) = restarted
+            .complete_upload(&upload_id, "fp_10_0")
+            .await
+            .unwrap();
+        assert_eq!(file_id, "87acec17cd9dcd20a716cc2cf67417b71c8a7016");
+        assert_eq!(filename.as_deref(), Some("a.bin"));
+        assert_eq!(size, 10);
+        assert_eq!(dir.entries(), [file_id, "fp_10_0.fp".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn unknown_upload_ids_are_not_found() {
+        let dir = TestDir::new();
+        let manager = dir.manager();
+
+        for upload_id in ["missing", "../fp_10_0", "a.tmp"] {
+            let result = manager.append_upload(upload_id, 0, b"x").await;
+            assert!(matches!(result, Err(AppError::FileNotFound(_))), "{}", upload_id);
+        }
+    }
+
+    #[tokio::test]
+    async fn sweep_spares_reuploaded_and_foreign_files() {
+        let dir = TestDir::new();
+        let manager = dir.manager();
+        let hour = Duration::from_secs(3600);
+        let age = |name: &str| {
+            std::fs::File::open(dir.0.join(name))
+                .and_then(|file| file.set_modified(SystemTime::now() - 2 * hour))
+                .unwrap()
+        };
+        std::fs::write(dir.0.join(".gitkeep"), b"").unwrap();
+        age(".gitkeep");
+
+        let (file_id, _) = manager
+            .save_stream_hashed(chunks(&[b"hello"]), None)
+            .await
+            .unwrap();
+        age(&file_id);
+        // 去重命中刷新修改时间，刚重新上传的文件不算过期
+        manager
+            .save_stream_hashed(chunks(&[b"hello"]), None)
+            .await
+            .unwrap();
+        assert!(manager.cleanup_older_than(hour).await.unwrap().is_empty());
+
+        // 不是文件 ID 的名字既不删除也不出现在结果中
+        age(&file_id);
+        assert_eq!(manager.cleanup_older_than(hour).await.unwrap(), [file_id]);
+        assert_eq!(dir.entries(), [".gitkeep"]);
+    }
+
+    #[tokio::test]
+    async fn fingerprint_aliases_follow_their_file() {
+        let dir = TestDir::new();
+        let manager = dir.manager();
+        std::fs::write(dir.0.join("0123abcd"), b"data").unwrap();
+
+        manager.record_fingerprint("fp_4_0", "0123abcd").await;
+        // 非法的指纹不记录
+        manager.record_fingerprint("../fp", "0123abcd").await;
+        assert_eq!(
+            manager.check_fingerprint("fp_4_0").await.as_deref(),
+            Some("0123abcd")
+        );
+        assert_eq!(
+            manager.check_fingerprint("0123abcd").await.as_deref(),
+            Some("0123abcd")
+        );
+
+        // 清理不把映射当作文件；文件删除后映射随之删除
+        assert!(manager.cleanup_older_than(Duration::ZERO).await.unwrap() == ["0123abcd"]);
+        assert!(dir.entries().is_empty());
+        assert_eq!(manager.check_fingerprint("fp_4_0").await, None);
+    }
+
+    // 一次完成的可续传上传
+    async fn resumable(manager: &FileManager, fingerprint: &str, data: &[u8]) -> Result<String> {
+        let upload_id = manager
+            .create_upload(fingerprint, None, data.len())
+            .await
+            .unwrap();
+        manager.append_upload(&upload_id, 0, data).await.unwrap();
+        let result = manager.complete_upload(&upload_id, fingerprint).await;
+        result.map(|(file_id, _, _)| file_id)
+    }
+
+    #[tokio::test]
+    async fn resumable_uploads_are_keyed_by_content() {
+        let dir = TestDir::new();
+        let manager = dir.manager();
+        let victim = "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d";
+
+        // 声明别人内容的摘要、上传不同的内容：拒绝并丢弃这次上传
+        let result = resumable(&manager, victim, b"evil!").await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+        assert!(dir.entries().is_empty());
+
+        // 真正的内容随后按摘要保存，不会命中伪造的文件
+        assert_eq!(resumable(&manager, victim, b"hello").await.unwrap(), victim);
+        assert_eq!(std::fs::read(dir.0.join(victim)).unwrap(), b"hello");
+
+        // 其它形式的指纹不决定文件 ID，只记录映射
+        let file_id = resumable(&manager, "fp_5_0", b"hello").await.unwrap();
+        assert_eq!(file_id, victim);
+        assert_eq!(
+            manager.check_fingerprint("fp_5_0").await.as_deref(),
+            Some(victim)
+        );
+    }
 }
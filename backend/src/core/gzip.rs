@@ -0,0 +1,493 @@
+// gzip（RFC 1952）解码，成员内的压缩数据为 deflate（RFC 1951）
+// 多个成员首尾相接时（gzip 命令对这样的文件解压为各成员内容的拼接）依次解码，每个成员校验 CRC32 和长度
+use super::decompress::{invalid_data, unexpected_end, Input, OutputWindow};
+use std::io::{self, Read};
+
+pub(super) fn decompress<R: Read>(
+    input: &mut Input<R>,
+    limit: u64,
+    sink: &mut dyn FnMut(&[u8]) -> io::Result<()>,
+) -> io::Result<u64> {
+    let mut reader = BitReader::new(input);
+    let mut total = 0;
+    loop {
+        total += member(&mut reader, limit - total, sink)?;
+        if reader.at_end()? {
+            return Ok(total);
+        }
+    }
+}
+
+// deflate 回溯距离的上限
+const WINDOW_SIZE: usize = 32 * 1024;
+
+// 头部标志位
+const FLAG_HCRC: u8 = 0x02;
+const FLAG_EXTRA: u8 = 0x04;
+const FLAG_NAME: u8 = 0x08;
+const FLAG_COMMENT: u8 = 0x10;
+const FLAG_RESERVED: u8 = 0xe0;
+
+fn member<R: Read>(
+    reader: &mut BitReader<R>,
+    limit: u64,
+    sink: &mut dyn FnMut(&[u8]) -> io::Result<()>,
+) -> io::Result<u64> {
+    if reader.le(2)? != 0x8b1f {
+        return Err(invalid_data("not gzip data"));
+    }
+    let method = reader.byte()?;
+    if method != 8 {
+        return Err(invalid_data(format!(
+            "unsupported gzip compression method {}",
+            method
+        )));
+    }
+    let flags = reader.byte()?;
+    if flags & FLAG_RESERVED != 0 {
+        return Err(invalid_data("reserved gzip header flags are set"));
+    }
+    // 修改时间、额外标志和操作系统
+    reader.le(6)?;
+    if flags & FLAG_EXTRA != 0 {
+        let len = reader.le(2)?;
+        for _ in 0..len {
+            reader.byte()?;
+        }
+    }
+    // 原始文件名和注释都以 0 结尾
+    for flag in [FLAG_NAME, FLAG_COMMENT] {
+        if flags & flag != 0 {
+            while reader.byte()? != 0 {}
+        }
+    }
+    if flags & FLAG_HCRC != 0 {
+        reader.le(2)?;
+    }
+
+    let mut crc = Crc32::new();
+    let size = {
+        let mut checked = |data: &[u8]| {
+            crc.update(data);
+            sink(data)
+        };
+        let mut window = OutputWindow::new(WINDOW_SIZE, limit, &mut checked);
+        inflate(reader, &mut window)?;
+        window.finish()?
+    };
+
+    // 尾部按字节对齐：CRC32 和 mod 2^32 的原始长度
+    reader.align();
+    let expected_crc = reader.le(4)? as u32;
+    let expected_size = reader.le(4)? as u32;
+    if crc.finish() != expected_crc {
+        return Err(invalid_data("gzip CRC32 mismatch, the data is corrupt"));
+    }
+    if size as u32 != expected_size {
+        return Err(invalid_data("gzip length mismatch, the data is corrupt"));
+    }
+    Ok(size)
+}
+
+fn inflate<R: Read>(reader: &mut BitReader<R>, window: &mut OutputWindow) -> io::Result<()> {
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => stored_block(reader, window)?,
+            1 => {
+                let (literals, distances) = fixed_codes();
+                compressed_block(reader, window, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(reader)?;
+                compressed_block(reader, window, &literals, &distances)?;
+            }
+            _ => return Err(invalid_data("invalid deflate block type")),
+        }
+        if last {
+            return Ok(());
+        }
+    }
+}
+
+fn stored_block<R: Read>(reader: &mut BitReader<R>, window: &mut OutputWindow) -> io::Result<()> {
+    reader.align();
+    let len = reader.le(2)? as u16;
+    let nlen = reader.le(2)? as u16;
+    if len != !nlen {
+        return Err(invalid_data("deflate stored block length check failed"));
+    }
+    // 先取出已经读入位缓冲的字节，其余直接整块读取
+    let mut remaining = len as usize;
+    while remaining > 0 && reader.count > 0 {
+        window.push(reader.byte()?)?;
+        remaining -= 1;
+    }
+    let mut buf = [0u8; 4096];
+    while remaining > 0 {
+        let n = remaining.min(buf.len());
+        reader.input.read_exact(&mut buf[..n])?;
+        window.extend(&buf[..n])?;
+        remaining -= n;
+    }
+    Ok(())
+}
+
+// 长度码 257–285 的基数和额外位数
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+// 距离码 0–29 的基数和额外位数
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+fn compressed_block<R: Read>(
+    reader: &mut BitReader<R>,
+    window: &mut OutputWindow,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> io::Result<()> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+        if symbol < 256 {
+            window.push(symbol as u8)?;
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+
+        let index = symbol - 257;
+        if index >= LENGTH_BASE.len() {
+            return Err(invalid_data("invalid deflate length code"));
+        }
+        let length = LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index])? as usize;
+
+        let index = distances.decode(reader)? as usize;
+        if index >= DISTANCE_BASE.len() {
+            return Err(invalid_data("invalid deflate distance code"));
+        }
+        let distance = DISTANCE_BASE[index] as usize + reader.bits(DISTANCE_EXTRA[index])? as usize;
+
+        window.copy(distance, length)?;
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    // 固定码表是完整的前缀码，不会构建失败
+    let literals = Huffman::new(&lengths).expect("fixed literal code");
+    let distances = Huffman::new(&[5; 30]).expect("fixed distance code");
+    (literals, distances)
+}
+
+// 码长码的码长按这个顺序出现
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+fn dynamic_codes<R: Read>(reader: &mut BitReader<R>) -> io::Result<(Huffman, Huffman)> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+    if literal_count > 286 || distance_count > 30 {
+        return Err(invalid_data("too many deflate codes"));
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[symbol] = reader.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths)?;
+
+    // 字面量/长度码和距离码的码长连续编码，重复可以跨越两者的边界
+    let count = literal_count + distance_count;
+    let mut lengths = vec![0u8; count];
+    let mut i = 0;
+    while i < count {
+        let symbol = code_length_code.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *i
+                    .checked_sub(1)
+                    .and_then(|previous| lengths.get(previous))
+                    .ok_or_else(|| {
+                        invalid_data("deflate code length repeat without a previous length")
+                    })?;
+                (previous, 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        if i + repeat > count {
+            return Err(invalid_data("deflate code lengths overflow"));
+        }
+        lengths[i..i + repeat].fill(value);
+        i += repeat;
+    }
+
+    if lengths[256] == 0 {
+        return Err(invalid_data("deflate block has no end-of-block code"));
+    }
+    let literals = Huffman::new(&lengths[..literal_count])?;
+    let distances = Huffman::new(&lengths[literal_count..])?;
+    Ok((literals, distances))
+}
+
+// 范式 Huffman 解码表，按接下来 bits 位（低位在前）直接查出符号和码长
+// 每项为 symbol << 4 | length，length 为 0 表示没有对应的码
+struct Huffman {
+    table: Vec<u16>,
+    bits: u8,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> io::Result<Self> {
+        let mut counts = [0u32; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // 过度分配的码长无法构成前缀码；不完整的码允许出现（例如只用到一个距离码），遇到未分配的码时报错
+        let mut left = 1i64;
+        for &count in &counts[1..] {
+            left = (left << 1) - count as i64;
+            if left < 0 {
+                return Err(invalid_data("over-subscribed deflate code lengths"));
+            }
+        }
+
+        let bits = lengths.iter().copied().max().unwrap_or(0).max(1);
+        let mut next_code = [0u32; 16];
+        let mut code = 0;
+        for length in 1..16 {
+            code = (code + counts[length - 1]) << 1;
+            next_code[length] = code;
+        }
+
+        let mut table = vec![0u16; 1 << bits];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length == 0 {
+                continue;
+            }
+            let code = next_code[length as usize];
+            next_code[length as usize] += 1;
+            // 码按高位在前定义，数据流按低位在前读取，表项下标为反转后的码
+            let reversed = code.reverse_bits() >> (32 - length as u32);
+            let entry = (symbol as u16) << 4 | length as u16;
+            for index in (reversed as usize..table.len()).step_by(1 << length) {
+                table[index] = entry;
+            }
+        }
+
+        Ok(Self { table, bits })
+    }
+
+    fn decode<R: Read>(&self, reader: &mut BitReader<R>) -> io::Result<u16> {
+        let entry = self.table[reader.peek(self.bits)? as usize];
+        let length = (entry & 0xf) as u8;
+        if length == 0 {
+            return Err(invalid_data("invalid deflate Huffman code"));
+        }
+        reader.consume(length)?;
+        Ok(entry >> 4)
+    }
+}
+
+// 低位在前的位读取；peek 可能多读入之后的字节，按字节读取时先从已读入的位中取
+struct BitReader<'a, R> {
+    input: &'a mut Input<R>,
+    bits: u64,
+    count: u8,
+}
+
+impl<'a, R: Read> BitReader<'a, R> {
+    fn new(input: &'a mut Input<R>) -> Self {
+        Self {
+            input,
+            bits: 0,
+            count: 0,
+        }
+    }
+
+    // 接下来的 n 位（n <= 32），输入不足时高位补 0，由 consume 检查是否真的有这么多位
+    fn peek(&mut self, n: u8) -> io::Result<u32> {
+        while self.count < n {
+            match self.input.try_byte()? {
+                Some(byte) => {
+                    self.bits |= (byte as u64) << self.count;
+                    self.count += 8;
+                }
+                None => break,
+            }
+        }
+        Ok((self.bits & ((1u64 << n) - 1)) as u32)
+    }
+
+    fn consume(&mut self, n: u8) -> io::Result<()> {
+        if n > self.count {
+            return Err(unexpected_end());
+        }
+        self.bits >>= n;
+        self.count -= n;
+        Ok(())
+    }
+
+    fn bits(&mut self, n: u8) -> io::Result<u32> {
+        let value = self.peek(n)?;
+        self.consume(n)?;
+        Ok(value)
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        Ok(self.bits(8)? as u8)
+    }
+
+    // 小端整数（bytes <= 8），调用时已按字节对齐
+    fn le(&mut self, bytes: usize) -> io::Result<u64> {
+        let mut value = 0;
+        for i in 0..bytes {
+            value |= (self.byte()? as u64) << (8 * i);
+        }
+        Ok(value)
+    }
+
+    // 丢弃当前字节剩余的位
+    fn align(&mut self) {
+        let partial = self.count % 8;
+        self.bits >>= partial;
+        self.count -= partial;
+    }
+
+    fn at_end(&mut self) -> io::Result<bool> {
+        Ok(self.count == 0 && self.input.at_end()?)
+    }
+}
+
+// CRC-32（IEEE 802.3，反射多项式 0xEDB88320）
+struct Crc32(u32);
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+impl Crc32 {
+    fn new() -> Self {
+        Self(!0)
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.0 = CRC32_TABLE[((self.0 ^ byte as u32) & 0xff) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::decompress::tests::{decode, short, SHORT_GZ};
+    use super::super::decompress::Compression;
+    use super::*;
+
+    // 头部之后为 deflate 数据
+    const HEADER: [u8; 10] = [0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 3];
+
+    fn gzip(header_flags: u8, fields: &[u8], deflate: &[u8]) -> Vec<u8> {
+        let mut data = HEADER.to_vec();
+        data[3] = header_flags;
+        data.extend_from_slice(fields);
+        data.extend_from_slice(deflate);
+        data
+    }
+
+    #[test]
+    fn optional_header_fields_are_skipped() {
+        let deflate = &SHORT_GZ[HEADER.len()..];
+        let mut fields = vec![3, 0, b'x', b'y', b'z'];
+        fields.extend_from_slice(b"short.txt\0a comment\0");
+        fields.extend_from_slice(&[0xab, 0xcd]);
+        let flags = FLAG_EXTRA | FLAG_NAME | FLAG_COMMENT | FLAG_HCRC;
+
+        let data = gzip(flags, &fields, deflate);
+        assert_eq!(decode(Compression::Gzip, &data, u64::MAX).unwrap(), short());
+    }
+
+    #[test]
+    fn reserved_flags_and_other_methods_are_rejected() {
+        let deflate = &SHORT_GZ[HEADER.len()..];
+        assert!(decode(Compression::Gzip, &gzip(0x20, &[], deflate), u64::MAX).is_err());
+
+        let mut data = SHORT_GZ.to_vec();
+        data[2] = 7;
+        assert!(decode(Compression::Gzip, &data, u64::MAX).is_err());
+    }
+
+    #[test]
+    fn checksum_and_length_mismatches_are_rejected() {
+        let crc_at = SHORT_GZ.len() - 8;
+        for (pos, message) in [(crc_at, "CRC32"), (crc_at + 4, "length")] {
+            let mut data = SHORT_GZ.to_vec();
+            data[pos] ^= 1;
+            let error = decode(Compression::Gzip, &data, u64::MAX).unwrap_err();
+            assert!(error.to_string().contains(message), "{}", error);
+        }
+    }
+
+    #[test]
+    fn trailing_garbage_is_rejected() {
+        let data = [SHORT_GZ, b"junk"].concat();
+        assert!(decode(Compression::Gzip, &data, u64::MAX).is_err());
+    }
+
+    #[test]
+    fn back_reference_before_the_start_is_rejected() {
+        // 固定 Huffman 块，第一个符号就是长度 3、距离 1 的回溯
+        let data = gzip(0, &[], &[0x03, 0x02]);
+        let error = decode(Compression::Gzip, &data, u64::MAX).unwrap_err();
+        assert!(error.to_string().contains("distance"), "{}", error);
+    }
+
+    #[test]
+    fn stored_block_with_bad_length_check_is_rejected() {
+        // 最后一个原始块，LEN = 1 而 NLEN 不是它的反码
+        let data = gzip(0, &[], &[0x01, 0x01, 0x00, 0x00, 0x00, b'x']);
+        let error = decode(Compression::Gzip, &data, u64::MAX).unwrap_err();
+        assert!(error.to_string().contains("length check"), "{}", error);
+    }
+}
@@ -0,0 +1,59 @@
+// 文件类型签名表：(偏移, 魔数, 类型标签)
+// 按顺序匹配，更具体的签名放在前面
+const SIGNATURES: &[(usize, &[u8], &str)] = &[
+    (0, b"\x7fELF", "elf"),
+    (0, b"\x89PNG\r\n\x1a\n", "png"),
+    (0, b"%PDF-", "pdf"),
+    (0, b"PK\x03\x04", "zip"),
+    (0, b"PK\x05\x06", "zip"),
+    (0, b"\x1f\x8b", "gzip"),
+    (0, b"BZh", "bzip2"),
+    (0, b"\xfd7zXZ\x00", "xz"),
+    (0, b"\x28\xb5\x2f\xfd", "zstd"),
+    (0, b"7z\xbc\xaf\x27\x1c", "7z"),
+    (0, b"Rar!\x1a\x07", "rar"),
+    (257, b"ustar", "tar"),
+    (0, b"\xff\xd8\xff", "jpeg"),
+    (0, b"GIF87a", "gif"),
+    (0, b"GIF89a", "gif"),
+    (0, b"SQLite format 3\x00", "sqlite"),
+    (0, b"\x00asm", "wasm"),
+    (0, b"\xfe\xed\xfa\xce", "macho"),
+    (0, b"\xfe\xed\xfa\xcf", "macho"),
+    (0, b"\xce\xfa\xed\xfe", "macho"),
+    (0, b"\xcf\xfa\xed\xfe", "macho"),
+    (0, b"\xca\xfe\xba\xbe", "java_class"),
+    (0, b"dex\n", "dex"),
+];
+
+// 识别时最多读取的头部字节数
+pub const SNIFF_LEN: usize = 4096;
+
+// 根据文件头部字节猜测文件类型
+pub fn detect(header: &[u8]) -> Option<&'static str> {
+    // MZ 头需要进一步区分 PE 和 DOS 可执行文件
+    if header.starts_with(b"MZ") {
+        return Some(if is_pe(header) { "pe" } else { "dos" });
+    }
+
+    SIGNATURES
+        .iter()
+        .find(|(offset, magic, _)| {
+            header
+                .get(*offset..*offset + magic.len())
+                .is_some_and(|bytes| bytes == *magic)
+        })
+        .map(|(_, _, label)| *label)
+}
+
+// e_lfanew（0x3C 处）指向 "PE\0\0" 签名
+fn is_pe(header: &[u8]) -> bool {
+    let Some(bytes) = header.get(0x3c..0x40) else {
+        return false;
+    };
+    let pe_offset = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+
+    header
+        .get(pe_offset..pe_offset + 4)
+        .is_some_and(|sig| sig == b"PE\0\0")
+}
@@ -1,7 +1,17 @@
+pub mod analysis;
 pub mod file_manager;
 pub mod cache;
+pub mod decompress;
+pub mod disk_cache;
+pub mod fetch;
+pub mod gzip;
+pub mod magic;
+pub mod render;
+pub mod webp;
+pub mod zstd;
 pub mod sampler;
 
-pub use file_manager::{FileManager, FileInfo};
-pub use cache::Cache;
+pub use file_manager::FileManager;
+pub use cache::{Cache, EvictionPolicy};
+pub use disk_cache::DiskCache;
 pub use sampler::Sampler;
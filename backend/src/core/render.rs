@@ -0,0 +1,233 @@
+// 把缩略图网格渲染为 PNG 或 WebP 图像
+// 不依赖图像库：PNG 的像素数据使用不压缩的 deflate 存储块，缩略图最大 4096×4096，体积可以接受；
+// 需要更小的体积时用 WebP 无损编码（见 webp 模块）
+use super::webp;
+
+// 输出的图像格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImageFormat {
+    Png,
+    Webp,
+}
+
+impl ImageFormat {
+    pub const ALL: [Self; 2] = [Self::Png, Self::Webp];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "png" => Some(Self::Png),
+            "webp" => Some(Self::Webp),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Webp => "webp",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Webp => "image/webp",
+        }
+    }
+
+    pub fn encode(self, cells: &[u8], width: usize, height: usize, colormap: Colormap) -> Vec<u8> {
+        match self {
+            Self::Png => encode_png(cells, width, height, colormap),
+            Self::Webp => encode_webp(cells, width, height, colormap),
+        }
+    }
+}
+
+// 颜色映射
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Colormap {
+    Grayscale,
+    Viridis,
+    // 按字节分类着色，输入为分类编号而不是字节均值
+    ByteClass,
+}
+
+impl Colormap {
+    pub const ALL: [Self; 3] = [Self::Grayscale, Self::Viridis, Self::ByteClass];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "grayscale" => Some(Self::Grayscale),
+            "viridis" => Some(Self::Viridis),
+            "byte-class" => Some(Self::ByteClass),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Grayscale => "grayscale",
+            Self::Viridis => "viridis",
+            Self::ByteClass => "byte-class",
+        }
+    }
+
+    fn color(self, value: u8) -> [u8; 3] {
+        match self {
+            Self::Grayscale => [value; 3],
+            Self::Viridis => viridis(value),
+            Self::ByteClass => match value {
+                0 => [0, 0, 0],
+                1 => [55, 126, 184],
+                2 => [77, 175, 74],
+                _ => [228, 26, 28],
+            },
+        }
+    }
+}
+
+// viridis 的 9 个等距色标，中间线性插值
+const VIRIDIS_STOPS: [[u8; 3]; 9] = [
+    [68, 1, 84],
+    [71, 44, 122],
+    [59, 81, 139],
+    [44, 113, 142],
+    [33, 144, 141],
+    [39, 173, 129],
+    [92, 200, 99],
+    [170, 220, 50],
+    [253, 231, 37],
+];
+
+fn viridis(value: u8) -> [u8; 3] {
+    let position = value as usize * (VIRIDIS_STOPS.len() - 1);
+    let index = position / 255;
+    let frac = (position % 255) as u32;
+    let low = VIRIDIS_STOPS[index];
+    let high = VIRIDIS_STOPS[(index + 1).min(VIRIDIS_STOPS.len() - 1)];
+
+    let mut color = [0u8; 3];
+    for (channel, (&low, &high)) in color.iter_mut().zip(low.iter().zip(high.iter())) {
+        *channel = ((low as u32 * (255 - frac) + high as u32 * frac) / 255) as u8;
+    }
+    color
+}
+
+// 把按行优先排列的 width×height 网格编码为 RGB PNG
+pub fn encode_png(cells: &[u8], width: usize, height: usize, colormap: Colormap) -> Vec<u8> {
+    // 每行以滤波类型字节 0（不滤波）开头
+    let mut raw = Vec::with_capacity(height * (width * 3 + 1));
+    for row in cells.chunks(width).take(height) {
+        raw.push(0);
+        for &value in row {
+            raw.extend_from_slice(&colormap.color(value));
+        }
+    }
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+    // 位深 8，颜色类型 2（RGB），默认压缩、滤波方式，不隔行
+    ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    write_chunk(&mut png, b"IHDR", &ihdr);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+// 把按行优先排列的 width×height 网格编码为无损 WebP
+// 调色板只包含出现过的值，值的种类少时（如 byte-class 只有 4 种）多个像素打包进一个索引
+pub fn encode_webp(cells: &[u8], width: usize, height: usize, colormap: Colormap) -> Vec<u8> {
+    let cells = &cells[..width * height];
+    let mut used = [false; 256];
+    for &value in cells {
+        used[value as usize] = true;
+    }
+
+    let mut indices = [0u8; 256];
+    let mut palette = Vec::new();
+    for value in 0..=255u8 {
+        if used[value as usize] {
+            indices[value as usize] = palette.len() as u8;
+            palette.push(colormap.color(value));
+        }
+    }
+
+    let indexed: Vec<u8> = cells.iter().map(|&value| indices[value as usize]).collect();
+    webp::encode_indexed(&indexed, width, height, &palette)
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+// zlib 流：deflate 存储块，每块最多 65535 字节
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + data.len() / 65535 * 5 + 11);
+    out.extend_from_slice(&[0x78, 0x01]);
+
+    let mut blocks = data.chunks(65535).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+// CRC-32（IEEE）查找表，编译期生成
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut crc = n as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[n] = crc;
+        n += 1;
+    }
+    table
+};
+
+fn crc32(data: &[u8]) -> u32 {
+    let crc = data.iter().fold(!0u32, |crc, &byte| {
+        CRC_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    });
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    // 每 5552 字节取一次模，保证累加不溢出
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
@@ -1,15 +1,119 @@
+use crate::core::cache::CacheSized;
+use crate::core::file_manager::MappedFile;
 use crate::error::Result;
-use memmap2::Mmap;
+use bytes::Bytes;
 use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 
 pub trait Sampler: Send + Sync {
-    fn sample(&self, data: Arc<Mmap>, target_size: usize) -> Result<SampleResult>;
+    // cancel 置位后，并行提取在每块开始前检查并尽早返回 SamplingFailed("cancelled")，
+    // 请求被取消后不必等整个采样算完
+    fn sample_cancellable(
+        &self,
+        data: &[u8],
+        target_size: usize,
+        options: &SampleOptions,
+        cancel: &AtomicBool,
+    ) -> Result<SampleResult>;
+
+    // 只在 data[range] 内采样；默认实现切片后交给 sample_cancellable，
+    // 再把窗口偏移换算回整个文件的坐标
+    fn sample_range(
+        &self,
+        data: &[u8],
+        range: Range<usize>,
+        target_size: usize,
+        options: &SampleOptions,
+        cancel: &AtomicBool,
+    ) -> Result<SampleResult> {
+        let options = SampleOptions {
+            range_start: range.start,
+            ..options.clone()
+        };
+        let mut result =
+            self.sample_cancellable(&data[range.clone()], target_size, &options, cancel)?;
+
+        let metadata = &mut result.metadata;
+        metadata.original_size = data.len();
+        if let Some(offsets) = metadata.offsets.as_mut() {
+            for offset in offsets.iter_mut() {
+                *offset += range.start;
+            }
+        }
+        metadata.range_start = Some(range.start);
+        metadata.range_end = Some(range.end);
+
+        Ok(result)
+    }
+
+    // 数据不大于目标大小时采样结果是否就是数据本身；是时调用方直接引用文件映射，不调用采样器
+    fn passthrough_small(&self) -> bool {
+        true
+    }
+
+    // 相同的数据和参数是否总是得到相同的结果；不是时同一请求的两次响应不能共用 ETag
+    fn deterministic(&self) -> bool {
+        true
+    }
+}
+
+// 单次采样请求的可选参数，不适用的采样器直接忽略
+#[derive(Debug, Clone, Default)]
+pub struct SampleOptions {
+    // 相邻窗口的重叠比例（0.0–0.9），仅 uniform 和 golden 使用
+    pub overlap: f32,
+    // 窗口起点在文件中的字节偏移向下对齐到该值的整数倍，仅 uniform 和 golden 使用
+    pub alignment: Option<usize>,
+    // 传给 sample_cancellable 的数据在文件中的起始偏移，由 sample_range 填入，对齐按文件偏移计算；
+    // 由请求的采样范围决定，缓存键中已有范围，不参与比较和哈希
+    pub range_start: usize,
+    // 由文件 ID 导出的固定种子，仅 golden 使用；缓存键中已有文件 ID，磁盘文件名不必包含它
+    pub seed: u64,
+    // 每 element_size 字节（2、4 或 8）作为一个元素采样，结果中每个元素占一个字节；None 表示逐字节
+    pub element_size: Option<usize>,
+    // 网格的列数（2 的幂）和行数，仅 grid 使用；未指定行数时用满采样大小
+    pub row_width: Option<usize>,
+    pub rows: Option<usize>,
+}
+
+impl SampleOptions {
+    pub fn element_size(&self) -> usize {
+        self.element_size.unwrap_or(1)
+    }
+}
+
+// 按位比较，与 Hash 保持一致，可以作为缓存键的一部分
+impl PartialEq for SampleOptions {
+    fn eq(&self, other: &Self) -> bool {
+        self.overlap.to_bits() == other.overlap.to_bits()
+            && self.alignment == other.alignment
+            && self.seed == other.seed
+            && self.element_size == other.element_size
+            && self.row_width == other.row_width
+            && self.rows == other.rows
+    }
+}
+
+impl Eq for SampleOptions {}
+
+impl Hash for SampleOptions {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.overlap.to_bits().hash(state);
+        self.alignment.hash(state);
+        self.seed.hash(state);
+        self.element_size.hash(state);
+        self.row_width.hash(state);
+        self.rows.hash(state);
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SampleResult {
-    pub data: Vec<u8>,
+    // 克隆只增加引用计数；直接返回原文件时引用共享的内存映射，不做拷贝
+    pub data: Bytes,
     pub metadata: SampleMetadata,
 }
 
@@ -18,4 +122,135 @@ pub struct SampleMetadata {
     pub original_size: usize,
     pub sample_size: usize,
     pub method: String,
+    // 等间隔采样的步长；uniform 窗口重叠时为相邻窗口起点的间距
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stride: Option<usize>,
+    // 首尾采样中尾部数据在结果中的起始位置
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub split: Option<usize>,
+    // 均匀采样的窗口大小和各窗口在原文件中的起始偏移
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window_size: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offsets: Option<Vec<usize>>,
+    // 窗口数超过上限、窗口被放大时记录放大前的窗口大小
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requested_window_size: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overlap: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alignment: Option<usize>,
+    // 按元素采样时每个元素的字节数；此时 window_size、stride、split 以元素（即结果中的字节）为单位，offsets 仍是字节偏移
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub element_size: Option<usize>,
+    // 网格采样的列数和单元格覆盖的最大字节数，行数为 sample_size / row_width
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub row_width: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes_per_cell: Option<usize>,
+    // 只对文件的一段采样时记录该段的范围 [range_start, range_end)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range_start: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range_end: Option<usize>,
+    // 数据的编码，原始字节时省略；"class" 表示每个字节已替换为类别 ID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    // 请求 class_summary 时，采样数据每 class_window 字节出现最多的字节分类
+    // uniform 采样按采样窗口汇总，其它方法按固定字节数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class_window: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "serde_bytes")]
+    pub class_summary: Option<Vec<u8>>,
+    // 采样耗时（毫秒）；命中缓存时为 0 且 cached 为 true
+    #[serde(default)]
+    pub compute_ms: u64,
+    #[serde(default)]
+    pub cached: bool,
+}
+
+impl SampleMetadata {
+    pub fn new(original_size: usize, sample_size: usize, method: &str) -> Self {
+        Self {
+            original_size,
+            sample_size,
+            method: method.to_string(),
+            stride: None,
+            split: None,
+            window_size: None,
+            offsets: None,
+            requested_window_size: None,
+            overlap: None,
+            alignment: None,
+            element_size: None,
+            row_width: None,
+            bytes_per_cell: None,
+            range_start: None,
+            range_end: None,
+            encoding: None,
+            class_window: None,
+            class_summary: None,
+            compute_ms: 0,
+            cached: false,
+        }
+    }
+}
+
+impl SampleResult {
+    // 文件不大于目标大小时直接返回全部数据
+    pub fn full(data: &[u8]) -> Self {
+        Self {
+            data: Bytes::copy_from_slice(data),
+            metadata: SampleMetadata::new(data.len(), data.len(), "full"),
+        }
+    }
+
+    // 采样范围不大于目标大小时直接引用映射中的 mmap[range]，省去整段拷贝
+    // 结果持有映射的引用，文件在发送过程中被删除也可以安全读取，见 FileManager::mmap_file
+    pub fn mapped(mmap: Arc<MappedFile>, range: Range<usize>) -> Self {
+        let file_size = mmap.len();
+        let mut metadata = SampleMetadata::new(file_size, range.len(), "full");
+        if range != (0..file_size) {
+            metadata.range_start = Some(range.start);
+            metadata.range_end = Some(range.end);
+        }
+
+        Self {
+            data: Bytes::from_owner(SharedMmap(mmap)).slice(range),
+            metadata,
+        }
+    }
+}
+
+// 让 Bytes 持有共享映射的引用
+struct SharedMmap(Arc<MappedFile>);
+
+impl AsRef<[u8]> for SharedMmap {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+// 缓存完整的采样结果，元数据请求也能命中缓存
+impl CacheSized for SampleResult {
+    fn byte_size(&self) -> usize {
+        let offsets_size = self
+            .metadata
+            .offsets
+            .as_ref()
+            .map_or(0, |offsets| offsets.len() * std::mem::size_of::<usize>());
+        self.data.len() + offsets_size
+    }
+
+    fn to_disk_bytes(&self) -> Option<Vec<u8>> {
+        rmp_serde::to_vec_named(self).ok()
+    }
+
+    fn from_disk_bytes(bytes: &[u8]) -> Option<Self> {
+        rmp_serde::from_slice(bytes).ok()
+    }
+
+    fn source_len(&self) -> Option<usize> {
+        Some(self.metadata.original_size)
+    }
 }
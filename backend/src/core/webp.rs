@@ -0,0 +1,697 @@
+// WebP 无损（VP8L）编码，只实现渲染缩略图需要的部分：
+// 颜色索引变换 + 规范哈夫曼编码 + 简单的 LZ77（重复左侧像素或上一行），不使用颜色缓存和多组前缀码
+// 缩略图的颜色来自最多 256 项的调色板，颜色索引后每个像素只剩一个索引值，大片同色区域压缩效果明显
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+// 哈夫曼码长上限：普通前缀码 15，码长码 7
+const MAX_CODE_LENGTH: usize = 15;
+const MAX_CODE_LENGTH_CODE_LENGTH: usize = 7;
+
+// 码长码的码长按该顺序写入
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    17, 18, 0, 1, 2, 3, 4, 5, 16, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+
+// 绿色通道的字母表包含 24 个长度前缀（不使用颜色缓存），距离字母表有 40 个前缀
+const GREEN_ALPHABET: usize = 256 + 24;
+const DISTANCE_ALPHABET: usize = 40;
+
+// 后向引用的长度范围，太短的重复直接写字面量
+const MIN_COPY_LENGTH: usize = 3;
+const MAX_COPY_LENGTH: usize = 4096;
+
+// 距离映射表中的前两项：1 为正上方的像素，2 为左侧的像素
+const DIST_CODE_UP: usize = 1;
+const DIST_CODE_LEFT: usize = 2;
+
+// 颜色索引变换的类型编号
+const COLOR_INDEXING_TRANSFORM: u32 = 3;
+
+// 把按行优先排列的调色板索引编码为 WebP 图像，palette 为 1–256 项 RGB 颜色
+// 宽高不能超过 16384
+pub fn encode_indexed(indices: &[u8], width: usize, height: usize, palette: &[[u8; 3]]) -> Vec<u8> {
+    let mut writer = BitWriter::default();
+    writer.write(0x2f, 8);
+    writer.write(width as u32 - 1, 14);
+    writer.write(height as u32 - 1, 14);
+    // 不使用透明通道，版本号 0
+    writer.write(0, 1);
+    writer.write(0, 3);
+
+    // 调色板按与前一项的差值存储
+    writer.write(1, 1);
+    writer.write(COLOR_INDEXING_TRANSFORM, 2);
+    writer.write(palette.len() as u32 - 1, 8);
+    let mut previous = [0u8; 4];
+    let table: Vec<u32> = palette
+        .iter()
+        .map(|&[r, g, b]| {
+            let color = [0xff, r, g, b];
+            let delta: [u8; 4] = std::array::from_fn(|i| color[i].wrapping_sub(previous[i]));
+            previous = color;
+            u32::from_be_bytes(delta)
+        })
+        .collect();
+    write_image(&mut writer, &table, palette.len(), false);
+    writer.write(0, 1);
+
+    // 调色板较小时多个索引打包进一个像素的绿色通道，先放的像素在低位
+    let width_bits = match palette.len() {
+        0..=2 => 3,
+        3..=4 => 2,
+        5..=16 => 1,
+        _ => 0,
+    };
+    let bits_per_index = 8 >> width_bits;
+    let packed_width = width.div_ceil(1 << width_bits);
+    let mut packed = vec![0u32; packed_width * height];
+    for (y, row) in indices.chunks(width).take(height).enumerate() {
+        for (x, &index) in row.iter().enumerate() {
+            let shift = 8 + bits_per_index * (x & ((1 << width_bits) - 1));
+            packed[y * packed_width + (x >> width_bits)] |= (index as u32) << shift;
+        }
+    }
+    write_image(&mut writer, &packed, packed_width, true);
+
+    let data = writer.finish();
+    let padding = data.len() % 2;
+    let mut webp = Vec::with_capacity(20 + data.len() + padding);
+    webp.extend_from_slice(b"RIFF");
+    webp.extend_from_slice(&((12 + data.len() + padding) as u32).to_le_bytes());
+    webp.extend_from_slice(b"WEBP");
+    webp.extend_from_slice(b"VP8L");
+    webp.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    webp.extend_from_slice(&data);
+    webp.resize(webp.len() + padding, 0);
+    webp
+}
+
+// 按 LSB 优先写入比特流
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    pending: u64,
+    pending_bits: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, bits: u32) {
+        self.pending |= (value as u64) << self.pending_bits;
+        self.pending_bits += bits;
+        while self.pending_bits >= 8 {
+            self.bytes.push(self.pending as u8);
+            self.pending >>= 8;
+            self.pending_bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.pending_bits > 0 {
+            self.bytes.push(self.pending as u8);
+        }
+        self.bytes
+    }
+}
+
+enum Token {
+    Literal(u32),
+    Copy { length: usize, dist_code: usize },
+}
+
+// 写入一幅 ARGB 图像：主图像之外（如调色板）没有多组前缀码的标志位
+fn write_image(writer: &mut BitWriter, pixels: &[u32], width: usize, is_main: bool) {
+    // 不使用颜色缓存
+    writer.write(0, 1);
+    if is_main {
+        writer.write(0, 1);
+    }
+
+    let tokens = backward_refs(pixels, width);
+    let mut histograms = [
+        vec![0u32; GREEN_ALPHABET],
+        vec![0u32; 256],
+        vec![0u32; 256],
+        vec![0u32; 256],
+        vec![0u32; DISTANCE_ALPHABET],
+    ];
+    for token in &tokens {
+        match *token {
+            Token::Literal(argb) => {
+                let [alpha, red, green, blue] = argb.to_be_bytes();
+                histograms[0][green as usize] += 1;
+                histograms[1][red as usize] += 1;
+                histograms[2][blue as usize] += 1;
+                histograms[3][alpha as usize] += 1;
+            }
+            Token::Copy { length, dist_code } => {
+                histograms[0][256 + prefix_encode(length).0] += 1;
+                histograms[4][prefix_encode(dist_code).0] += 1;
+            }
+        }
+    }
+
+    let [green, red, blue, alpha, distance] = histograms
+        .map(|histogram| write_prefix_code(writer, &code_lengths(&histogram, MAX_CODE_LENGTH)));
+    for token in &tokens {
+        match *token {
+            Token::Literal(argb) => {
+                let [a, r, g, b] = argb.to_be_bytes();
+                green.write_symbol(writer, g as usize);
+                red.write_symbol(writer, r as usize);
+                blue.write_symbol(writer, b as usize);
+                alpha.write_symbol(writer, a as usize);
+            }
+            Token::Copy { length, dist_code } => {
+                let (prefix, extra_bits, extra) = prefix_encode(length);
+                green.write_symbol(writer, 256 + prefix);
+                writer.write(extra, extra_bits);
+                let (prefix, extra_bits, extra) = prefix_encode(dist_code);
+                distance.write_symbol(writer, prefix);
+                writer.write(extra, extra_bits);
+            }
+        }
+    }
+}
+
+// 贪心匹配：每个位置取重复左侧像素和重复上一行中较长的一种
+// 两种匹配的长度都不超过选中的长度，整体仍是线性时间
+fn backward_refs(pixels: &[u32], width: usize) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < pixels.len() {
+        let limit = (pixels.len() - pos).min(MAX_COPY_LENGTH);
+        let run = |distance: usize| {
+            if pos < distance {
+                return 0;
+            }
+            (0..limit)
+                .take_while(|&i| pixels[pos + i] == pixels[pos + i - distance])
+                .count()
+        };
+
+        let (left, up) = (run(1), run(width));
+        let (length, dist_code) = if up > left {
+            (up, DIST_CODE_UP)
+        } else {
+            (left, DIST_CODE_LEFT)
+        };
+        if length >= MIN_COPY_LENGTH {
+            tokens.push(Token::Copy { length, dist_code });
+            pos += length;
+        } else {
+            tokens.push(Token::Literal(pixels[pos]));
+            pos += 1;
+        }
+    }
+    tokens
+}
+
+// 长度和距离码的前缀编码：返回 (前缀符号, 额外比特数, 额外比特的值)
+fn prefix_encode(value: usize) -> (usize, u32, u32) {
+    let value = value - 1;
+    if value < 4 {
+        return (value, 0, 0);
+    }
+    let highest = usize::BITS - 1 - value.leading_zeros();
+    let second = (value >> (highest - 1)) & 1;
+    let extra_bits = highest - 1;
+    (
+        2 * highest as usize + second,
+        extra_bits,
+        (value & ((1 << extra_bits) - 1)) as u32,
+    )
+}
+
+// 由频数构造不超过 limit 的哈夫曼码长，未出现的符号码长为 0
+// 超过上限时把过小的频数抬高后重建，直到树足够浅；频数全部相等时树是平衡的，一定能结束
+fn code_lengths(histogram: &[u32], limit: usize) -> Vec<u8> {
+    let mut lengths = vec![0u8; histogram.len()];
+    let used: Vec<usize> = (0..histogram.len()).filter(|&s| histogram[s] > 0).collect();
+    if used.len() == 1 {
+        lengths[used[0]] = 1;
+    }
+    if used.len() < 2 {
+        return lengths;
+    }
+
+    let mut min_count = 1u64;
+    loop {
+        // 前 used.len() 个节点是叶子，合并出的节点编号总大于其子节点
+        let mut parents = vec![usize::MAX; used.len()];
+        let mut heap: BinaryHeap<Reverse<(u64, usize)>> = used
+            .iter()
+            .enumerate()
+            .map(|(node, &symbol)| Reverse(((histogram[symbol] as u64).max(min_count), node)))
+            .collect();
+        while heap.len() > 1 {
+            let Reverse((first, a)) = heap.pop().unwrap();
+            let Reverse((second, b)) = heap.pop().unwrap();
+            let node = parents.len();
+            parents.push(usize::MAX);
+            parents[a] = node;
+            parents[b] = node;
+            heap.push(Reverse((first + second, node)));
+        }
+
+        let mut depths = vec![0usize; parents.len()];
+        for node in (0..parents.len()).rev() {
+            if parents[node] != usize::MAX {
+                depths[node] = depths[parents[node]] + 1;
+            }
+        }
+        if depths[..used.len()].iter().all(|&depth| depth <= limit) {
+            for (node, &symbol) in used.iter().enumerate() {
+                lengths[symbol] = depths[node] as u8;
+            }
+            return lengths;
+        }
+        min_count *= 2;
+    }
+}
+
+// 可写入的前缀码：codes 已按比特流顺序反转
+// 只有一个符号时解码器不读取任何比特，写入长度为 0
+struct PrefixCode {
+    bits: Vec<u8>,
+    codes: Vec<u16>,
+}
+
+impl PrefixCode {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; MAX_CODE_LENGTH + 1];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // 规范哈夫曼编码：码长短的在前，码长相同时按符号顺序递增
+        let mut next = [0u16; MAX_CODE_LENGTH + 1];
+        let mut code = 0u16;
+        for length in 1..=MAX_CODE_LENGTH {
+            code = (code + counts[length - 1]) << 1;
+            next[length] = code;
+        }
+        let codes = lengths
+            .iter()
+            .map(|&length| {
+                if length == 0 {
+                    return 0;
+                }
+                let code = next[length as usize];
+                next[length as usize] += 1;
+                code.reverse_bits() >> (16 - length)
+            })
+            .collect();
+
+        let single = lengths.iter().filter(|&&length| length > 0).count() == 1;
+        let bits = if single {
+            vec![0; lengths.len()]
+        } else {
+            lengths.to_vec()
+        };
+        Self { bits, codes }
+    }
+
+    fn write_symbol(&self, writer: &mut BitWriter, symbol: usize) {
+        writer.write(self.codes[symbol] as u32, self.bits[symbol] as u32);
+    }
+}
+
+// 写入前缀码的码长：不超过两个 8 位以内的符号时用简单编码，否则先写码长码再写游程压缩后的码长
+fn write_prefix_code(writer: &mut BitWriter, lengths: &[u8]) -> PrefixCode {
+    let used: Vec<usize> = (0..lengths.len()).filter(|&s| lengths[s] > 0).collect();
+    if used.len() <= 2 && used.iter().all(|&symbol| symbol < 256) {
+        // 没有用到的码（如从不出现后向引用时的距离码）写成只有符号 0
+        let first = used.first().copied().unwrap_or(0);
+        writer.write(1, 1);
+        writer.write(used.len().max(1) as u32 - 1, 1);
+        if first < 2 {
+            writer.write(0, 1);
+            writer.write(first as u32, 1);
+        } else {
+            writer.write(1, 1);
+            writer.write(first as u32, 8);
+        }
+        if let Some(&second) = used.get(1) {
+            writer.write(second as u32, 8);
+        }
+        return PrefixCode::new(lengths);
+    }
+
+    writer.write(0, 1);
+    let tokens = code_length_tokens(lengths);
+    let mut histogram = [0u32; 19];
+    for &(symbol, _) in &tokens {
+        histogram[symbol as usize] += 1;
+    }
+    let code_length_lengths = code_lengths(&histogram, MAX_CODE_LENGTH_CODE_LENGTH);
+    let count = (4..=CODE_LENGTH_ORDER.len())
+        .rev()
+        .find(|&count| code_length_lengths[CODE_LENGTH_ORDER[count - 1]] > 0)
+        .unwrap_or(4);
+    writer.write(count as u32 - 4, 4);
+    for &symbol in &CODE_LENGTH_ORDER[..count] {
+        writer.write(code_length_lengths[symbol] as u32, 3);
+    }
+    // 码长写满整个字母表，不另外给出 max_symbol
+    writer.write(0, 1);
+
+    let code_length_code = PrefixCode::new(&code_length_lengths);
+    for (symbol, extra) in tokens {
+        code_length_code.write_symbol(writer, symbol as usize);
+        match symbol {
+            16 => writer.write(extra, 2),
+            17 => writer.write(extra, 3),
+            18 => writer.write(extra, 7),
+            _ => {}
+        }
+    }
+    PrefixCode::new(lengths)
+}
+
+// 码长的游程压缩：16 重复前一个非零码长 3–6 次，17 写 3–10 个 0，18 写 11–138 个 0
+// 返回 (码长码符号, 额外比特的值)
+fn code_length_tokens(lengths: &[u8]) -> Vec<(u8, u32)> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    while start < lengths.len() {
+        let value = lengths[start];
+        let run = lengths[start..]
+            .iter()
+            .take_while(|&&length| length == value)
+            .count();
+        let mut left = run;
+        if value == 0 {
+            while left >= 11 {
+                let repeat = left.min(138);
+                tokens.push((18, (repeat - 11) as u32));
+                left -= repeat;
+            }
+            if left >= 3 {
+                tokens.push((17, (left - 3) as u32));
+                left = 0;
+            }
+        } else {
+            tokens.push((value, 0));
+            left -= 1;
+            while left >= 3 {
+                let repeat = left.min(6);
+                tokens.push((16, (repeat - 3) as u32));
+                left -= repeat;
+            }
+        }
+        tokens.extend(std::iter::repeat_n((value, 0), left));
+        start += run;
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 按 VP8L 规范解码编码器用到的子集（颜色索引变换、普通和简单前缀码、后向引用），
+    // 不支持的特性直接 panic；返回宽、高和按行优先排列的 ARGB 像素
+    fn decode(webp: &[u8]) -> (usize, usize, Vec<u32>) {
+        assert_eq!(&webp[0..4], b"RIFF");
+        assert_eq!(webp.len() % 2, 0);
+        assert_eq!(u32_le(&webp[4..8]) as usize, webp.len() - 8);
+        assert_eq!(&webp[8..16], b"WEBPVP8L");
+        let size = u32_le(&webp[16..20]) as usize;
+        assert!(size == webp.len() - 20 || size == webp.len() - 21);
+
+        let mut reader = BitReader {
+            data: &webp[20..20 + size],
+            pos: 0,
+        };
+        assert_eq!(reader.read(8), 0x2f);
+        let width = reader.read(14) as usize + 1;
+        let height = reader.read(14) as usize + 1;
+        reader.read(1);
+        assert_eq!(reader.read(3), 0, "version");
+
+        let mut palette = None;
+        while reader.read(1) == 1 {
+            assert_eq!(reader.read(2), COLOR_INDEXING_TRANSFORM);
+            assert!(palette.is_none(), "transform used twice");
+            let len = reader.read(8) as usize + 1;
+            let mut colors = decode_image(&mut reader, len, 1, false);
+            for i in 1..len {
+                let [a, r, g, b] = colors[i].to_be_bytes();
+                let [pa, pr, pg, pb] = colors[i - 1].to_be_bytes();
+                colors[i] = u32::from_be_bytes([
+                    a.wrapping_add(pa),
+                    r.wrapping_add(pr),
+                    g.wrapping_add(pg),
+                    b.wrapping_add(pb),
+                ]);
+            }
+            palette = Some(colors);
+        }
+        let palette = palette.expect("no color indexing transform");
+
+        let width_bits = match palette.len() {
+            0..=2 => 3,
+            3..=4 => 2,
+            5..=16 => 1,
+            _ => 0,
+        };
+        let bits_per_index = 8 >> width_bits;
+        let packed_width = width.div_ceil(1 << width_bits);
+        let packed = decode_image(&mut reader, packed_width, height, true);
+        // 比特流在最后一个字节内结束
+        assert!(reader.pos.div_ceil(8) == reader.data.len());
+
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let green = (packed[y * packed_width + (x >> width_bits)] >> 8) & 0xff;
+                let shift = bits_per_index * (x & ((1 << width_bits) - 1));
+                let index = (green >> shift) & ((1 << bits_per_index) - 1);
+                pixels.push(palette[index as usize]);
+            }
+        }
+        (width, height, pixels)
+    }
+
+    fn u32_le(bytes: &[u8]) -> u32 {
+        u32::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    struct BitReader<'a> {
+        data: &'a [u8],
+        pos: usize,
+    }
+
+    impl BitReader<'_> {
+        fn read(&mut self, bits: usize) -> u32 {
+            let mut value = 0;
+            for i in 0..bits {
+                let bit = (self.data[self.pos / 8] >> (self.pos % 8)) & 1;
+                value |= (bit as u32) << i;
+                self.pos += 1;
+            }
+            value
+        }
+    }
+
+    // 规范哈夫曼码，要求码是完整的；只有一个符号时不读取比特
+    struct Code {
+        counts: [usize; MAX_CODE_LENGTH + 1],
+        symbols: Vec<usize>,
+    }
+
+    impl Code {
+        fn new(lengths: &[u8]) -> Self {
+            let mut counts = [0; MAX_CODE_LENGTH + 1];
+            for &length in lengths {
+                counts[length as usize] += 1;
+            }
+            counts[0] = 0;
+            let mut symbols: Vec<usize> = (0..lengths.len()).filter(|&s| lengths[s] > 0).collect();
+            symbols.sort_by_key(|&s| lengths[s]);
+            if symbols.len() > 1 {
+                let kraft: u32 = (1..=MAX_CODE_LENGTH)
+                    .map(|length| (counts[length] as u32) << (MAX_CODE_LENGTH - length))
+                    .sum();
+                assert_eq!(kraft, 1 << MAX_CODE_LENGTH, "incomplete prefix code");
+            }
+            Self { counts, symbols }
+        }
+
+        fn decode(&self, reader: &mut BitReader) -> usize {
+            if self.symbols.len() == 1 {
+                return self.symbols[0];
+            }
+            let (mut code, mut first, mut index) = (0, 0, 0);
+            for length in 1..=MAX_CODE_LENGTH {
+                code |= reader.read(1) as usize;
+                let count = self.counts[length];
+                if code < first + count {
+                    return self.symbols[index + code - first];
+                }
+                index += count;
+                first = (first + count) << 1;
+                code <<= 1;
+            }
+            panic!("invalid prefix code");
+        }
+    }
+
+    fn read_code(reader: &mut BitReader, alphabet: usize) -> Code {
+        let mut lengths = vec![0u8; alphabet];
+        if reader.read(1) == 1 {
+            let count = reader.read(1) + 1;
+            let first_bits = if reader.read(1) == 1 { 8 } else { 1 };
+            lengths[reader.read(first_bits) as usize] = 1;
+            if count == 2 {
+                lengths[reader.read(8) as usize] = 1;
+            }
+            return Code::new(&lengths);
+        }
+
+        let count = reader.read(4) as usize + 4;
+        let mut code_length_lengths = [0u8; 19];
+        for &symbol in &CODE_LENGTH_ORDER[..count] {
+            code_length_lengths[symbol] = reader.read(3) as u8;
+        }
+        let code_length_code = Code::new(&code_length_lengths);
+        let mut max_symbol = if reader.read(1) == 1 {
+            let bits = 2 + 2 * reader.read(3) as usize;
+            2 + reader.read(bits) as usize
+        } else {
+            alphabet
+        };
+
+        let (mut symbol, mut previous) = (0, 8);
+        while symbol < alphabet && max_symbol > 0 {
+            max_symbol -= 1;
+            let (value, repeat) = match code_length_code.decode(reader) {
+                length @ 0..=15 => (length as u8, 1),
+                16 => (previous, 3 + reader.read(2) as usize),
+                17 => (0, 3 + reader.read(3) as usize),
+                _ => (0, 11 + reader.read(7) as usize),
+            };
+            if value > 0 {
+                previous = value;
+            }
+            lengths[symbol..symbol + repeat].fill(value);
+            symbol += repeat;
+        }
+        Code::new(&lengths)
+    }
+
+    fn prefix_value(reader: &mut BitReader, symbol: usize) -> usize {
+        if symbol < 4 {
+            return symbol + 1;
+        }
+        let extra_bits = (symbol - 2) >> 1;
+        let offset = (2 + (symbol & 1)) << extra_bits;
+        offset + reader.read(extra_bits) as usize + 1
+    }
+
+    fn decode_image(
+        reader: &mut BitReader,
+        width: usize,
+        height: usize,
+        is_main: bool,
+    ) -> Vec<u32> {
+        assert_eq!(reader.read(1), 0, "color cache");
+        if is_main {
+            assert_eq!(reader.read(1), 0, "meta prefix codes");
+        }
+        let [green, red, blue, alpha, distance] =
+            [GREEN_ALPHABET, 256, 256, 256, DISTANCE_ALPHABET]
+                .map(|alphabet| read_code(reader, alphabet));
+
+        let mut pixels = Vec::with_capacity(width * height);
+        while pixels.len() < width * height {
+            let symbol = green.decode(reader);
+            if symbol < 256 {
+                let r = red.decode(reader) as u8;
+                let b = blue.decode(reader) as u8;
+                let a = alpha.decode(reader) as u8;
+                pixels.push(u32::from_be_bytes([a, r, symbol as u8, b]));
+                continue;
+            }
+
+            let length = prefix_value(reader, symbol - 256);
+            let dist_symbol = distance.decode(reader);
+            // 距离映射表的前两项为 (0, 1) 和 (1, 0)，超过 120 的码减去 120 即为距离
+            let distance = match prefix_value(reader, dist_symbol) {
+                1 => width,
+                2 => 1,
+                code if code > 120 => code - 120,
+                code => panic!("distance code {} is not used by the encoder", code),
+            };
+            assert!(distance <= pixels.len(), "copy before the start");
+            assert!(pixels.len() + length <= width * height, "copy past the end");
+            for _ in 0..length {
+                pixels.push(pixels[pixels.len() - distance]);
+            }
+        }
+        pixels
+    }
+
+    fn palette(len: usize) -> Vec<[u8; 3]> {
+        (0..len)
+            .map(|i| [i as u8, (i * 7) as u8, 255 - (i * 3) as u8])
+            .collect()
+    }
+
+    // 有大片重复（产生后向引用）也有噪声（产生字面量）的索引图像
+    fn indices(width: usize, height: usize, colors: usize) -> Vec<u8> {
+        let mut x: u32 = 12345;
+        (0..width * height)
+            .map(|i| {
+                x = x.wrapping_mul(1103515245).wrapping_add(12345);
+                let (col, row) = (i % width, i / width);
+                let value = match (row / 4 + col / 8) % 3 {
+                    0 => row,
+                    1 => (x >> 16) as usize,
+                    _ => col / 3,
+                };
+                (value % colors) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn encoded_images_decode_to_the_same_pixels() {
+        for colors in [1, 2, 3, 4, 5, 16, 17, 256] {
+            for (width, height) in [(1, 1), (7, 3), (13, 29), (64, 64), (300, 2)] {
+                let palette = palette(colors);
+                let indices = indices(width, height, colors);
+                let webp = encode_indexed(&indices, width, height, &palette);
+
+                let (decoded_width, decoded_height, pixels) = decode(&webp);
+                assert_eq!((decoded_width, decoded_height), (width, height));
+                let expected: Vec<u32> = indices
+                    .iter()
+                    .map(|&i| {
+                        let [r, g, b] = palette[i as usize];
+                        u32::from_be_bytes([0xff, r, g, b])
+                    })
+                    .collect();
+                assert!(
+                    pixels == expected,
+                    "{} colors, {}x{}",
+                    colors,
+                    width,
+                    height
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn uniform_image_uses_long_copies() {
+        let indices = vec![0u8; 512 * 512];
+        let webp = encode_indexed(&indices, 512, 512, &[[1, 2, 3]]);
+        assert!(webp.len() < 256, "{} bytes", webp.len());
+
+        let (_, _, pixels) = decode(&webp);
+        assert!(pixels.iter().all(|&pixel| pixel == 0xff01_0203));
+    }
+}
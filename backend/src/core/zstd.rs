@@ -0,0 +1,1031 @@
+// zstd（RFC 8878）解码：原始、RLE 和压缩块，Huffman 编码的字面量和 FSE 编码的序列，
+// 可选的 XXH64 内容校验和；多个帧和可跳过帧首尾相接时依次处理
+// 不支持字典；声明的窗口超过 MAX_WINDOW_SIZE 的帧直接拒绝，避免压缩数据中的窗口声明耗尽内存
+use super::decompress::{invalid_data, Input, OutputWindow};
+use std::io::{self, Read};
+
+const FRAME_MAGIC: u32 = 0xfd2f_b528;
+// 可跳过帧的魔数为 0x184D2A50–0x184D2A5F
+const SKIPPABLE_MAGIC: u32 = 0x184d_2a50;
+const MAX_WINDOW_SIZE: u64 = 128 * 1024 * 1024;
+const MAX_BLOCK_SIZE: usize = 128 * 1024;
+
+pub(super) fn decompress<R: Read>(
+    input: &mut Input<R>,
+    limit: u64,
+    sink: &mut dyn FnMut(&[u8]) -> io::Result<()>,
+) -> io::Result<u64> {
+    let mut total = 0;
+    loop {
+        let magic = input.le(4)? as u32;
+        if magic == FRAME_MAGIC {
+            total += frame(input, limit - total, sink)?;
+        } else if magic & 0xffff_fff0 == SKIPPABLE_MAGIC {
+            let size = input.le(4)?;
+            input.skip(size)?;
+        } else {
+            return Err(invalid_data("not zstd data"));
+        }
+
+        if input.at_end()? {
+            return Ok(total);
+        }
+    }
+}
+
+fn frame<R: Read>(
+    input: &mut Input<R>,
+    limit: u64,
+    sink: &mut dyn FnMut(&[u8]) -> io::Result<()>,
+) -> io::Result<u64> {
+    let descriptor = input.byte()?;
+    let single_segment = descriptor & 0x20 != 0;
+    let has_checksum = descriptor & 0x04 != 0;
+    if descriptor & 0x08 != 0 {
+        return Err(invalid_data("reserved zstd frame header bit is set"));
+    }
+
+    let window_descriptor = if single_segment {
+        None
+    } else {
+        Some(input.byte()?)
+    };
+    let dictionary_id = input.le([0, 1, 2, 4][(descriptor & 0x03) as usize])?;
+    if dictionary_id != 0 {
+        return Err(invalid_data("zstd dictionaries are not supported"));
+    }
+    let content_size = match descriptor >> 6 {
+        0 if single_segment => Some(input.le(1)?),
+        0 => None,
+        1 => Some(input.le(2)? + 256),
+        2 => Some(input.le(4)?),
+        _ => Some(input.le(8)?),
+    };
+
+    // 单段帧的窗口即整个内容
+    let window_size = match window_descriptor {
+        Some(byte) => {
+            let base = 1u64 << (10 + (byte >> 3));
+            base + (base / 8) * (byte & 0x07) as u64
+        }
+        None => content_size.unwrap_or(0),
+    };
+    if window_size > MAX_WINDOW_SIZE {
+        return Err(invalid_data(format!(
+            "zstd window of {} bytes exceeds the {} byte limit",
+            window_size, MAX_WINDOW_SIZE
+        )));
+    }
+
+    let mut hash = Xxh64::new();
+    let mut decoder = BlockDecoder::new();
+    let size = {
+        let mut checked = |data: &[u8]| {
+            if has_checksum {
+                hash.update(data);
+            }
+            sink(data)
+        };
+        let mut window = OutputWindow::new(window_size as usize, limit, &mut checked);
+        let mut block = Vec::with_capacity(MAX_BLOCK_SIZE);
+        loop {
+            let header = input.le(3)? as usize;
+            let last = header & 1 != 0;
+            let size = header >> 3;
+            if size > MAX_BLOCK_SIZE {
+                return Err(invalid_data("zstd block exceeds the maximum block size"));
+            }
+
+            match (header >> 1) & 0x03 {
+                // 原始块
+                0 => {
+                    block.resize(size, 0);
+                    input.read_exact(&mut block)?;
+                    window.extend(&block)?;
+                }
+                // RLE 块：一个字节重复 size 次
+                1 => {
+                    let byte = input.byte()?;
+                    block.clear();
+                    block.resize(size, byte);
+                    window.extend(&block)?;
+                }
+                2 => {
+                    block.resize(size, 0);
+                    input.read_exact(&mut block)?;
+                    decoder.decode(&block, &mut window)?;
+                }
+                _ => return Err(invalid_data("reserved zstd block type")),
+            }
+
+            if last {
+                break;
+            }
+        }
+        window.finish()?
+    };
+
+    if content_size.is_some_and(|content_size| content_size != size) {
+        return Err(invalid_data(
+            "zstd content size mismatch, the data is corrupt",
+        ));
+    }
+    if has_checksum && input.le(4)? as u32 != hash.finish() as u32 {
+        return Err(invalid_data("zstd checksum mismatch, the data is corrupt"));
+    }
+    Ok(size)
+}
+
+// 同一帧内各块之间延续的状态：上一个 Huffman 表和 FSE 表（供重复模式使用）以及重复偏移
+struct BlockDecoder {
+    huffman: Option<HuffmanTable>,
+    literal_lengths: Option<FseTable>,
+    offsets: Option<FseTable>,
+    match_lengths: Option<FseTable>,
+    repeat_offsets: [usize; 3],
+    literals: Vec<u8>,
+}
+
+impl BlockDecoder {
+    fn new() -> Self {
+        Self {
+            huffman: None,
+            literal_lengths: None,
+            offsets: None,
+            match_lengths: None,
+            repeat_offsets: [1, 4, 8],
+            literals: Vec::with_capacity(MAX_BLOCK_SIZE),
+        }
+    }
+
+    fn decode(&mut self, block: &[u8], window: &mut OutputWindow) -> io::Result<()> {
+        let used = self.read_literals(block)?;
+        self.execute_sequences(&block[used..], window)
+    }
+
+    // 解出字面量段，返回它占用的字节数
+    fn read_literals(&mut self, block: &[u8]) -> io::Result<usize> {
+        let first = *block.first().ok_or_else(truncated)?;
+        let kind = first & 0x03;
+        let size_format = (first >> 2) & 0x03;
+
+        if kind < 2 {
+            // 原始或 RLE 字面量
+            let (header_len, size) = match size_format {
+                0 | 2 => (1, (first >> 3) as usize),
+                1 => (2, (le(block, 2)? >> 4) as usize),
+                _ => (3, (le(block, 3)? >> 4) as usize),
+            };
+            if size > MAX_BLOCK_SIZE {
+                return Err(invalid_data("zstd literals exceed the maximum block size"));
+            }
+            self.literals.clear();
+            if kind == 0 {
+                let data = slice(block, header_len, size)?;
+                self.literals.extend_from_slice(data);
+                return Ok(header_len + size);
+            }
+            let byte = *slice(block, header_len, 1)?.first().ok_or_else(truncated)?;
+            self.literals.resize(size, byte);
+            return Ok(header_len + 1);
+        }
+
+        // Huffman 编码的字面量，kind 为 3 时沿用上一个块的 Huffman 表
+        let (header_len, size_bits) = match size_format {
+            0 | 1 => (3, 10),
+            2 => (4, 14),
+            _ => (5, 18),
+        };
+        let header = le(block, header_len)?;
+        let mask = (1u64 << size_bits) - 1;
+        let regenerated = ((header >> 4) & mask) as usize;
+        let compressed = ((header >> (4 + size_bits)) & mask) as usize;
+        if regenerated > MAX_BLOCK_SIZE {
+            return Err(invalid_data("zstd literals exceed the maximum block size"));
+        }
+
+        let mut data = slice(block, header_len, compressed)?;
+        if kind == 2 {
+            let (table, used) = HuffmanTable::read(data)?;
+            self.huffman = Some(table);
+            data = &data[used..];
+        }
+        let table = self.huffman.as_ref().ok_or_else(|| {
+            invalid_data("zstd literals reuse a Huffman table that was never sent")
+        })?;
+
+        self.literals.clear();
+        if size_format == 0 {
+            table.decode_stream(data, regenerated, &mut self.literals)?;
+        } else {
+            // 4 个流：6 字节跳转表给出前 3 个流的长度，前 3 个流各解出 ceil(regenerated / 4) 个字节
+            let jump = slice(data, 0, 6)?;
+            let sizes = [
+                u16::from_le_bytes([jump[0], jump[1]]) as usize,
+                u16::from_le_bytes([jump[2], jump[3]]) as usize,
+                u16::from_le_bytes([jump[4], jump[5]]) as usize,
+            ];
+            let segment = regenerated.div_ceil(4);
+            let last_segment = regenerated
+                .checked_sub(3 * segment)
+                .ok_or_else(|| invalid_data("zstd literals are too short for 4 streams"))?;
+            let mut rest = &data[6..];
+            for size in sizes {
+                let stream = slice(rest, 0, size)?;
+                table.decode_stream(stream, segment, &mut self.literals)?;
+                rest = &rest[size..];
+            }
+            table.decode_stream(rest, last_segment, &mut self.literals)?;
+        }
+
+        Ok(header_len + compressed)
+    }
+
+    // 解出序列段并依次执行：复制字面量，再从窗口中复制匹配
+    fn execute_sequences(&mut self, data: &[u8], window: &mut OutputWindow) -> io::Result<()> {
+        let first = *data.first().ok_or_else(truncated)? as usize;
+        let (count, mut pos) = match first {
+            0..=127 => (first, 1),
+            128..=254 => (
+                ((first - 128) << 8) + *slice(data, 1, 1)?.first().unwrap() as usize,
+                2,
+            ),
+            _ => (le(&data[1..], 2)? as usize + 0x7f00, 3),
+        };
+
+        if count == 0 {
+            return window.extend(&self.literals);
+        }
+
+        let modes = *slice(data, pos, 1)?.first().unwrap();
+        pos += 1;
+        if modes & 0x03 != 0 {
+            return Err(invalid_data("reserved zstd sequence mode bits are set"));
+        }
+        pos += update_table(
+            &mut self.literal_lengths,
+            modes >> 6,
+            &data[pos..],
+            &LITERAL_LENGTH,
+        )?;
+        pos += update_table(
+            &mut self.offsets,
+            (modes >> 4) & 0x03,
+            &data[pos..],
+            &OFFSET,
+        )?;
+        pos += update_table(
+            &mut self.match_lengths,
+            (modes >> 2) & 0x03,
+            &data[pos..],
+            &MATCH_LENGTH,
+        )?;
+
+        // update_table 成功后三个表都已存在
+        let (Some(literal_lengths), Some(offsets), Some(match_lengths)) =
+            (&self.literal_lengths, &self.offsets, &self.match_lengths)
+        else {
+            unreachable!("sequence tables are set by update_table");
+        };
+
+        let mut bits = BackwardBits::new(&data[pos..])?;
+        let mut literal_state = bits.read(literal_lengths.log) as usize;
+        let mut offset_state = bits.read(offsets.log) as usize;
+        let mut match_state = bits.read(match_lengths.log) as usize;
+
+        let mut literal_pos = 0;
+        for i in 0..count {
+            let offset_code = offsets.entries[offset_state].symbol;
+            let match_code = match_lengths.entries[match_state].symbol as usize;
+            let literal_code = literal_lengths.entries[literal_state].symbol as usize;
+            if offset_code > 31 {
+                return Err(invalid_data("unsupported zstd offset code"));
+            }
+
+            // 额外位的读取顺序：偏移、匹配长度、字面量长度
+            let offset_value = (1usize << offset_code) + bits.read(offset_code) as usize;
+            let match_length = MATCH_LENGTH_BASE[match_code] as usize
+                + bits.read(MATCH_LENGTH_EXTRA[match_code]) as usize;
+            let literal_length = LITERAL_LENGTH_BASE[literal_code] as usize
+                + bits.read(LITERAL_LENGTH_EXTRA[literal_code]) as usize;
+
+            // 最后一个序列之后不再更新状态；更新顺序：字面量长度、匹配长度、偏移
+            if i + 1 < count {
+                literal_state = literal_lengths.update(literal_state, &mut bits);
+                match_state = match_lengths.update(match_state, &mut bits);
+                offset_state = offsets.update(offset_state, &mut bits);
+            }
+
+            let literals = self
+                .literals
+                .get(literal_pos..literal_pos + literal_length)
+                .ok_or_else(|| invalid_data("zstd sequence uses more literals than decoded"))?;
+            window.extend(literals)?;
+            literal_pos += literal_length;
+
+            let offset = resolve_offset(&mut self.repeat_offsets, offset_value, literal_length)?;
+            window.copy(offset, match_length)?;
+        }
+
+        if bits.remaining() != 0 {
+            return Err(invalid_data("zstd sequence bitstream is corrupt"));
+        }
+        window.extend(&self.literals[literal_pos..])
+    }
+}
+
+// 偏移值 1–3 表示重复最近使用过的偏移（字面量长度为 0 时整体后移一位），大于 3 时为新偏移加 3
+fn resolve_offset(
+    repeat_offsets: &mut [usize; 3],
+    offset_value: usize,
+    literal_length: usize,
+) -> io::Result<usize> {
+    let [first, second, third] = *repeat_offsets;
+    if offset_value > 3 {
+        let offset = offset_value - 3;
+        *repeat_offsets = [offset, first, second];
+        return Ok(offset);
+    }
+
+    let index = offset_value - 1 + (literal_length == 0) as usize;
+    let offset = match index {
+        0 => first,
+        1 => {
+            *repeat_offsets = [second, first, third];
+            second
+        }
+        2 => {
+            *repeat_offsets = [third, first, second];
+            third
+        }
+        _ => {
+            let offset = first
+                .checked_sub(1)
+                .filter(|&offset| offset > 0)
+                .ok_or_else(|| invalid_data("zstd repeat offset underflows"))?;
+            *repeat_offsets = [offset, first, second];
+            offset
+        }
+    };
+    Ok(offset)
+}
+
+fn truncated() -> io::Error {
+    invalid_data("zstd block is truncated")
+}
+
+// data[start..start + len]，越界时返回数据截断的错误
+fn slice(data: &[u8], start: usize, len: usize) -> io::Result<&[u8]> {
+    data.get(start..start + len).ok_or_else(truncated)
+}
+
+// data 开头 bytes 个字节的小端整数
+fn le(data: &[u8], bytes: usize) -> io::Result<u64> {
+    Ok(slice(data, 0, bytes)?
+        .iter()
+        .rev()
+        .fold(0, |value, &byte| value << 8 | byte as u64))
+}
+
+// 一类序列符号（字面量长度、偏移、匹配长度）的 FSE 参数
+struct SequenceCode {
+    default_distribution: &'static [i16],
+    default_log: u8,
+    max_log: u8,
+    max_symbol: usize,
+}
+
+const LITERAL_LENGTH: SequenceCode = SequenceCode {
+    default_distribution: &[
+        4, 3, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 3, 2, 1, 1, 1,
+        1, 1, -1, -1, -1, -1,
+    ],
+    default_log: 6,
+    max_log: 9,
+    max_symbol: 35,
+};
+
+const MATCH_LENGTH: SequenceCode = SequenceCode {
+    default_distribution: &[
+        1, 4, 3, 2, 2, 2, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1, -1, -1,
+    ],
+    default_log: 6,
+    max_log: 9,
+    max_symbol: 52,
+};
+
+const OFFSET: SequenceCode = SequenceCode {
+    default_distribution: &[
+        1, 1, 1, 1, 1, 1, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1,
+    ],
+    default_log: 5,
+    max_log: 8,
+    max_symbol: 31,
+};
+
+// 字面量长度码的基数和额外位数
+const LITERAL_LENGTH_BASE: [u32; 36] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 18, 20, 22, 24, 28, 32, 40, 48, 64,
+    128, 256, 512, 1024, 2048, 4096, 8192, 16384, 32768, 65536,
+];
+const LITERAL_LENGTH_EXTRA: [u8; 36] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 3, 3, 4, 6, 7, 8, 9, 10, 11,
+    12, 13, 14, 15, 16,
+];
+
+// 匹配长度码的基数和额外位数
+const MATCH_LENGTH_BASE: [u32; 53] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27,
+    28, 29, 30, 31, 32, 33, 34, 35, 37, 39, 41, 43, 47, 51, 59, 67, 83, 99, 131, 259, 515, 1027,
+    2051, 4099, 8195, 16387, 32771, 65539,
+];
+const MATCH_LENGTH_EXTRA: [u8; 53] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    1, 1, 1, 1, 2, 2, 3, 3, 4, 4, 5, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16,
+];
+
+// 按压缩模式更新一类序列符号的 FSE 表，返回表描述占用的字节数
+fn update_table(
+    table: &mut Option<FseTable>,
+    mode: u8,
+    data: &[u8],
+    code: &SequenceCode,
+) -> io::Result<usize> {
+    match mode {
+        // 预定义分布
+        0 => {
+            *table = Some(FseTable::new(code.default_distribution, code.default_log)?);
+            Ok(0)
+        }
+        // RLE：所有序列使用同一个符号
+        1 => {
+            let symbol = *data.first().ok_or_else(truncated)?;
+            if symbol as usize > code.max_symbol {
+                return Err(invalid_data("zstd RLE sequence symbol is out of range"));
+            }
+            *table = Some(FseTable::rle(symbol));
+            Ok(1)
+        }
+        2 => {
+            let (read, used) = FseTable::read(data, code.max_log, code.max_symbol)?;
+            *table = Some(read);
+            Ok(used)
+        }
+        // 重复上一个块的表
+        _ => {
+            if table.is_none() {
+                return Err(invalid_data(
+                    "zstd block repeats a sequence table that was never sent",
+                ));
+            }
+            Ok(0)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct FseEntry {
+    symbol: u8,
+    bits: u8,
+    baseline: u16,
+}
+
+// FSE 解码表：每个状态给出符号，以及转移到下一个状态需要读取的位数和基数
+struct FseTable {
+    log: u8,
+    entries: Vec<FseEntry>,
+}
+
+impl FseTable {
+    fn rle(symbol: u8) -> Self {
+        Self {
+            log: 0,
+            entries: vec![FseEntry {
+                symbol,
+                bits: 0,
+                baseline: 0,
+            }],
+        }
+    }
+
+    // 按归一化的符号概率构建解码表，概率 -1 表示“小于 1”，占用表尾的一个状态
+    fn new(distribution: &[i16], log: u8) -> io::Result<Self> {
+        let size = 1usize << log;
+        let mut entries = vec![FseEntry::default(); size];
+        let mut next = vec![0u32; distribution.len()];
+
+        let mut high = size as isize - 1;
+        for (symbol, &probability) in distribution.iter().enumerate() {
+            if probability == -1 {
+                if high < 0 {
+                    return Err(invalid_data("zstd FSE distribution is corrupt"));
+                }
+                entries[high as usize].symbol = symbol as u8;
+                high -= 1;
+                next[symbol] = 1;
+            } else {
+                next[symbol] = probability.max(0) as u32;
+            }
+        }
+
+        // 其余符号按固定步长分散到表中，跳过表尾已被占用的状态
+        let step = (size >> 1) + (size >> 3) + 3;
+        let mask = size - 1;
+        let mut position = 0;
+        for (symbol, &probability) in distribution.iter().enumerate() {
+            for _ in 0..probability.max(0) {
+                entries[position].symbol = symbol as u8;
+                loop {
+                    position = (position + step) & mask;
+                    if position as isize <= high {
+                        break;
+                    }
+                }
+            }
+        }
+        if position != 0 {
+            return Err(invalid_data("zstd FSE distribution is corrupt"));
+        }
+
+        for entry in entries.iter_mut() {
+            let state = &mut next[entry.symbol as usize];
+            let bits = log - (31 - state.leading_zeros()) as u8;
+            entry.bits = bits;
+            entry.baseline = ((*state << bits) as usize - size) as u16;
+            *state += 1;
+        }
+
+        Ok(Self { log, entries })
+    }
+
+    // 读取压缩的表描述（低位在前的位流），返回解码表和描述占用的字节数
+    fn read(data: &[u8], max_log: u8, max_symbol: usize) -> io::Result<(Self, usize)> {
+        let mut bits = ForwardBits::new(data);
+        let log = bits.read(4)? as u8 + 5;
+        if log > max_log {
+            return Err(invalid_data("zstd FSE table log is too large"));
+        }
+
+        let mut remaining = (1i32 << log) + 1;
+        let mut threshold = 1i32 << log;
+        let mut width = log + 1;
+        let mut distribution: Vec<i16> = Vec::new();
+        let mut previous_zero = false;
+        while remaining > 1 && distribution.len() <= max_symbol {
+            // 概率为 0 的符号之后是 2 位的重复次数，值为 3 时继续读下一组
+            if previous_zero {
+                loop {
+                    let repeat = bits.read(2)?;
+                    distribution.extend(std::iter::repeat_n(0, repeat as usize));
+                    if repeat != 3 {
+                        break;
+                    }
+                }
+                if distribution.len() > max_symbol {
+                    return Err(invalid_data("zstd FSE table has too many symbols"));
+                }
+            }
+
+            // 较小的值只用 width - 1 位
+            let max = (2 * threshold - 1) - remaining;
+            let low = bits.peek(width - 1) as i32;
+            let value = if low < max {
+                bits.consume(width - 1)?;
+                low
+            } else {
+                let value = bits.peek(width) as i32;
+                bits.consume(width)?;
+                if value >= threshold {
+                    value - max
+                } else {
+                    value
+                }
+            };
+            // 存储的值比概率大 1，0 表示概率 -1
+            let count = value - 1;
+
+            remaining -= count.abs();
+            distribution.push(count as i16);
+            previous_zero = count == 0;
+            while remaining < threshold {
+                width -= 1;
+                threshold >>= 1;
+            }
+        }
+        if remaining != 1 || distribution.len() > max_symbol + 1 {
+            return Err(invalid_data("zstd FSE table description is corrupt"));
+        }
+
+        Ok((Self::new(&distribution, log)?, bits.bytes_used()))
+    }
+
+    fn update(&self, state: usize, bits: &mut BackwardBits) -> usize {
+        let entry = self.entries[state];
+        entry.baseline as usize + bits.read(entry.bits) as usize
+    }
+}
+
+// 字面量的 Huffman 解码表，按接下来 max_bits 位（高位在前）查出符号和码长
+struct HuffmanTable {
+    entries: Vec<(u8, u8)>,
+    max_bits: u8,
+}
+
+impl HuffmanTable {
+    // 读取表描述（各符号的权重），返回解码表和描述占用的字节数
+    fn read(data: &[u8]) -> io::Result<(Self, usize)> {
+        let header = *data.first().ok_or_else(truncated)? as usize;
+        let (mut weights, used) = if header < 128 {
+            // FSE 压缩的权重，两个状态交替解码
+            let compressed = slice(data, 1, header)?;
+            (decode_weights(compressed)?, 1 + header)
+        } else {
+            // 直接存储的权重，每个 4 位，高半字节在前
+            let count = header - 127;
+            let packed = slice(data, 1, count.div_ceil(2))?;
+            let weights = (0..count)
+                .map(|i| {
+                    let byte = packed[i / 2];
+                    if i % 2 == 0 {
+                        byte >> 4
+                    } else {
+                        byte & 0x0f
+                    }
+                })
+                .collect();
+            (weights, 1 + packed.len())
+        };
+
+        // 最后一个符号的权重不存储，由其余权重补齐到 2 的幂
+        let mut sum = 0u32;
+        for &weight in &weights {
+            if weight > MAX_HUFFMAN_BITS {
+                return Err(invalid_data("zstd Huffman weight is too large"));
+            }
+            if weight > 0 {
+                sum += 1 << (weight - 1);
+            }
+        }
+        if sum == 0 || weights.len() > 255 {
+            return Err(invalid_data("zstd Huffman table is corrupt"));
+        }
+        let max_bits = (32 - sum.leading_zeros()) as u8;
+        let left = (1u32 << max_bits) - sum;
+        if max_bits > MAX_HUFFMAN_BITS || !left.is_power_of_two() {
+            return Err(invalid_data("zstd Huffman table is corrupt"));
+        }
+        weights.push(left.trailing_zeros() as u8 + 1);
+
+        // 权重小（码长）的符号排在前面，同一权重内按符号顺序，权重 w 的符号占 2^(w-1) 项
+        let mut entries = Vec::with_capacity(1 << max_bits);
+        for weight in 1..=max_bits {
+            for (symbol, _) in weights.iter().enumerate().filter(|(_, &w)| w == weight) {
+                let length = max_bits + 1 - weight;
+                entries.extend(std::iter::repeat_n(
+                    (symbol as u8, length),
+                    1 << (weight - 1),
+                ));
+            }
+        }
+
+        Ok((Self { entries, max_bits }, used))
+    }
+
+    // 解码一个流中的 count 个字面量，流必须恰好用完
+    fn decode_stream(&self, data: &[u8], count: usize, out: &mut Vec<u8>) -> io::Result<()> {
+        let mut bits = BackwardBits::new(data)?;
+        for _ in 0..count {
+            let (symbol, length) = self.entries[bits.peek(self.max_bits) as usize];
+            out.push(symbol);
+            bits.consume(length);
+        }
+        if bits.remaining() != 0 {
+            return Err(invalid_data("zstd Huffman stream is corrupt"));
+        }
+        Ok(())
+    }
+}
+
+const MAX_HUFFMAN_BITS: u8 = 11;
+
+// FSE 压缩的 Huffman 权重：读到位流起点之外时，另一个状态的符号是最后一个权重
+fn decode_weights(data: &[u8]) -> io::Result<Vec<u8>> {
+    let (table, used) = FseTable::read(data, 6, 255)?;
+    let mut bits = BackwardBits::new(&data[used..])?;
+    let mut states = [bits.read(table.log) as usize, bits.read(table.log) as usize];
+
+    let mut weights = Vec::new();
+    for current in (0..2).cycle() {
+        if weights.len() >= 255 {
+            return Err(invalid_data("zstd Huffman table has too many weights"));
+        }
+        weights.push(table.entries[states[current]].symbol);
+        states[current] = table.update(states[current], &mut bits);
+        if bits.overflowed() {
+            weights.push(table.entries[states[1 - current]].symbol);
+            break;
+        }
+    }
+    Ok(weights)
+}
+
+// 低位在前读取的位流（FSE 表描述）
+struct ForwardBits<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ForwardBits<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    // 接下来的 n 位（n <= 16），超出数据的部分补 0，由 consume 检查
+    fn peek(&self, n: u8) -> u32 {
+        let mut value = 0u32;
+        for i in 0..n as usize {
+            let bit = self.pos + i;
+            let byte = self.data.get(bit / 8).copied().unwrap_or(0);
+            value |= (((byte >> (bit % 8)) & 1) as u32) << i;
+        }
+        value
+    }
+
+    fn consume(&mut self, n: u8) -> io::Result<()> {
+        self.pos += n as usize;
+        if self.pos > self.data.len() * 8 {
+            return Err(truncated());
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, n: u8) -> io::Result<u32> {
+        let value = self.peek(n);
+        self.consume(n)?;
+        Ok(value)
+    }
+
+    fn bytes_used(&self) -> usize {
+        self.pos.div_ceil(8)
+    }
+}
+
+// 从末尾向前读取的位流（Huffman 流和序列）：最后一个字节的最高位 1 是结束标记，
+// 之后按从高到低的顺序读取；读到起点之外时按 0 补齐，由 overflowed 判断
+struct BackwardBits<'a> {
+    data: &'a [u8],
+    // 剩余的位数，读到起点之外时为负
+    pos: isize,
+}
+
+impl<'a> BackwardBits<'a> {
+    fn new(data: &'a [u8]) -> io::Result<Self> {
+        let last = *data.last().ok_or_else(truncated)?;
+        if last == 0 {
+            return Err(invalid_data("zstd bitstream has no end marker"));
+        }
+        let pos = (data.len() - 1) * 8 + (7 - last.leading_zeros() as usize);
+        Ok(Self {
+            data,
+            pos: pos as isize,
+        })
+    }
+
+    // 接下来的 n 位（n <= 32）
+    fn peek(&self, n: u8) -> u64 {
+        if n == 0 || self.pos <= 0 {
+            return 0;
+        }
+        let start = self.pos - n as isize;
+        if start >= 0 {
+            self.extract(start as usize, n)
+        } else {
+            self.extract(0, self.pos as u8) << (-start)
+        }
+    }
+
+    // 从第 start 位开始的 n 位，位按字节内从低到高、字节从前到后编号
+    fn extract(&self, start: usize, n: u8) -> u64 {
+        let first = start / 8;
+        let word = match self.data.get(first..first + 8) {
+            Some(bytes) => u64::from_le_bytes(bytes.try_into().unwrap()),
+            None => self.data[first..]
+                .iter()
+                .rev()
+                .fold(0, |word, &byte| word << 8 | byte as u64),
+        };
+        (word >> (start % 8)) & ((1u64 << n) - 1)
+    }
+
+    fn consume(&mut self, n: u8) {
+        self.pos -= n as isize;
+    }
+
+    fn read(&mut self, n: u8) -> u64 {
+        let value = self.peek(n);
+        self.consume(n);
+        value
+    }
+
+    fn remaining(&self) -> isize {
+        self.pos
+    }
+
+    fn overflowed(&self) -> bool {
+        self.pos < 0
+    }
+}
+
+// 帧内容校验和：XXH64（种子 0）的低 32 位
+struct Xxh64 {
+    acc: [u64; 4],
+    buf: [u8; 32],
+    buf_len: usize,
+    total: u64,
+}
+
+const PRIME1: u64 = 0x9e37_79b1_85eb_ca87;
+const PRIME2: u64 = 0xc2b2_ae3d_27d4_eb4f;
+const PRIME3: u64 = 0x1656_67b1_9e37_79f9;
+const PRIME4: u64 = 0x85eb_ca77_c2b2_ae63;
+const PRIME5: u64 = 0x27d4_eb2f_1656_67c5;
+
+impl Xxh64 {
+    fn new() -> Self {
+        Self {
+            acc: [
+                PRIME1.wrapping_add(PRIME2),
+                PRIME2,
+                0,
+                0u64.wrapping_sub(PRIME1),
+            ],
+            buf: [0; 32],
+            buf_len: 0,
+            total: 0,
+        }
+    }
+
+    fn round(acc: u64, lane: u64) -> u64 {
+        acc.wrapping_add(lane.wrapping_mul(PRIME2))
+            .rotate_left(31)
+            .wrapping_mul(PRIME1)
+    }
+
+    fn stripe(&mut self, stripe: &[u8]) {
+        for (acc, lane) in self.acc.iter_mut().zip(stripe.chunks_exact(8)) {
+            *acc = Self::round(*acc, u64::from_le_bytes(lane.try_into().unwrap()));
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        self.total += data.len() as u64;
+        if self.buf_len > 0 {
+            let n = data.len().min(32 - self.buf_len);
+            self.buf[self.buf_len..self.buf_len + n].copy_from_slice(&data[..n]);
+            self.buf_len += n;
+            data = &data[n..];
+            if self.buf_len < 32 {
+                return;
+            }
+            let buf = self.buf;
+            self.stripe(&buf);
+            self.buf_len = 0;
+        }
+
+        let mut stripes = data.chunks_exact(32);
+        for stripe in &mut stripes {
+            self.stripe(stripe);
+        }
+        let rest = stripes.remainder();
+        self.buf[..rest.len()].copy_from_slice(rest);
+        self.buf_len = rest.len();
+    }
+
+    fn finish(&self) -> u64 {
+        let mut hash = if self.total >= 32 {
+            let [a, b, c, d] = self.acc;
+            let mut hash = a
+                .rotate_left(1)
+                .wrapping_add(b.rotate_left(7))
+                .wrapping_add(c.rotate_left(12))
+                .wrapping_add(d.rotate_left(18));
+            for acc in self.acc {
+                hash = (hash ^ Self::round(0, acc))
+                    .wrapping_mul(PRIME1)
+                    .wrapping_add(PRIME4);
+            }
+            hash
+        } else {
+            PRIME5
+        };
+        hash = hash.wrapping_add(self.total);
+
+        let mut rest = &self.buf[..self.buf_len];
+        while rest.len() >= 8 {
+            let lane = u64::from_le_bytes(rest[..8].try_into().unwrap());
+            hash = (hash ^ Self::round(0, lane))
+                .rotate_left(27)
+                .wrapping_mul(PRIME1)
+                .wrapping_add(PRIME4);
+            rest = &rest[8..];
+        }
+        if rest.len() >= 4 {
+            let lane = u32::from_le_bytes(rest[..4].try_into().unwrap()) as u64;
+            hash = (hash ^ lane.wrapping_mul(PRIME1))
+                .rotate_left(23)
+                .wrapping_mul(PRIME2)
+                .wrapping_add(PRIME3);
+            rest = &rest[4..];
+        }
+        for &byte in rest {
+            hash = (hash ^ (byte as u64).wrapping_mul(PRIME5))
+                .rotate_left(11)
+                .wrapping_mul(PRIME1);
+        }
+
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(PRIME2);
+        hash ^= hash >> 29;
+        hash = hash.wrapping_mul(PRIME3);
+        hash ^ (hash >> 32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::decompress::tests::{decode, short, text, SHORT_ZST, TEXT_ZST};
+    use super::super::decompress::Compression;
+    use super::*;
+
+    fn skippable_frame(payload: &[u8]) -> Vec<u8> {
+        let mut frame = (SKIPPABLE_MAGIC + 5).to_le_bytes().to_vec();
+        frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    #[test]
+    fn skippable_frames_are_ignored() {
+        let data = [
+            skippable_frame(b"metadata"),
+            SHORT_ZST.to_vec(),
+            skippable_frame(&[]),
+            TEXT_ZST.to_vec(),
+        ]
+        .concat();
+        let expected = [short(), text()].concat();
+        assert_eq!(
+            decode(Compression::Zstd, &data, u64::MAX).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn frames_without_checksum_decode() {
+        // 清除帧头的校验和标志并去掉末尾的 4 字节校验和
+        for (data, expected) in [(SHORT_ZST, short()), (TEXT_ZST, text())] {
+            let mut unchecked = data[..data.len() - 4].to_vec();
+            unchecked[4] &= !0x04;
+            assert_eq!(
+                decode(Compression::Zstd, &unchecked, u64::MAX).unwrap(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn oversized_window_is_rejected() {
+        // 非单段帧，窗口描述字节的指数为 31，即 2TB 的窗口
+        let mut data = FRAME_MAGIC.to_le_bytes().to_vec();
+        data.extend_from_slice(&[0x00, 0xf8, 0x01, 0x00, 0x00]);
+        let error = decode(Compression::Zstd, &data, u64::MAX).unwrap_err();
+        assert!(error.to_string().contains("window"), "{}", error);
+    }
+
+    #[test]
+    fn dictionaries_are_rejected() {
+        let mut data = FRAME_MAGIC.to_le_bytes().to_vec();
+        data.extend_from_slice(&[0x21, 0x07, 0x00, 0x01, 0x00, 0x00]);
+        let error = decode(Compression::Zstd, &data, u64::MAX).unwrap_err();
+        assert!(error.to_string().contains("dictionaries"), "{}", error);
+    }
+
+    #[test]
+    fn content_size_mismatch_is_rejected() {
+        // short 的帧头声明 20 字节内容，改为 19
+        let mut data = SHORT_ZST.to_vec();
+        data[5] -= 1;
+        let error = decode(Compression::Zstd, &data, u64::MAX).unwrap_err();
+        assert!(error.to_string().contains("content size"), "{}", error);
+    }
+
+    #[test]
+    fn reserved_block_type_is_rejected() {
+        // 单段帧，内容大小 0，最后一个块的类型为保留值 3
+        let mut data = FRAME_MAGIC.to_le_bytes().to_vec();
+        data.extend_from_slice(&[0x20, 0x00, 0x07, 0x00, 0x00]);
+        let error = decode(Compression::Zstd, &data, u64::MAX).unwrap_err();
+        assert!(error.to_string().contains("reserved"), "{}", error);
+    }
+}
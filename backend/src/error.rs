@@ -14,13 +14,20 @@ pub enum AppError {
     FileAccess(std::io::Error),
 
     // 采样错误
-    #[allow(dead_code)]
     SamplingFailed(String),
     InvalidSampleSize(usize),
 
     // 系统错误
     Internal(anyhow::Error),
     BadRequest(String),
+    Unauthorized,
+    TooManyRequests(String),
+    // 与进行中的另一个请求冲突，如同一幂等键的上传尚未完成
+    Conflict(String),
+    // 拉取远程文件失败：无法连接、超时或远程服务器返回错误
+    RemoteFetch(String),
+    // 服务器正在关闭，进行中的请求被中断
+    ServerShuttingDown,
 
     // WebSocket错误
     ConnectionClosed,
@@ -37,6 +44,11 @@ impl fmt::Display for AppError {
             Self::InvalidSampleSize(size) => write!(f, "Invalid sample size: {}", size),
             Self::Internal(e) => write!(f, "Internal error: {}", e),
             Self::BadRequest(msg) => write!(f, "Bad request: {}", msg),
+            Self::Unauthorized => write!(f, "Unauthorized"),
+            Self::TooManyRequests(msg) => write!(f, "Too many requests: {}", msg),
+            Self::Conflict(msg) => write!(f, "Conflict: {}", msg),
+            Self::RemoteFetch(msg) => write!(f, "Remote fetch failed: {}", msg),
+            Self::ServerShuttingDown => write!(f, "Server shutting down"),
             Self::ConnectionClosed => write!(f, "Connection closed"),
             Self::InvalidMessage => write!(f, "Invalid message format"),
         }
@@ -45,17 +57,67 @@ impl fmt::Display for AppError {
 
 impl std::error::Error for AppError {}
 
+impl AppError {
+    // 致命错误需要断开连接，其余错误只需回报给客户端，连接可继续使用
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            Self::ConnectionClosed | Self::FileAccess(_) | Self::Internal(_)
+        )
+    }
+
+    // HTTP 状态码映射
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Self::FileNotFound(_) => StatusCode::NOT_FOUND,
+            Self::FileTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::FileAccess(_) => StatusCode::FORBIDDEN,
+            Self::InvalidSampleSize(_) => StatusCode::BAD_REQUEST,
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::InvalidMessage => StatusCode::BAD_REQUEST,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            // 客户端应退避后重试
+            Self::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::RemoteFetch(_) => StatusCode::BAD_GATEWAY,
+            Self::ServerShuttingDown => StatusCode::SERVICE_UNAVAILABLE,
+            // 请求格式正确但无法对该文件执行采样
+            Self::SamplingFailed(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    // WebSocket 错误码，与 HTTP 状态码保持一致
+    pub fn ws_code(&self) -> u16 {
+        self.status_code().as_u16()
+    }
+
+    // 机器可读的错误类别
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::FileNotFound(_) => "file_not_found",
+            Self::FileTooLarge(_) => "file_too_large",
+            Self::FileAccess(_) => "file_access",
+            Self::SamplingFailed(_) => "sampling_failed",
+            Self::InvalidSampleSize(_) => "invalid_sample_size",
+            Self::Internal(_) => "internal",
+            Self::BadRequest(_) => "bad_request",
+            Self::Unauthorized => "unauthorized",
+            Self::TooManyRequests(_) => "too_many_requests",
+            Self::Conflict(_) => "conflict",
+            Self::RemoteFetch(_) => "remote_fetch_failed",
+            Self::ServerShuttingDown => "server_shutting_down",
+            Self::ConnectionClosed => "connection_closed",
+            Self::InvalidMessage => "invalid_message",
+        }
+    }
+}
+
 // Fast-fail: 直接返回错误，不做降级
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let (status, message) = match self {
-            Self::FileNotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
-            Self::FileTooLarge(_) => (StatusCode::PAYLOAD_TOO_LARGE, self.to_string()),
-            Self::FileAccess(_) => (StatusCode::FORBIDDEN, self.to_string()),
-            Self::InvalidSampleSize(_) => (StatusCode::BAD_REQUEST, self.to_string()),
-            Self::BadRequest(_) => (StatusCode::BAD_REQUEST, self.to_string()),
-            _ => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
-        };
+        let status = self.status_code();
+        let message = self.to_string();
 
         let body = Json(json!({
             "error": message,
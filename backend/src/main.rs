@@ -1,56 +1,133 @@
-use axum::{extract::DefaultBodyLimit, Extension, Router};
 use server::handlers::AppState;
 use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpListener;
-use tower_http::cors::CorsLayer;
-use tracing::info;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info};
 
 mod config;
+mod config_file;
 mod core;
 mod error;
+mod logging;
 mod protocol;
 mod sampling;
 mod server;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // 初始化日志
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::INFO)
-        .init();
-
-    // 加载配置
+    // 加载配置，日志格式和级别来自配置，因此先于日志初始化
     let config = config::Config::from_env()?;
+    config.validate()?;
+
+    // 初始化日志，级别已在 validate 中校验
+    logging::init(
+        config.log_format,
+        config.log_level.parse().unwrap_or(tracing::Level::INFO),
+    );
 
     info!("Starting BinaryVis backend server");
     info!("Configuration: {:?}", config);
 
     // 初始化应用状态
-    let state = Arc::new(AppState {
-        file_manager: Arc::new(core::FileManager::new(
-            config.upload_dir.clone(),
-            config.max_file_size,
-        )),
-        cache: Arc::new(core::Cache::new(config.cache_size)),
-        config: config.clone(),
-    });
+    let state = Arc::new(AppState::new(config.clone())?);
+
+    // 定期清理过期上传文件
+    if config.file_ttl_secs > 0 {
+        tokio::spawn(cleanup_task(state.clone(), config.file_ttl_secs));
+    }
 
     // 构建路由
-    let app = Router::new()
-        .nest("/api", server::api_routes())
-        .nest("/ws", server::ws_routes())
-        .layer(Extension(state))
-        .layer(CorsLayer::permissive())
-        // 设置请求体大小限制为配置中的 max_file_size
-        .layer(DefaultBodyLimit::max(config.max_file_size));
+    let app = server::app(state.clone())?;
 
     // 启动服务器
     let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
     info!("Server listening on {}", addr);
 
     let listener = TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    // 采样接口按客户端 IP 限流，需要连接的对端地址
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+        .with_graceful_shutdown(shutdown_signal(state.shutdown.clone()))
+        .await?;
+
+    // 优雅关闭不等待已升级的 WebSocket 连接，这里等待它们发出最后的消息和 Close 帧
+    let drain_deadline = tokio::time::Instant::now() + WS_DRAIN_TIMEOUT;
+    while state.active_connections.load(Ordering::Relaxed) > 0
+        && tokio::time::Instant::now() < drain_deadline
+    {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    // 清理被中断的上传留下的临时文件
+    let removed = state.file_manager.cleanup_tmp_files().await;
+    if removed > 0 {
+        info!("Removed {} temporary upload files", removed);
+    }
+    info!("Server stopped");
 
     Ok(())
 }
+
+// 关闭时等待 WebSocket 连接结束的最长时间，需要长于连接内等待请求收尾的时间
+const WS_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+// 等待 Ctrl-C 或 SIGTERM，然后通知所有连接停止
+async fn shutdown_signal(shutdown: CancellationToken) {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    info!("Shutdown signal received, draining connections");
+    shutdown.cancel();
+}
+
+async fn cleanup_task(state: Arc<AppState>, ttl_secs: u64) {
+    let max_age = Duration::from_secs(ttl_secs);
+    // 检查周期跟随 TTL，但限制在 1 分钟到 1 小时之间
+    let mut interval = tokio::time::interval(Duration::from_secs(ttl_secs.clamp(60, 3600)));
+
+    loop {
+        interval.tick().await;
+
+        match state.file_manager.cleanup_older_than(max_age).await {
+            Ok(removed) => {
+                for file_id in &removed {
+                    state.invalidate_file(file_id);
+                }
+                if !removed.is_empty() {
+                    info!("Cleaned up {} expired files", removed.len());
+                }
+            }
+            Err(e) => error!("File cleanup failed: {}", e),
+        }
+    }
+}
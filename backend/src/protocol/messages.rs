@@ -1,3 +1,5 @@
+use crate::error::{AppError, Result};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,30 +18,244 @@ pub enum MessageType {
     Data,
     Control,
     Error,
+    Progress,
+    Prepare,
+    Complete,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DataMessage {
+    // 发起该数据流的控制消息 ID
+    pub request_id: String,
     pub offset: usize,
     pub total: usize,
     #[serde(with = "serde_bytes")]
     pub chunk: Vec<u8>,
+    // sample_multi 请求中该数据所属的采样大小，其余请求省略
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample_size: Option<usize>,
+}
+
+// 采样完成、发送第一条数据消息之前发送一次，客户端可以据此预先分配缓冲区
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrepareMessage {
+    pub request_id: String,
+    // 采样数据的总字节数，以及将要发送的数据消息条数和每条的字节数（最后一条可能不足）
+    pub total_bytes: usize,
+    pub chunk_count: usize,
+    pub chunk_size: usize,
+    // 同 DataMessage::sample_size
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample_size: Option<usize>,
+}
+
+// 熵分布的一段：values[i] 是第 offset + i 个窗口的熵，共 total 个窗口
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntropyMessage {
+    pub request_id: String,
+    pub offset: usize,
+    pub total: usize,
+    pub window_size: usize,
+    pub values: Vec<f32>,
+    // metric 为 highbit 时附带每个窗口高位字节（>= 0x80）的比例，与 values 一一对应
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub highbit: Option<Vec<f32>>,
+}
+
+// 整个文件的 256 桶字节直方图
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistogramMessage {
+    pub request_id: String,
+    pub counts: Vec<u64>,
+    // 由 counts 得出的整体熵（比特/字节），无需再扫描文件
+    pub entropy: f32,
+}
+
+// 采样数据全部发送完毕后发送，total 为采样数据的总字节数
+// 服务器关闭中断发送时 partial 为 true，total 为已经发出的字节数，随后是 server_shutting_down 错误
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompleteMessage {
+    pub request_id: String,
+    pub total: usize,
+    pub method: String,
+    pub original_size: usize,
+    // 采样耗时（毫秒），命中缓存时为 0 且 cached 为 true
+    pub compute_ms: u64,
+    pub cached: bool,
+    // 请求 class_summary 时附带，含义同采样元数据中的同名字段
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class_window: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "serde_bytes")]
+    pub class_summary: Option<Vec<u8>>,
+    // 同 DataMessage::sample_size，sample_multi 请求中每个采样大小各发送一次完成消息
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample_size: Option<usize>,
+    #[serde(default)]
+    pub partial: bool,
+}
+
+// 分析任务的进度，按已处理的字节数计
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProgressMessage {
+    pub request_id: String,
+    pub processed: usize,
+    pub total: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ControlMessage {
     pub command: String,
     pub params: Option<serde_json::Value>,
+    // 协商后续消息的编码："msgpack"（默认）或 "json"
+    #[serde(default)]
+    pub encoding: Option<String>,
+}
+
+// WebSocket 消息编码
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Encoding {
+    #[default]
+    MessagePack,
+    Json,
+}
+
+impl Encoding {
+    pub const ALL: [Self; 2] = [Self::MessagePack, Self::Json];
+
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "msgpack" => Ok(Self::MessagePack),
+            "json" => Ok(Self::Json),
+            other => Err(AppError::BadRequest(format!("Unknown encoding: {}", other))),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::MessagePack => "msgpack",
+            Self::Json => "json",
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T> {
+        match self {
+            Self::MessagePack => rmp_serde::from_slice(data).map_err(|_| AppError::InvalidMessage),
+            Self::Json => serde_json::from_slice(data).map_err(|_| AppError::InvalidMessage),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SampleRequest {
     pub sample_size: usize,
     pub method: Option<String>,
+    // 只在 [range_start, range_end) 内采样，缺省分别为文件开头和结尾
+    #[serde(default)]
+    pub range_start: Option<usize>,
+    #[serde(default)]
+    pub range_end: Option<usize>,
+    // uniform 采样相邻窗口的重叠比例（0.0–0.9），默认 0
+    #[serde(default)]
+    pub overlap: Option<f32>,
+    // uniform 采样的窗口起点在文件中的字节偏移向下对齐到该值的整数倍（如记录长度 16、512），不能超过窗口大小
+    #[serde(default)]
+    pub alignment: Option<usize>,
+    // 每 element_size 字节（1、2、4 或 8）作为一个数值采样，结果中每个元素取其小端最高字节，默认 1
+    #[serde(default)]
+    pub element_size: Option<usize>,
+    // grid 采样的列数（2 的幂）和行数，行数默认为 sample_size / row_width
+    #[serde(default)]
+    pub row_width: Option<usize>,
+    #[serde(default)]
+    pub rows: Option<usize>,
+    // 为 true 时返回每个采样字节的类别 ID 而不是原始字节
+    #[serde(default)]
+    pub classify: bool,
+    // 为 true 时同时返回每个采样窗口出现最多的字节分类，数据本身仍按 classify 返回
+    #[serde(default)]
+    pub class_summary: bool,
+    // WebSocket 分块发送时每块的字节数，HTTP 接口忽略
+    #[serde(default)]
+    pub chunk_size: Option<usize>,
+}
+
+// 一次请求多个采样大小（如缩放界面的多级细节），其余参数对每个大小相同
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SampleMultiRequest {
+    pub sample_sizes: Vec<usize>,
+    pub method: Option<String>,
+    #[serde(default)]
+    pub range_start: Option<usize>,
+    #[serde(default)]
+    pub range_end: Option<usize>,
+    #[serde(default)]
+    pub overlap: Option<f32>,
+    #[serde(default)]
+    pub alignment: Option<usize>,
+    #[serde(default)]
+    pub element_size: Option<usize>,
+    #[serde(default)]
+    pub row_width: Option<usize>,
+    #[serde(default)]
+    pub rows: Option<usize>,
+    #[serde(default)]
+    pub classify: bool,
+    #[serde(default)]
+    pub class_summary: bool,
+    #[serde(default)]
+    pub chunk_size: Option<usize>,
+}
+
+impl SampleMultiRequest {
+    // 单个采样大小对应的采样请求
+    pub fn request_for(&self, sample_size: usize) -> SampleRequest {
+        SampleRequest {
+            sample_size,
+            method: self.method.clone(),
+            range_start: self.range_start,
+            range_end: self.range_end,
+            overlap: self.overlap,
+            alignment: self.alignment,
+            element_size: self.element_size,
+            row_width: self.row_width,
+            rows: self.rows,
+            classify: self.classify,
+            class_summary: self.class_summary,
+            chunk_size: self.chunk_size,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CancelRequest {
+    pub request_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EntropyRequest {
+    pub window_size: Option<usize>,
+    // "entropy"（默认）只计算熵；"highbit" 同时计算高位字节比例
+    pub metric: Option<String>,
+}
+
+// 跟随文件的追加写入，从 offset 开始发送新数据，缺省为订阅时的文件末尾
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FollowRequest {
+    pub offset: Option<usize>,
+    pub chunk_size: Option<usize>,
+}
+
+// 清空缓存需要携带管理令牌
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ClearCacheRequest {
+    pub token: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ErrorMessage {
+    // 出错的请求（控制消息）ID；消息本身无法解析时省略
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
     pub code: u16,
     pub message: String,
     pub details: Option<serde_json::Value>,
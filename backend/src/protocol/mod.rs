@@ -1,3 +1,7 @@
 pub mod messages;
 
-pub use messages::{Message, MessageType, DataMessage, ControlMessage, ErrorMessage, SampleRequest};
+pub use messages::{
+    CancelRequest, ClearCacheRequest, CompleteMessage, ControlMessage, DataMessage, Encoding,
+    EntropyMessage, EntropyRequest, ErrorMessage, FollowRequest, HistogramMessage, Message,
+    MessageType, PrepareMessage, ProgressMessage, SampleMultiRequest, SampleRequest,
+};
@@ -0,0 +1,55 @@
+use crate::core::sampler::{SampleOptions, SampleResult, Sampler};
+use crate::error::Result;
+use std::sync::atomic::AtomicBool;
+
+// 可复现的均匀采样：窗口布局与 uniform 相同，但窗口位置由文件 ID 导出的固定种子决定，
+// 同一文件在不同进程、不同服务器上得到逐字节相同的结果，供前端视觉回归测试使用
+// 种子的导出（FNV-1a）和随机数生成器（SplitMix64）都在这里实现，不随 rand 的版本变化；
+// 改动它们或 uniform 的窗口布局都会改变 golden 的输出，补丁版本中不允许；
+// 窗口数达到 max_windows 上限后窗口会被放大，不同服务器只有上限相同时输出才一致
+pub struct GoldenSampler {
+    pub max_windows: usize,
+}
+
+impl Sampler for GoldenSampler {
+    fn sample_cancellable(
+        &self,
+        data: &[u8],
+        target_size: usize,
+        options: &SampleOptions,
+        cancel: &AtomicBool,
+    ) -> Result<SampleResult> {
+        let mut rng = SplitMix64(options.seed);
+        super::uniform::sample_windows(
+            data,
+            target_size,
+            options,
+            cancel,
+            self.max_windows,
+            "golden",
+            |max_offset| {
+                // 取模有轻微偏差，不影响可复现性
+                (rng.next() % (max_offset as u64 + 1)) as usize
+            },
+        )
+    }
+}
+
+// 由文件 ID 导出 golden 采样的种子（64 位 FNV-1a）
+pub fn seed_for(file_id: &str) -> u64 {
+    file_id.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
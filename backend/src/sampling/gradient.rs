@@ -0,0 +1,74 @@
+use crate::core::sampler::{SampleOptions, SampleResult, Sampler};
+use crate::error::Result;
+use std::ops::Range;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+// 边缘采样：先用基础采样器采样，再把每个位置替换为与前一个采样字节之差的绝对值，突出结构边界
+// 结果与基础采样等长；不连续片段（uniform 的窗口、head_tail 的首尾两段）的第一个位置在文件中没有左邻，输出 0
+// 采样范围和种子原样交给基础采样器，元数据保留它的窗口和范围信息
+pub struct GradientSampler {
+    pub base: Arc<dyn Sampler>,
+}
+
+impl Sampler for GradientSampler {
+    fn sample_cancellable(
+        &self,
+        data: &[u8],
+        target_size: usize,
+        options: &SampleOptions,
+        cancel: &AtomicBool,
+    ) -> Result<SampleResult> {
+        let base = self
+            .base
+            .sample_cancellable(data, target_size, options, cancel)?;
+        gradient(base, cancel)
+    }
+
+    fn sample_range(
+        &self,
+        data: &[u8],
+        range: Range<usize>,
+        target_size: usize,
+        options: &SampleOptions,
+        cancel: &AtomicBool,
+    ) -> Result<SampleResult> {
+        let base = self
+            .base
+            .sample_range(data, range, target_size, options, cancel)?;
+        gradient(base, cancel)
+    }
+
+    // 数据再小也要计算差值
+    fn passthrough_small(&self) -> bool {
+        false
+    }
+
+    fn deterministic(&self) -> bool {
+        self.base.deterministic()
+    }
+}
+
+fn gradient(result: SampleResult, cancel: &AtomicBool) -> Result<SampleResult> {
+    let SampleResult { data, mut metadata } = result;
+    let window_size = metadata.window_size;
+    let split = metadata.split;
+    let segment_start = |i: usize| {
+        i == 0 || window_size.is_some_and(|window| i.is_multiple_of(window)) || split == Some(i)
+    };
+
+    let result = super::extract_cancellable(data.len(), cancel, |i| {
+        if segment_start(i) {
+            0
+        } else {
+            data[i].abs_diff(data[i - 1])
+        }
+    })?;
+
+    metadata.method = "gradient".to_string();
+    metadata.encoding = Some("gradient".to_string());
+    Ok(SampleResult {
+        data: result.into(),
+        metadata,
+    })
+}
@@ -0,0 +1,116 @@
+use crate::core::sampler::{SampleMetadata, SampleOptions, SampleResult, Sampler};
+use crate::error::{AppError, Result};
+use std::sync::atomic::AtomicBool;
+
+// 网格采样：把整个数据按顺序均分给 row_width × rows 个单元格，每个单元格取所含字节的均值，
+// 结果按行排列即为固定分辨率的字节图（"walk the bytes"）
+// 第 i 个单元格覆盖 [i * N / cells, (i + 1) * N / cells)，各单元格的字节数最多相差 1，
+// 数据末尾不会留下空单元格；数据比网格小时没有分到字节的单元格取其起点处的字节
+pub struct GridSampler;
+
+impl Sampler for GridSampler {
+    fn sample_cancellable(
+        &self,
+        data: &[u8],
+        target_size: usize,
+        options: &SampleOptions,
+        cancel: &AtomicBool,
+    ) -> Result<SampleResult> {
+        let data_size = data.len();
+        super::ensure_sampleable(data_size, target_size)?;
+
+        let row_width = options.row_width.ok_or_else(|| {
+            AppError::BadRequest("row_width is required for grid sampling".to_string())
+        })?;
+        // 未指定行数时用满采样大小
+        let rows = options.rows.unwrap_or(target_size / row_width);
+        let cells = row_width
+            .checked_mul(rows)
+            .filter(|&cells| cells > 0 && cells <= target_size)
+            .ok_or_else(|| {
+                AppError::BadRequest(format!(
+                    "Grid of {} x {} cells does not fit in sample size {}",
+                    row_width, rows, target_size
+                ))
+            })?;
+
+        // 单元格的字节数为 N / cells 向下或向上取整，元数据记录较大的那个
+        let bytes_per_cell = data_size.div_ceil(cells);
+        let result = super::extract_cancellable(cells, cancel, |cell| {
+            let start = cell_start(cell, data_size, cells);
+            let end = cell_start(cell + 1, data_size, cells);
+            if start == end {
+                return data[start];
+            }
+            let sum: u64 = data[start..end].iter().map(|&byte| byte as u64).sum();
+            (sum / (end - start) as u64) as u8
+        })?;
+
+        let mut metadata = SampleMetadata::new(data_size, result.len(), "grid");
+        metadata.row_width = Some(row_width);
+        metadata.bytes_per_cell = Some(bytes_per_cell);
+
+        Ok(SampleResult {
+            data: result.into(),
+            metadata,
+        })
+    }
+
+    // 数据比网格小时仍然输出完整的网格
+    fn passthrough_small(&self) -> bool {
+        false
+    }
+}
+
+// 第 cell 个单元格在数据中的起点 cell * data_size / cells，乘积可能超出 usize，用 u128 计算
+fn cell_start(cell: usize, data_size: usize, cells: usize) -> usize {
+    (cell as u128 * data_size as u128 / cells as u128) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(
+        data: &[u8],
+        target_size: usize,
+        row_width: usize,
+        rows: Option<usize>,
+    ) -> Result<SampleResult> {
+        let options = SampleOptions {
+            row_width: Some(row_width),
+            rows,
+            ..Default::default()
+        };
+        GridSampler.sample_cancellable(data, target_size, &options, &AtomicBool::new(false))
+    }
+
+    #[test]
+    fn overflowing_grid_is_rejected() {
+        let data = vec![1u8; 1024];
+        let result = grid(&data, usize::MAX, 1 << 32, Some(usize::MAX / 2));
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+    }
+
+    #[test]
+    fn cells_split_the_data_evenly_without_empty_tail() {
+        // 10 个字节分给 4 个单元格：[0, 2) [2, 5) [5, 7) [7, 10)，最后一个单元格不为空
+        let data: Vec<u8> = (0..10).map(|i| i * 10).collect();
+        let result = grid(&data, 4, 4, Some(1)).unwrap();
+        assert_eq!(&result.data[..], &[5, 30, 55, 80]);
+        assert_eq!(result.metadata.bytes_per_cell, Some(3));
+
+        // 1025 个字节、1024 个单元格时按 div_ceil 每格 2 字节，后一半单元格会是空的
+        let data = vec![0xffu8; 1025];
+        let result = grid(&data, 1024, 32, None).unwrap();
+        assert_eq!(result.data.len(), 1024);
+        assert!(result.data.iter().all(|&byte| byte == 0xff));
+    }
+
+    #[test]
+    fn small_data_fills_every_cell() {
+        let data = [10u8, 20, 30];
+        let result = grid(&data, 8, 8, Some(1)).unwrap();
+        assert_eq!(&result.data[..], &[10, 10, 10, 20, 20, 20, 30, 30]);
+    }
+}
@@ -0,0 +1,50 @@
+use crate::core::sampler::{SampleMetadata, SampleOptions, SampleResult, Sampler};
+use crate::error::Result;
+use std::sync::atomic::AtomicBool;
+
+// 首尾采样：返回文件开头和结尾的字节，便于查看文件头和尾部目录等格式结构
+pub struct HeadTailSampler;
+
+impl Sampler for HeadTailSampler {
+    fn sample_cancellable(
+        &self,
+        data: &[u8],
+        target_size: usize,
+        options: &SampleOptions,
+        cancel: &AtomicBool,
+    ) -> Result<SampleResult> {
+        let data_size = data.len();
+        let element_size = options.element_size();
+        let element_count = super::element_count(data_size, target_size, element_size)?;
+
+        // 如果文件小于目标大小，返回全部数据
+        if element_count <= target_size {
+            return super::full_elements(data, element_size, cancel);
+        }
+
+        // 两端是连续拷贝，开始前检查一次即可
+        super::check_cancelled(cancel)?;
+
+        // 只访问 mmap 的两端区域；以元素为单位，末尾不足一个元素的字节不参与采样
+        let head_size = target_size / 2;
+        let tail_size = target_size - head_size;
+
+        let mut result = Vec::with_capacity(target_size);
+        if element_size == 1 {
+            result.extend_from_slice(&data[..head_size]);
+            result.extend_from_slice(&data[data_size - tail_size..]);
+        } else {
+            let elements = (0..head_size).chain(element_count - tail_size..element_count);
+            result.extend(elements.map(|i| super::element_byte(data, i, element_size)));
+        }
+
+        let mut metadata = SampleMetadata::new(data_size, result.len(), "head_tail");
+        metadata.split = Some(head_size);
+        metadata.element_size = options.element_size;
+
+        Ok(SampleResult {
+            data: result.into(),
+            metadata,
+        })
+    }
+}
@@ -1,3 +1,210 @@
+pub mod golden;
+pub mod gradient;
+pub mod grid;
+pub mod head_tail;
+pub mod systematic;
 pub mod uniform;
 
+pub use golden::GoldenSampler;
+pub use gradient::GradientSampler;
+pub use grid::GridSampler;
+pub use head_tail::HeadTailSampler;
+pub use systematic::SystematicSampler;
 pub use uniform::UniformSampler;
+
+use crate::core::sampler::{SampleMetadata, SampleResult};
+use crate::core::Sampler;
+use crate::error::{AppError, Result};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// 采样方法名到采样器的注册表，启动时构建后放入 AppState
+// 新的采样器只需注册，不用修改按方法分发的代码
+#[derive(Clone, Default)]
+pub struct SamplerRegistry {
+    samplers: HashMap<String, Arc<dyn Sampler>>,
+}
+
+impl SamplerRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 包含全部内置采样器，max_windows 为 uniform 和 golden 的窗口数上限
+    pub fn with_builtin(max_windows: usize) -> Self {
+        let mut registry = Self::new();
+        let uniform: Arc<dyn Sampler> = Arc::new(UniformSampler { max_windows });
+        registry.register("uniform", uniform.clone());
+        registry.register("systematic", Arc::new(SystematicSampler));
+        registry.register("head_tail", Arc::new(HeadTailSampler));
+        registry.register("golden", Arc::new(GoldenSampler { max_windows }));
+        registry.register("gradient", Arc::new(GradientSampler { base: uniform }));
+        registry.register("grid", Arc::new(GridSampler));
+        registry
+    }
+
+    // 同名的采样器会被替换
+    pub fn register(&mut self, name: impl Into<String>, sampler: Arc<dyn Sampler>) {
+        self.samplers.insert(name.into(), sampler);
+    }
+
+    // 已注册的采样方法名，按名称排序
+    pub fn methods(&self) -> Vec<&str> {
+        let mut methods: Vec<&str> = self.samplers.keys().map(String::as_str).collect();
+        methods.sort_unstable();
+        methods
+    }
+
+    // 按请求的采样方法查找采样器，未指定时使用 uniform
+    pub fn get(&self, method: Option<&str>) -> Result<Arc<dyn Sampler>> {
+        let method = method.unwrap_or("uniform");
+        self.samplers.get(method).cloned().ok_or_else(|| {
+            AppError::BadRequest(format!("Unknown sampling method: {}", method))
+        })
+    }
+}
+
+// 采样前的通用检查，无法采样时返回带原因的 SamplingFailed
+pub fn ensure_sampleable(data_size: usize, target_size: usize) -> Result<()> {
+    if target_size == 0 {
+        return Err(AppError::SamplingFailed(
+            "target size must be greater than 0".to_string(),
+        ));
+    }
+    if data_size == 0 {
+        return Err(AppError::SamplingFailed(
+            "file is empty, nothing to sample".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+// 数据按 element_size 字节分组后的元素个数，末尾不足一个元素的字节不参与采样
+pub(crate) fn element_count(
+    data_size: usize,
+    target_size: usize,
+    element_size: usize,
+) -> Result<usize> {
+    ensure_sampleable(data_size, target_size)?;
+    if data_size < element_size {
+        return Err(AppError::SamplingFailed(format!(
+            "{} bytes of data are smaller than one {}-byte element",
+            data_size, element_size
+        )));
+    }
+
+    Ok(data_size / element_size)
+}
+
+// 第 index 个元素在结果中的字节：按小端整数取最高字节，即把元素的数值缩放到 0–255
+pub(crate) fn element_byte(data: &[u8], index: usize, element_size: usize) -> u8 {
+    data[index * element_size + element_size - 1]
+}
+
+// 元素个数不大于目标大小时返回全部元素
+pub(crate) fn full_elements(
+    data: &[u8],
+    element_size: usize,
+    cancel: &AtomicBool,
+) -> Result<SampleResult> {
+    if element_size == 1 {
+        return Ok(SampleResult::full(data));
+    }
+
+    let result = extract_cancellable(data.len() / element_size, cancel, |i| {
+        element_byte(data, i, element_size)
+    })?;
+    let mut metadata = SampleMetadata::new(data.len(), result.len(), "full");
+    metadata.element_size = Some(element_size);
+    Ok(SampleResult {
+        data: result.into(),
+        metadata,
+    })
+}
+
+pub(crate) fn check_cancelled(cancel: &AtomicBool) -> Result<()> {
+    if cancel.load(Ordering::Relaxed) {
+        return Err(AppError::SamplingFailed("cancelled".to_string()));
+    }
+    Ok(())
+}
+
+// 可取消的并行提取：结果的第 i 个字节为 byte_at(i)，每 CANCEL_CHUNK 字节为一块并行填充，每块开始前检查 cancel
+pub(crate) fn extract_cancellable(
+    len: usize,
+    cancel: &AtomicBool,
+    byte_at: impl Fn(usize) -> u8 + Sync,
+) -> Result<Vec<u8>> {
+    let mut result = vec![0u8; len];
+    result
+        .par_chunks_mut(CANCEL_CHUNK)
+        .enumerate()
+        .try_for_each(|(chunk, dest)| {
+            check_cancelled(cancel)?;
+            let base = chunk * CANCEL_CHUNK;
+            for (i, byte) in dest.iter_mut().enumerate() {
+                *byte = byte_at(base + i);
+            }
+            Ok(())
+        })?;
+    Ok(result)
+}
+
+// 可取消的并行提取中每块的字节数，取消后最多再处理每个线程手上的一块
+const CANCEL_CHUNK: usize = 1024 * 1024;
+
+// 压缩或编码后的格式，内容接近均匀随机，局部结构对可视化没有意义
+const COMPRESSED_TYPES: &[&str] = &[
+    "gzip", "bzip2", "xz", "zstd", "7z", "rar", "zip", "jpeg", "png", "gif",
+];
+
+// 推荐的采样参数
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Suggestion {
+    pub sample_size: usize,
+    pub method: &'static str,
+    pub rationale: String,
+}
+
+// 根据文件大小和识别出的类型推荐采样参数，每个像素对应一个采样字节，不读取文件内容
+pub fn suggest(
+    file_size: usize,
+    file_type: Option<&str>,
+    target_pixels: usize,
+    max_sample_size: usize,
+) -> Suggestion {
+    let sample_size = target_pixels.min(max_sample_size).max(1);
+    if file_size <= sample_size {
+        return Suggestion {
+            sample_size: file_size.max(1),
+            method: "uniform",
+            rationale: format!(
+                "file ({} bytes) fits in the render area, the whole file is returned unsampled",
+                file_size
+            ),
+        };
+    }
+
+    match file_type.filter(|file_type| COMPRESSED_TYPES.contains(file_type)) {
+        Some(file_type) => Suggestion {
+            sample_size,
+            method: "systematic",
+            rationale: format!(
+                "{} content is compressed and looks uniform, evenly spaced bytes show it as well as \
+                 contiguous windows at lower cost",
+                file_type
+            ),
+        },
+        None => Suggestion {
+            sample_size,
+            method: "uniform",
+            rationale: format!(
+                "contiguous windows keep local structure visible; about 1 in {} bytes is shown",
+                file_size.div_ceil(sample_size)
+            ),
+        },
+    }
+}
@@ -0,0 +1,74 @@
+use crate::core::sampler::{SampleMetadata, SampleOptions, SampleResult, Sampler};
+use crate::error::Result;
+use std::sync::atomic::AtomicBool;
+
+// 等间隔采样：每隔 stride 个字节取一个，保留文件的整体结构
+pub struct SystematicSampler;
+
+impl Sampler for SystematicSampler {
+    fn sample_cancellable(
+        &self,
+        data: &[u8],
+        target_size: usize,
+        options: &SampleOptions,
+        cancel: &AtomicBool,
+    ) -> Result<SampleResult> {
+        let data_size = data.len();
+        let element_size = options.element_size();
+        let element_count = super::element_count(data_size, target_size, element_size)?;
+
+        // 如果文件小于目标大小，返回全部数据
+        if element_count <= target_size {
+            return super::full_elements(data, element_size, cancel);
+        }
+
+        // element_count > target_size，所以 stride >= 1；步长以元素为单位
+        let stride = element_count / target_size;
+
+        let result = super::extract_cancellable(target_size, cancel, |i| {
+            super::element_byte(data, i * stride, element_size)
+        })?;
+
+        let mut metadata = SampleMetadata::new(data_size, result.len(), "systematic");
+        metadata.stride = Some(stride);
+        metadata.element_size = options.element_size;
+
+        Ok(SampleResult {
+            data: result.into(),
+            metadata,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(data: &[u8], target_size: usize, options: &SampleOptions) -> SampleResult {
+        SystematicSampler
+            .sample_cancellable(data, target_size, options, &AtomicBool::new(false))
+            .unwrap()
+    }
+
+    #[test]
+    fn output_byte_i_is_input_byte_i_times_stride() {
+        let data: Vec<u8> = (0..10_007).map(|i| (i * 7 % 256) as u8).collect();
+        for target_size in [1, 3, 100, 5_003, 10_006] {
+            let result = sample(&data, target_size, &SampleOptions::default());
+            let stride = result.metadata.stride.unwrap();
+            assert_eq!(stride, data.len() / target_size);
+            assert_eq!(result.data.len(), target_size);
+            for (i, &byte) in result.data.iter().enumerate() {
+                assert_eq!(byte, data[i * stride], "target {} index {}", target_size, i);
+            }
+        }
+    }
+
+    #[test]
+    fn small_data_is_returned_whole() {
+        let data = b"short".to_vec();
+        let result = sample(&data, 16, &SampleOptions::default());
+        assert_eq!(&result.data[..], &data[..]);
+        assert_eq!(result.metadata.method, "full");
+    }
+}
@@ -1,106 +1,343 @@
-use crate::core::sampler::{SampleMetadata, SampleResult, Sampler};
-use crate::error::Result;
-use memmap2::Mmap;
+use crate::core::sampler::{SampleMetadata, SampleOptions, SampleResult, Sampler};
+use crate::error::{AppError, Result};
 use rand::prelude::*;
 use rayon::prelude::*;
-use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
 
-pub struct UniformSampler;
+pub struct UniformSampler {
+    // 窗口数上限，超过时放大窗口
+    pub max_windows: usize,
+}
 
 impl Sampler for UniformSampler {
-    fn sample(&self, data: Arc<Mmap>, target_size: usize) -> Result<SampleResult> {
-        let data_size = data.len();
-
-        // 如果文件小于目标大小，返回全部数据
-        if data_size <= target_size {
-            return Ok(SampleResult {
-                data: data.to_vec(),
-                metadata: SampleMetadata {
-                    original_size: data_size,
-                    sample_size: data_size,
-                    method: "full".to_string(),
-                },
-            });
-        }
+    fn sample_cancellable(
+        &self,
+        data: &[u8],
+        target_size: usize,
+        options: &SampleOptions,
+        cancel: &AtomicBool,
+    ) -> Result<SampleResult> {
+        let mut rng = thread_rng();
+        sample_windows(
+            data,
+            target_size,
+            options,
+            cancel,
+            self.max_windows,
+            "uniform",
+            |max_offset| rng.gen_range(0..=max_offset),
+        )
+    }
+
+    // 每次随机选取窗口位置
+    fn deterministic(&self) -> bool {
+        false
+    }
+}
+
+// uniform 和 golden 共用的采样主体，pick(max) 返回窗口在段内的偏移（0..=max）
+pub(super) fn sample_windows(
+    data: &[u8],
+    target_size: usize,
+    options: &SampleOptions,
+    cancel: &AtomicBool,
+    max_windows: usize,
+    method: &str,
+    mut pick: impl FnMut(usize) -> usize,
+) -> Result<SampleResult> {
+    let data_size = data.len();
+    // 以下窗口布局都以元素为单位，未指定 element_size 时一个字节即一个元素
+    let element_size = options.element_size();
+    let element_count = super::element_count(data_size, target_size, element_size)?;
+
+    // 如果文件小于目标大小，返回全部数据
+    if element_count <= target_size {
+        return super::full_elements(data, element_size, cancel);
+    }
+
+    // 计算采样参数
+    let window_size = (target_size as f64).sqrt().floor() as usize;
+
+    // 确保 window_size 至少为 1，避免除以零
+    let requested_window_size = window_size.max(1);
 
-        // 计算采样参数
-        let window_size = (target_size as f64).sqrt().floor() as usize;
+    // 窗口数 target_size / window_size 超过上限时放大窗口；
+    // window_size = ceil(target_size / max_windows) 时 target_size / window_size <= max_windows
+    let window_size = if target_size / requested_window_size > max_windows {
+        target_size.div_ceil(max_windows)
+    } else {
+        requested_window_size
+    };
 
-        // 确保 window_size 至少为 1，避免除以零
-        let window_size = window_size.max(1);
+    // 对齐值以字节为单位。超过窗口覆盖的字节数时，窗口会成片挤到同一个对齐点上；
+    // 不是元素大小的整数倍时，对齐点落不到元素边界上
+    if let Some(alignment) = options.alignment {
+        if alignment > window_size * element_size {
+            return Err(AppError::BadRequest(format!(
+                "Alignment {} exceeds window size {}",
+                alignment,
+                window_size * element_size
+            )));
+        }
+        if alignment % element_size != 0 {
+            return Err(AppError::BadRequest(format!(
+                "Alignment {} is not a multiple of element size {}",
+                alignment, element_size
+            )));
+        }
+    }
 
-        let windows_count = target_size / window_size;
+    // window_size 不超过 target_size（放大后也是 ceil(target_size / max_windows)），所以至少有一个窗口
+    let windows_count = target_size / window_size;
 
-        // 如果计算出的窗口数为 0，说明目标大小太小，返回全部数据
-        if windows_count == 0 {
-            return Ok(SampleResult {
-                data: data.to_vec(),
-                metadata: SampleMetadata {
-                    original_size: data_size,
-                    sample_size: data_size,
-                    method: "full".to_string(),
-                },
-            });
+    // overlap 为 0 时将文件分为 windows_count 段，每段内随机放置一个窗口，
+    // 保证窗口都完整落在文件内
+    // （element_count > target_size >= windows_count * window_size，所以每段长度 >= window_size）
+    // overlap 为 o 时相邻窗口起点的间距为 step = window_size * (1 - o)，窗口连成一段
+    // (windows_count - 1) * step + window_size 长的区间，整段在文件内随机放置；
+    // 区间不长于 windows_count * window_size，同样完整落在文件内
+    let step = overlap_step(window_size, options.overlap);
+    let mut windows: Vec<usize> = match step {
+        None => (0..windows_count)
+            .map(|i| {
+                let segment_start = i * element_count / windows_count;
+                let segment_end = (i + 1) * element_count / windows_count;
+                segment_start + pick(segment_end - segment_start - window_size)
+            })
+            .collect(),
+        Some(step) => {
+            let span = (windows_count - 1) * step + window_size;
+            let start = pick(element_count - span);
+            (0..windows_count).map(|i| start + i * step).collect()
         }
+    };
 
-        // 生成随机窗口位置
-        let mut rng = thread_rng();
-        let max_offset = data_size.saturating_sub(windows_count * window_size);
-
-        // 如果 max_offset 为 0，说明数据大小刚好等于采样大小，返回全部数据
-        if max_offset == 0 && windows_count * window_size == data_size {
-            return Ok(SampleResult {
-                data: data.to_vec(),
-                metadata: SampleMetadata {
-                    original_size: data_size,
-                    sample_size: data_size,
-                    method: "full".to_string(),
-                },
-            });
+    // 放置后把窗口起点在文件中的字节偏移向下对齐到 alignment 的整数倍，再换算回元素下标，
+    // 这样采样范围的起点不是 alignment 的整数倍时窗口仍然落在文件的记录边界上。
+    // 对齐点落到范围起点之前时改用范围内的第一个对齐点（与原位置相距不到 alignment，不超过一个窗口）；
+    // 数据太短、放不下该位置的窗口时退回到最后一个放得下的位置，此时不再对齐。
+    // 这些调整都不改变窗口的先后顺序
+    if let Some(alignment) = options.alignment {
+        let base = options.range_start;
+        let last = element_count - window_size;
+        for offset in windows.iter_mut() {
+            let absolute = base + *offset * element_size;
+            let mut aligned = absolute - absolute % alignment;
+            if aligned < base {
+                aligned += alignment;
+            }
+            *offset = ((aligned - base) / element_size).min(last);
         }
+    }
 
-        let mut windows: Vec<usize> = (0..windows_count)
-            .map(|_| {
-                if max_offset == 0 {
-                    0
-                } else {
-                    rng.gen_range(0..=max_offset)
+    // 预先分配结果，每个窗口并行拷贝到各自互不重叠的位置，省去中间缓冲和串行合并
+    // 每个窗口拷贝前检查是否已取消
+    let mut result = vec![0u8; windows_count * window_size];
+    result
+        .par_chunks_mut(window_size)
+        .zip(windows.par_iter())
+        .try_for_each(|(dest, &offset)| {
+            super::check_cancelled(cancel)?;
+            if element_size == 1 {
+                dest.copy_from_slice(&data[offset..offset + window_size]);
+            } else {
+                for (i, byte) in dest.iter_mut().enumerate() {
+                    *byte = super::element_byte(data, offset + i, element_size);
                 }
-            })
-            .collect();
+            }
+            Ok::<_, AppError>(())
+        })?;
 
-        // 排序窗口位置
-        windows.sort_unstable();
+    let result_len = result.len();
 
-        // 调整位置避免重叠
-        for i in 0..windows_count {
-            windows[i] += i * window_size;
+    // 元数据中的偏移是字节偏移，窗口大小仍以元素（即结果中的字节）为单位
+    if element_size > 1 {
+        for offset in windows.iter_mut() {
+            *offset *= element_size;
         }
+    }
 
-        // 并行提取数据
-        let chunks: Vec<Vec<u8>> = windows
-            .par_iter()
-            .map(|&offset| {
-                let end = (offset + window_size).min(data_size);
-                data[offset..end].to_vec()
-            })
-            .collect();
+    let mut metadata = SampleMetadata::new(data_size, result_len, method);
+    metadata.window_size = Some(window_size);
+    metadata.offsets = Some(windows);
+    metadata.requested_window_size =
+        (window_size != requested_window_size).then_some(requested_window_size);
+    metadata.overlap = Some(options.overlap);
+    metadata.stride = step;
+    metadata.alignment = options.alignment;
+    metadata.element_size = options.element_size;
+
+    Ok(SampleResult {
+        data: result.into(),
+        metadata,
+    })
+}
 
-        // 合并数据
-        let mut result = Vec::with_capacity(target_size);
-        for chunk in chunks {
-            result.extend_from_slice(&chunk);
+// overlap 大于 0 时相邻窗口起点的间距（以元素为单位），至少为 1；overlap 为 0 时窗口按段放置，返回 None
+fn overlap_step(window_size: usize, overlap: f32) -> Option<usize> {
+    (overlap > 0.0).then(|| ((window_size as f64 * (1.0 - overlap as f64)) as usize).max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(data: &[u8], target_size: usize, options: &SampleOptions) -> SampleResult {
+        UniformSampler { max_windows: 1 << 20 }
+            .sample_cancellable(data, target_size, options, &AtomicBool::new(false))
+            .unwrap()
+    }
+
+    fn pattern(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 31 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn output_is_exactly_windows_count_times_window_size() {
+        // 文件只比目标大一点、质数大小、目标不是平方数等容易让最后一个窗口越界的组合
+        for (data_size, target_size) in [
+            (1001, 1000),
+            (1_000_003, 999_983),
+            (65_537, 4_097),
+            (1_048_576, 3),
+            (10_007, 10_006),
+            (7, 2),
+            (2_000_000, 1_000_001),
+        ] {
+            let data = pattern(data_size);
+            let result = sample(&data, target_size, &SampleOptions::default());
+            let window_size = result.metadata.window_size.unwrap();
+            let offsets = result.metadata.offsets.as_ref().unwrap();
+
+            assert_eq!(
+                result.data.len(),
+                offsets.len() * window_size,
+                "data {} target {}",
+                data_size,
+                target_size
+            );
+            assert_eq!(offsets.len(), target_size / window_size);
+            assert_eq!(result.metadata.stride, None);
+            for pair in offsets.windows(2) {
+                assert!(pair[0] + window_size <= pair[1], "windows overlap");
+            }
+            for (i, &offset) in offsets.iter().enumerate() {
+                assert!(offset + window_size <= data_size);
+                assert_eq!(
+                    &result.data[i * window_size..(i + 1) * window_size],
+                    &data[offset..offset + window_size]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn overlap_shortens_the_stride_between_window_starts() {
+        let data = pattern(1_000_000);
+        for overlap in [0.1f32, 0.5, 0.9] {
+            let options = SampleOptions {
+                overlap,
+                ..Default::default()
+            };
+            let result = sample(&data, 10_000, &options);
+            let window_size = result.metadata.window_size.unwrap();
+            let offsets = result.metadata.offsets.as_ref().unwrap();
+            let step = result.metadata.stride.unwrap();
+
+            assert_eq!(step, (window_size as f64 * (1.0 - overlap as f64)) as usize);
+            assert_eq!(result.data.len(), offsets.len() * window_size);
+            for pair in offsets.windows(2) {
+                assert_eq!(pair[1] - pair[0], step);
+                assert!(pair[1] - pair[0] < window_size, "overlap {}", overlap);
+            }
+            let last = *offsets.last().unwrap();
+            assert!(last + window_size <= data.len());
+            for (i, &offset) in offsets.iter().enumerate() {
+                assert_eq!(
+                    &result.data[i * window_size..(i + 1) * window_size],
+                    &data[offset..offset + window_size]
+                );
+            }
         }
+    }
+
+    #[test]
+    fn alignment_snaps_to_absolute_file_offsets() {
+        let data = pattern(1_000_000);
+        let sampler = UniformSampler { max_windows: 1 << 20 };
+        let cancel = AtomicBool::new(false);
+        // 范围起点不是对齐值的整数倍
+        for (range, element_size) in [(1_003..900_000, None), (4_100..999_001, Some(4))] {
+            let options = SampleOptions {
+                alignment: Some(16),
+                element_size,
+                ..Default::default()
+            };
+            let result = sampler
+                .sample_range(&data, range.clone(), 4_096, &options, &cancel)
+                .unwrap();
+            let window_size = result.metadata.window_size.unwrap();
+            let element_size = element_size.unwrap_or(1);
+            let offsets = result.metadata.offsets.as_ref().unwrap();
+
+            assert_eq!(result.data.len(), offsets.len() * window_size);
+            for pair in offsets.windows(2) {
+                assert!(pair[0] <= pair[1]);
+            }
+            for &offset in offsets {
+                assert_eq!(offset % 16, 0, "offset {} in range {:?}", offset, range);
+                assert!(offset >= range.start);
+                assert!(offset + window_size * element_size <= range.end);
+            }
+        }
+    }
+
+    #[test]
+    fn alignment_must_fit_window_and_elements() {
+        let data = pattern(100_000);
+        let cancel = AtomicBool::new(false);
+        let sampler = UniformSampler { max_windows: 1 << 20 };
+        // 采样大小 1024 时窗口为 32 个元素
+        for (alignment, element_size) in [(33, None), (6, Some(4)), (260, Some(8))] {
+            let options = SampleOptions {
+                alignment: Some(alignment),
+                element_size,
+                ..Default::default()
+            };
+            let result = sampler.sample_cancellable(&data, 1024, &options, &cancel);
+            assert!(matches!(result, Err(AppError::BadRequest(_))), "{}", alignment);
+        }
+    }
+
+    #[test]
+    fn max_windows_enlarges_windows_for_tiny_targets() {
+        let data = pattern(1_000_000);
+        let cancel = AtomicBool::new(false);
+        for max_windows in [1, 2, 3, 7] {
+            let sampler = UniformSampler { max_windows };
+            // 目标很小时窗口只有一两个字节，窗口数接近目标大小
+            for target_size in [1, 2, 3, 5, 8, 17, 100, 4_097] {
+                let result = sampler
+                    .sample_cancellable(&data, target_size, &SampleOptions::default(), &cancel)
+                    .unwrap();
+                let window_size = result.metadata.window_size.unwrap();
+                let offsets = result.metadata.offsets.as_ref().unwrap();
+                let context = format!("max_windows {} target {}", max_windows, target_size);
 
-        let result_len = result.len();
+                assert!(!offsets.is_empty(), "{}", context);
+                assert!(offsets.len() <= max_windows, "{}", context);
+                assert!(result.data.len() <= target_size, "{}", context);
+                assert_eq!(result.data.len(), offsets.len() * window_size, "{}", context);
 
-        Ok(SampleResult {
-            data: result,
-            metadata: SampleMetadata {
-                original_size: data_size,
-                sample_size: result_len,
-                method: "uniform".to_string(),
-            },
-        })
+                let requested = ((target_size as f64).sqrt().floor() as usize).max(1);
+                if target_size / requested > max_windows {
+                    assert_eq!(result.metadata.requested_window_size, Some(requested));
+                    assert!(window_size > requested, "{}", context);
+                } else {
+                    assert_eq!(result.metadata.requested_window_size, None);
+                    assert_eq!(window_size, requested);
+                }
+            }
+        }
     }
 }
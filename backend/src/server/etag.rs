@@ -0,0 +1,61 @@
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+// 文件按指纹寻址，同一文件 ID 的内容不会变化，相关响应可以使用强 ETag：
+// 文件信息直接用文件 ID，依赖查询参数的响应再附加参数的哈希
+pub struct ETag(String);
+
+impl ETag {
+    pub fn for_file(file_id: &str) -> Option<Self> {
+        Self::new(format!("\"{}\"", file_id))
+    }
+
+    pub fn for_query<T: Hash>(file_id: &str, query: &T) -> Option<Self> {
+        let mut hasher = DefaultHasher::new();
+        query.hash(&mut hasher);
+        Self::new(format!("\"{}-{:016x}\"", file_id, hasher.finish()))
+    }
+
+    // 内容等价但字节不完全相同的响应（如带有采样耗时的元数据）使用弱 ETag
+    pub fn weak(self) -> Self {
+        Self(format!("W/{}", self.0))
+    }
+
+    // 文件 ID 含有 ETag 不允许的字符时不生成 ETag
+    fn new(tag: String) -> Option<Self> {
+        let inner = &tag[1..tag.len() - 1];
+        let valid = inner
+            .bytes()
+            .all(|b| b == 0x21 || (0x23..=0x7e).contains(&b));
+        valid.then_some(Self(tag))
+    }
+
+    // If-None-Match 使用弱比较，且可以是逗号分隔的列表或 *
+    fn matches(&self, headers: &HeaderMap) -> bool {
+        let opaque = self.0.strip_prefix("W/").unwrap_or(&self.0);
+        headers
+            .get_all(header::IF_NONE_MATCH)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == opaque)
+    }
+}
+
+// 客户端缓存仍然有效时返回 304
+pub fn not_modified(etag: &Option<ETag>, headers: &HeaderMap) -> Option<Response> {
+    let etag = etag.as_ref().filter(|etag| etag.matches(headers))?;
+    Some(with_etag(Some(etag), StatusCode::NOT_MODIFIED))
+}
+
+// 给响应加上 ETag 头
+pub fn with_etag(etag: Option<&ETag>, response: impl IntoResponse) -> Response {
+    let mut response = response.into_response();
+    if let Some(value) = etag.and_then(|etag| HeaderValue::from_str(&etag.0).ok()) {
+        response.headers_mut().insert(header::ETAG, value);
+    }
+    response
+}
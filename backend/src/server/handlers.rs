@@ -1,28 +1,173 @@
-use crate::config::Config;
-use crate::core::{Cache, FileManager, Sampler};
+use crate::config::{Config, Secret};
+use crate::core::sampler::{SampleOptions, SampleResult};
+use crate::core::analysis;
+use crate::core::file_manager::{is_sha1_hex, FileInfo, FileListOptions, FileSort};
+use crate::core::cache::{self, CacheStats};
+use crate::core::decompress::Compression;
+use crate::core::fetch::FetchPolicy;
+use crate::core::render::{Colormap, ImageFormat};
+use crate::core::{Cache, DiskCache, FileManager};
 use crate::error::{AppError, Result};
-use crate::sampling::UniformSampler;
+use crate::protocol::{Encoding, SampleRequest};
+use crate::sampling::{self, SamplerRegistry};
+use crate::server::etag::{self, ETag};
+use crate::server::history::{RecentSample, SampleHistory};
+use crate::server::idempotency::{Claim, IdempotencyKeys};
+use crate::server::rate_limit::RateLimiter;
+use crate::server::{routes, websocket};
 use axum::{
-    extract::{Extension, Multipart, Path},
-    response::IntoResponse,
+    extract::{ConnectInfo, Extension, Multipart, Path, Request},
+    http::{HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
     Json,
 };
 use base64::Engine;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tower_http::services::ServeFile;
+use tracing::{info, instrument};
 
 pub struct AppState {
     pub config: Config,
     pub file_manager: Arc<FileManager>,
-    pub cache: Arc<Cache>,
+    pub cache: Arc<Cache<SampleResult>>,
+    // 文件级分析结果的缓存
+    pub histogram_cache: Arc<Cache<[u64; 256]>>,
+    pub entropy_cache: Arc<Cache<Vec<f32>>>,
+    // 编码好的 PNG 渲染图
+    pub render_cache: Arc<Cache<Vec<u8>>>,
+    // 累计接收的上传字节数
+    pub uploaded_bytes: AtomicU64,
+    // 当前活跃的 WebSocket 连接数
+    pub active_connections: AtomicUsize,
+    // 服务器关闭信号，各连接的取消令牌都派生自它
+    pub shutdown: CancellationToken,
+    // 采样使用的有界线程池，并发请求共享固定数量的线程
+    pub sampler_pool: Arc<rayon::ThreadPool>,
+    // HTTP 采样接口的按 IP 限流
+    pub sample_limiter: RateLimiter,
+    // 可用的采样方法
+    pub samplers: SamplerRegistry,
+    // 每个文件最近的采样参数
+    pub sample_history: SampleHistory,
+    // 已完成上传的幂等键
+    pub upload_keys: IdempotencyKeys<UploadResponse>,
+    // /api/upload/from-url 允许拉取的 URL
+    pub fetch_policy: FetchPolicy,
 }
 
-#[derive(Debug, Serialize)]
+impl AppState {
+    // 按配置创建缓存（配置了 CACHE_DIR 时启用磁盘二级缓存）、采样线程池等全部状态
+    pub fn new(config: Config) -> anyhow::Result<Self> {
+        let mut cache = Cache::new(config.cache_size).with_policy(config.cache_policy);
+        if let Some(cache_dir) = &config.cache_dir {
+            cache = cache.with_disk(DiskCache::new(cache_dir.clone(), config.cache_disk_size)?);
+            info!("Disk cache enabled at {:?}", cache_dir);
+        }
+
+        // 各类分析结果使用独立的缓存，大的熵分布或渲染图不会挤掉采样结果
+        let histogram_cache =
+            Cache::new(config.cache_size_analysis).with_policy(config.cache_policy);
+        let entropy_cache = Cache::new(config.cache_size_analysis).with_policy(config.cache_policy);
+        let render_cache = Cache::new(config.cache_size_analysis).with_policy(config.cache_policy);
+
+        // 采样专用线程池，与 tokio 运行时和全局 rayon 线程池隔离
+        let sampler_pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(config.sampler_threads)
+            .thread_name(|i| format!("sampler-{}", i))
+            .build()?;
+
+        Ok(Self {
+            file_manager: Arc::new(FileManager::new(
+                config.upload_dir.clone(),
+                config.max_file_size,
+            )),
+            cache: Arc::new(cache),
+            histogram_cache: Arc::new(histogram_cache),
+            entropy_cache: Arc::new(entropy_cache),
+            render_cache: Arc::new(render_cache),
+            uploaded_bytes: AtomicU64::new(0),
+            active_connections: AtomicUsize::new(0),
+            shutdown: CancellationToken::new(),
+            sampler_pool: Arc::new(sampler_pool),
+            sample_limiter: RateLimiter::new(config.sample_rate_limit),
+            samplers: SamplerRegistry::with_builtin(config.max_windows),
+            sample_history: SampleHistory::new(),
+            upload_keys: IdempotencyKeys::new(std::time::Duration::from_secs(
+                config.idempotency_ttl_secs,
+            )),
+            fetch_policy: FetchPolicy::new(
+                config.fetch_allowed_schemes.clone(),
+                config.fetch_allowed_hosts.clone(),
+            ),
+            config,
+        })
+    }
+
+    // 文件被删除时失效它在各个缓存中的条目，并删除它的采样记录和指向它的幂等键
+    pub fn invalidate_file(&self, file_id: &str) {
+        self.cache.invalidate_file(file_id);
+        self.histogram_cache.invalidate_file(file_id);
+        self.entropy_cache.invalidate_file(file_id);
+        self.render_cache.invalidate_file(file_id);
+        self.sample_history.forget(file_id);
+        self.upload_keys.forget_file(file_id);
+    }
+
+    // 清空全部缓存
+    pub fn clear_caches(&self) {
+        self.cache.clear();
+        self.histogram_cache.clear();
+        self.entropy_cache.clear();
+        self.render_cache.clear();
+    }
+
+    // 整个文件的字节直方图，每个文件只扫描一次，之后从缓存读取
+    pub async fn file_histogram(&self, file_id: &str) -> Result<[u64; 256]> {
+        let file_size = self.file_manager.file_size(file_id).await?;
+        let key = cache::make_analysis_key(file_id, "histogram", 0, file_size);
+        if let Some(counts) = self.histogram_cache.get(&key) {
+            return Ok(counts);
+        }
+
+        let file_manager = self.file_manager.clone();
+        let id = file_id.to_string();
+        let started = Instant::now();
+        let counts = self.run_on_pool(move || file_manager.histogram(&id)).await??;
+        self.histogram_cache.put(key, counts, started.elapsed());
+        Ok(counts)
+    }
+
+    // 在采样线程池中执行 CPU 密集的任务，等待期间不占用异步运行时的工作线程
+    pub async fn run_on_pool<T, F>(&self, task: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let pool = self.sampler_pool.clone();
+        tokio::task::spawn_blocking(move || pool.install(task))
+            .await
+            .map_err(|e| AppError::Internal(e.into()))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct UploadResponse {
     file_id: String,
     filename: String,
     size: usize,
+    // 表单中文件字段以外的文本字段，如客户端声明的文件名和指纹
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    fields: BTreeMap<String, String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -36,35 +181,83 @@ pub struct CheckFingerprintResponse {
     file_id: Option<String>,
 }
 
-// 检查文件指纹是否已存在
+// 检查文件指纹是否已存在：指纹可以是文件 ID，也可以是上传时随文件提交的客户端指纹
 pub async fn check_fingerprint(
     Extension(state): Extension<Arc<AppState>>,
     axum::extract::Query(req): axum::extract::Query<CheckFingerprintRequest>,
 ) -> Result<Json<CheckFingerprintResponse>> {
-    let exists = state.file_manager.check_fingerprint(&req.fingerprint).await;
-
-    if exists {
-        Ok(Json(CheckFingerprintResponse {
-            exists: true,
-            file_id: Some(req.fingerprint),
-        }))
-    } else {
-        Ok(Json(CheckFingerprintResponse {
-            exists: false,
-            file_id: None,
-        }))
+    let file_id = state.file_manager.check_fingerprint(&req.fingerprint).await;
+    // 客户端确认文件存在后会跳过上传直接使用，刷新修改时间以免随即被过期清理删除
+    if let Some(file_id) = &file_id {
+        state.file_manager.touch(file_id);
     }
+
+    Ok(Json(CheckFingerprintResponse {
+        exists: file_id.is_some(),
+        file_id,
+    }))
 }
 
+// 客户端重试上传时携带与首次相同的键，服务器返回首次上传的结果
+const IDEMPOTENCY_KEY: &str = "idempotency-key";
+
 pub async fn upload_file(
     Extension(state): Extension<Arc<AppState>>,
-    mut multipart: Multipart,
+    axum::extract::Query(query): axum::extract::Query<UploadQuery>,
+    headers: HeaderMap,
+    multipart: Multipart,
 ) -> Result<Json<UploadResponse>> {
-    let mut filename = String::new();
-    let mut data: Option<bytes::Bytes> = None;
-    let mut fingerprint: Option<String> = None;
+    let key = headers
+        .get(IDEMPOTENCY_KEY)
+        .filter(|_| state.upload_keys.enabled())
+        .map(|value| {
+            value.to_str().map_err(|_| {
+                AppError::BadRequest("Idempotency-Key must be visible ASCII".to_string())
+            })
+        })
+        .transpose()?;
+    let guard = match key {
+        Some(key) => match state.upload_keys.claim(key)? {
+            Claim::Replay(response) => {
+                info!(
+                    "Idempotency-Key {} already completed, returning file {}",
+                    key, response.file_id
+                );
+                return Ok(Json(response));
+            }
+            Claim::Started(guard) => Some(guard),
+        },
+        None => None,
+    };
+
+    // 上传失败时 guard 被丢弃，释放该键，重试会重新处理
+    let response = receive_upload(&state, query, multipart).await?;
+    if let Some(guard) = guard {
+        guard.complete(&response.file_id, response.clone());
+    }
+    Ok(Json(response))
+}
 
-    // 解析 multipart 数据
+async fn receive_upload(
+    state: &AppState,
+    query: UploadQuery,
+    mut multipart: Multipart,
+) -> Result<UploadResponse> {
+    let compression = query
+        .decompress
+        .as_deref()
+        .map(|name| {
+            Compression::parse(name).ok_or_else(|| {
+                AppError::BadRequest(format!("Unsupported compression: {}", name))
+            })
+        })
+        .transpose()?;
+
+    let mut filename: Option<String> = None;
+    let mut fields: BTreeMap<String, String> = BTreeMap::new();
+    let mut saved: Option<(String, usize)> = None;
+
+    // 解析 multipart 数据：文件字段按配置的名称识别，其余字段作为文本收集
     while let Some(field) = multipart
         .next_field()
         .await
@@ -72,107 +265,1716 @@ pub async fn upload_file(
     {
         let field_name = field.name().unwrap_or("").to_string();
 
-        match field_name.as_str() {
-            "file" => {
-                filename = field.file_name().unwrap_or("unknown").to_string();
-                data = Some(
-                    field
-                        .bytes()
-                        .await
-                        .map_err(|e| AppError::BadRequest(e.to_string()))?,
-                );
+        if field_name == state.config.upload_field {
+            if saved.is_some() {
+                return Err(AppError::BadRequest(format!(
+                    "Multiple '{}' fields provided",
+                    field_name
+                )));
             }
-            "fingerprint" => {
-                fingerprint = Some(
-                    field
-                        .text()
-                        .await
-                        .map_err(|e| AppError::BadRequest(e.to_string()))?,
-                );
-            }
-            _ => {}
+            filename = field.file_name().map(str::to_string);
+
+            // 文件 ID 总是边写盘边计算的内容摘要；先于文件到达的指纹只用于登记上传进度
+            let progress_key = fields.get("fingerprint").map(String::as_str);
+            saved = Some(match compression {
+                Some(compression) => {
+                    let stream = state
+                        .file_manager
+                        .decompress_stream(field, compression)
+                        .await?;
+                    state
+                        .file_manager
+                        .save_stream_hashed(stream, progress_key)
+                        .await?
+                }
+                None => {
+                    state
+                        .file_manager
+                        .save_stream_hashed(field, progress_key)
+                        .await?
+                }
+            });
+        } else if !field_name.is_empty() {
+            let value = field
+                .text()
+                .await
+                .map_err(|e| AppError::BadRequest(e.to_string()))?;
+            fields.insert(field_name, value);
+        }
+    }
+
+    // 客户端声明的文件名优先于文件字段自带的文件名
+    let filename = fields.get("name").cloned().or(filename);
+
+    let (file_id, size) = saved.ok_or_else(|| {
+        AppError::BadRequest(format!("No '{}' field provided", state.config.upload_field))
+    })?;
+    // SHA-1 形式的指纹视为声明的内容摘要，必须与计算结果一致；其它形式的指纹（如前端按文件名、
+    // 大小和修改时间生成的指纹）无法对照内容，只作为进度查询的键
+    if let Some(declared) = fields.get("fingerprint").filter(|fp| is_sha1_hex(fp)) {
+        if !declared.eq_ignore_ascii_case(&file_id) {
+            return Err(AppError::BadRequest(format!(
+                "Fingerprint {} does not match content ({})",
+                declared, file_id
+            )));
         }
     }
+    state
+        .file_manager
+        .write_meta(&file_id, filename.as_deref(), size, Some(&file_id))
+        .await;
+    if let Some(fingerprint) = fields.get("fingerprint") {
+        state
+            .file_manager
+            .record_fingerprint(fingerprint, &file_id)
+            .await;
+    }
+    state
+        .uploaded_bytes
+        .fetch_add(size as u64, Ordering::Relaxed);
+
+    Ok(UploadResponse {
+        file_id,
+        filename: filename.unwrap_or_else(|| "unknown".to_string()),
+        size,
+        fields,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UploadFromUrlRequest {
+    url: String,
+    // 文件名，默认取 URL 路径的最后一段
+    name: Option<String>,
+}
+
+// 由服务器拉取 URL 指向的文件，与直接上传一样边接收边按内容摘要保存，
+// 客户端不必先下载再上传；URL 的协议和主机必须在 FETCH_ALLOWED_SCHEMES、FETCH_ALLOWED_HOSTS 之内
+#[instrument(skip_all, fields(url = %request.url))]
+pub async fn upload_from_url(
+    Extension(state): Extension<Arc<AppState>>,
+    Json(request): Json<UploadFromUrlRequest>,
+) -> Result<Json<UploadResponse>> {
+    if !state.fetch_policy.enabled() {
+        return Err(AppError::BadRequest(
+            "Uploading from URLs is disabled (FETCH_ALLOWED_HOSTS is empty)".to_string(),
+        ));
+    }
+
+    let remote = state.fetch_policy.open(&request.url).await?;
+    // 声明的长度超过限制时不必开始接收；未声明长度时由 save_stream_hashed 在接收过程中检查
+    if let Some(len) = remote.content_length {
+        if len > state.config.max_file_size as u64 {
+            return Err(AppError::FileTooLarge(len as usize));
+        }
+    }
+    let filename = request.name.or_else(|| remote.filename());
+    let final_url = remote.url.clone();
+
+    let (file_id, size) = state
+        .file_manager
+        .save_stream_hashed(remote.into_stream(), None)
+        .await?;
+    state
+        .file_manager
+        .write_meta(&file_id, filename.as_deref(), size, Some(&file_id))
+        .await;
+    state
+        .uploaded_bytes
+        .fetch_add(size as u64, Ordering::Relaxed);
+    info!("Fetched {} as {} ({} bytes)", final_url, file_id, size);
+
+    Ok(Json(UploadResponse {
+        file_id,
+        filename: filename.unwrap_or_else(|| "unknown".to_string()),
+        size,
+        fields: BTreeMap::new(),
+    }))
+}
 
-    let data = data.ok_or_else(|| AppError::BadRequest("No file provided".to_string()))?;
-    let fingerprint =
-        fingerprint.ok_or_else(|| AppError::BadRequest("No fingerprint provided".to_string()))?;
+#[derive(Debug, Deserialize)]
+pub struct InitUploadRequest {
+    fingerprint: String,
+    filename: Option<String>,
+    size: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InitUploadResponse {
+    upload_id: String,
+}
+
+// 创建可续传上传
+pub async fn init_upload(
+    Extension(state): Extension<Arc<AppState>>,
+    Json(request): Json<InitUploadRequest>,
+) -> Result<Json<InitUploadResponse>> {
+    let upload_id = state
+        .file_manager
+        .create_upload(&request.fingerprint, request.filename, request.size)
+        .await?;
+
+    Ok(Json(InitUploadResponse { upload_id }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UploadQuery {
+    // 上传数据的压缩格式（gzip 或 zstd），保存解压后的内容
+    decompress: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UploadChunkQuery {
+    offset: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadChunkResponse {
+    upload_id: String,
+    received: usize,
+}
+
+// 追加一个分块，重发已接收的分块不会重复写入
+#[instrument(skip_all, fields(upload_id = %upload_id, offset = query.offset))]
+pub async fn upload_chunk(
+    Extension(state): Extension<Arc<AppState>>,
+    Path(upload_id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<UploadChunkQuery>,
+    body: bytes::Bytes,
+) -> Result<Json<UploadChunkResponse>> {
+    let received = state
+        .file_manager
+        .append_upload(&upload_id, query.offset, &body)
+        .await?;
+
+    Ok(Json(UploadChunkResponse {
+        upload_id,
+        received,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CompleteUploadRequest {
+    fingerprint: String,
+}
 
-    // 保存文件（使用指纹作为文件名）
-    let file_id = state.file_manager.save_file(&data, &fingerprint).await?;
+// 完成可续传上传
+#[instrument(skip_all, fields(upload_id = %upload_id))]
+pub async fn complete_upload(
+    Extension(state): Extension<Arc<AppState>>,
+    Path(upload_id): Path<String>,
+    Json(request): Json<CompleteUploadRequest>,
+) -> Result<Json<UploadResponse>> {
+    let (file_id, filename, size) = state
+        .file_manager
+        .complete_upload(&upload_id, &request.fingerprint)
+        .await?;
+    // 文件 ID 即完成时计算的内容摘要
+    state
+        .file_manager
+        .write_meta(&file_id, filename.as_deref(), size, Some(&file_id))
+        .await;
+    state
+        .uploaded_bytes
+        .fetch_add(size as u64, Ordering::Relaxed);
 
     Ok(Json(UploadResponse {
         file_id,
-        filename,
-        size: data.len(),
+        filename: filename.unwrap_or_else(|| "unknown".to_string()),
+        size,
+        fields: BTreeMap::new(),
     }))
 }
 
+#[derive(Debug, Serialize)]
+pub struct UploadStatusResponse {
+    file_id: String,
+    status: &'static str,
+    bytes_written: u64,
+}
+
+// 查询上传进度：uploading 表示仍在写入，complete 表示文件已就绪
+pub async fn upload_status(
+    Extension(state): Extension<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<UploadStatusResponse>> {
+    if let Some(bytes_written) = state.file_manager.upload_progress(&id) {
+        return Ok(Json(UploadStatusResponse {
+            file_id: id,
+            status: "uploading",
+            bytes_written,
+        }));
+    }
+
+    // 上传完成后文件以内容摘要为 ID，按指纹查询时返回实际的文件 ID
+    let file_id = state
+        .file_manager
+        .check_fingerprint(&id)
+        .await
+        .ok_or(AppError::FileNotFound(id))?;
+    let info = state.file_manager.get_file_info(&file_id).await?;
+    Ok(Json(UploadStatusResponse {
+        file_id,
+        status: "complete",
+        bytes_written: info.size as u64,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListFilesQuery {
+    offset: Option<usize>,
+    limit: Option<usize>,
+    sort: Option<String>,
+    order: Option<String>,
+    #[serde(rename = "type")]
+    file_type: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileListResponse {
+    files: Vec<FileInfo>,
+    total: usize,
+    offset: usize,
+    limit: usize,
+}
+
+// 文件列表单页最多返回的文件数
+const MAX_LIST_LIMIT: usize = 1000;
+
+// 分页列出上传文件，可按类型筛选、按创建时间或大小排序，默认最新上传的在前、每页 100 个
+pub async fn list_files(
+    Extension(state): Extension<Arc<AppState>>,
+    axum::extract::Query(query): axum::extract::Query<ListFilesQuery>,
+) -> Result<Json<FileListResponse>> {
+    let offset = query.offset.unwrap_or(0);
+    let limit = query.limit.unwrap_or(100);
+    if limit > MAX_LIST_LIMIT {
+        return Err(AppError::BadRequest(format!(
+            "limit must not exceed {}",
+            MAX_LIST_LIMIT
+        )));
+    }
+
+    let sort = match query.sort.as_deref().unwrap_or("created") {
+        "created" => FileSort::Created,
+        "size" => FileSort::Size,
+        other => return Err(AppError::BadRequest(format!("Unknown sort key: {}", other))),
+    };
+    let descending = match query.order.as_deref().unwrap_or("desc") {
+        "desc" => true,
+        "asc" => false,
+        other => return Err(AppError::BadRequest(format!("Unknown sort order: {}", other))),
+    };
+
+    let options = FileListOptions {
+        sort,
+        descending,
+        file_type: query.file_type,
+        offset,
+        limit,
+    };
+    let (files, total) = state.file_manager.list_files(&options).await?;
+    Ok(Json(FileListResponse {
+        files,
+        total,
+        offset,
+        limit,
+    }))
+}
+
+#[instrument(skip_all, fields(file_id = %id))]
 pub async fn get_file_info(
     Extension(state): Extension<Arc<AppState>>,
     Path(id): Path<String>,
-) -> Result<Json<crate::core::FileInfo>> {
+    headers: HeaderMap,
+) -> Result<Response> {
     let info = state.file_manager.get_file_info(&id).await?;
-    Ok(Json(info))
+
+    let etag = ETag::for_file(&id);
+    if let Some(response) = etag::not_modified(&etag, &headers) {
+        return Ok(response);
+    }
+    Ok(etag::with_etag(etag.as_ref(), Json(info)))
 }
 
+// 最近对该文件请求过的采样参数，最近的在前
+pub async fn recent_samples(
+    Extension(state): Extension<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>> {
+    state.file_manager.file_size(&id).await?;
+
+    Ok(Json(json!({
+        "file_id": id,
+        "recent": state.sample_history.recent(&id),
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchInfoRequest {
+    ids: Vec<String>,
+}
+
+// 批量查询中每个 ID 的结果：文件信息，或与单个查询相同格式的错误
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum BatchInfoEntry {
+    Found(FileInfo),
+    Error { error: String, code: u16 },
+}
+
+// 单次批量查询最多包含的 ID 数
+const MAX_BATCH_INFO_IDS: usize = 1000;
+
+// 批量获取文件信息，各 ID 并发查询，返回 ID 到结果的映射；重复的 ID 只出现一次
+pub async fn batch_file_info(
+    Extension(state): Extension<Arc<AppState>>,
+    Json(request): Json<BatchInfoRequest>,
+) -> Result<Json<BTreeMap<String, BatchInfoEntry>>> {
+    if request.ids.len() > MAX_BATCH_INFO_IDS {
+        return Err(AppError::BadRequest(format!(
+            "At most {} ids per request",
+            MAX_BATCH_INFO_IDS
+        )));
+    }
+
+    let mut ids = request.ids;
+    ids.sort();
+    ids.dedup();
+    let results = futures::future::join_all(ids.iter().map(|id| async {
+        state.file_manager.get_file_info(id).await
+    }))
+    .await;
+
+    let entries = ids
+        .into_iter()
+        .zip(results)
+        .map(|(id, result)| {
+            let entry = match result {
+                Ok(info) => BatchInfoEntry::Found(info),
+                Err(e) => BatchInfoEntry::Error {
+                    code: e.status_code().as_u16(),
+                    error: e.to_string(),
+                },
+            };
+            (id, entry)
+        })
+        .collect();
+    Ok(Json(entries))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchDeleteRequest {
+    ids: Vec<String>,
+}
+
+// 批量删除中每个 ID 的结果，错误与单个删除的格式相同
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum BatchDeleteEntry {
+    Deleted { deleted: bool },
+    Error { error: String, code: u16 },
+}
+
+// 单次批量删除最多包含的 ID 数
+const MAX_BATCH_DELETE_IDS: usize = 1000;
+
+// 批量删除文件并使各自的缓存失效，返回 ID 到结果的映射；
+// 某个 ID 不存在或删除失败只记录在该 ID 的结果中，不影响其它 ID
+pub async fn batch_delete_files(
+    Extension(state): Extension<Arc<AppState>>,
+    Json(request): Json<BatchDeleteRequest>,
+) -> Result<Json<BTreeMap<String, BatchDeleteEntry>>> {
+    if request.ids.len() > MAX_BATCH_DELETE_IDS {
+        return Err(AppError::BadRequest(format!(
+            "At most {} ids per request",
+            MAX_BATCH_DELETE_IDS
+        )));
+    }
+
+    let mut ids = request.ids;
+    ids.sort();
+    ids.dedup();
+    let results = futures::future::join_all(ids.iter().map(|id| async {
+        state.file_manager.delete_file(id).await?;
+        state.invalidate_file(id);
+        Ok::<_, AppError>(())
+    }))
+    .await;
+
+    let deleted = results.iter().filter(|result| result.is_ok()).count();
+    info!("Batch delete removed {} of {} files", deleted, ids.len());
+
+    let entries = ids
+        .into_iter()
+        .zip(results)
+        .map(|(id, result)| {
+            let entry = match result {
+                Ok(()) => BatchDeleteEntry::Deleted { deleted: true },
+                Err(e) => BatchDeleteEntry::Error {
+                    code: e.status_code().as_u16(),
+                    error: e.to_string(),
+                },
+            };
+            (id, entry)
+        })
+        .collect();
+    Ok(Json(entries))
+}
+
+// 下载原始文件，支持 Range 请求（断点续传、部分读取），有记录时用上传时的文件名
+#[instrument(skip_all, fields(file_id = %id))]
+pub async fn download_file(
+    Extension(state): Extension<Arc<AppState>>,
+    Path(id): Path<String>,
+    request: Request,
+) -> Result<Response> {
+    let path = state.file_manager.stored_path(&id).await?;
+    let filename = state
+        .file_manager
+        .original_filename(&id)
+        .await
+        .unwrap_or_else(|| id.clone());
+
+    let mut response = ServeFile::new(path)
+        .try_call(request)
+        .await
+        .map_err(AppError::FileAccess)?
+        .map(axum::body::Body::new);
+
+    // 文件 ID 可能带有扩展名，不按扩展名猜测类型
+    let headers = response.headers_mut();
+    headers.insert(
+        axum::http::header::CONTENT_TYPE,
+        axum::http::HeaderValue::from_static("application/octet-stream"),
+    );
+    if let Ok(value) = axum::http::HeaderValue::from_str(&content_disposition(&filename)) {
+        headers.insert(axum::http::header::CONTENT_DISPOSITION, value);
+    }
+    Ok(response)
+}
+
+// attachment 头：filename 为替换掉非 ASCII 和引号的后备名称，filename* 为 RFC 5987 编码的原名
+fn content_disposition(filename: &str) -> String {
+    let fallback: String = filename
+        .chars()
+        .map(|c| match c {
+            ' '..='~' if c != '"' && c != '\\' => c,
+            _ => '_',
+        })
+        .collect();
+
+    let mut encoded = String::new();
+    for byte in filename.bytes() {
+        if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            let _ = write!(encoded, "%{:02X}", byte);
+        }
+    }
+
+    format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        fallback, encoded
+    )
+}
+
+#[instrument(skip_all, fields(file_id = %id))]
 pub async fn delete_file(
     Extension(state): Extension<Arc<AppState>>,
     Path(id): Path<String>,
 ) -> Result<Json<serde_json::Value>> {
     state.file_manager.delete_file(&id).await?;
+    state.invalidate_file(&id);
     Ok(Json(json!({
         "message": "File deleted successfully"
     })))
 }
 
+// 删除全部上传文件并清空缓存（需要管理令牌）
+pub async fn delete_all_files(
+    Extension(state): Extension<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>> {
+    authorize_admin(&state.config, &headers)?;
+
+    let (removed_files, freed_bytes) = state.file_manager.delete_all().await?;
+    state.clear_caches();
+    state.sample_history.clear();
+    state.upload_keys.clear();
+    info!(
+        "Admin cleanup removed {} files ({} bytes)",
+        removed_files, freed_bytes
+    );
+
+    Ok(Json(json!({
+        "removed_files": removed_files,
+        "freed_bytes": freed_bytes,
+    })))
+}
+
+// 清空采样缓存（需要管理令牌），返回清空前的统计
+pub async fn clear_cache(
+    Extension(state): Extension<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<CacheStats>> {
+    authorize_admin(&state.config, &headers)?;
+    Ok(Json(clear_cache_now(&state)))
+}
+
+// HTTP 和 WebSocket 共用
+pub fn clear_cache_now(state: &AppState) -> CacheStats {
+    let stats = state.cache.stats();
+    state.clear_caches();
+    info!("Cache cleared ({} entries, {} bytes)", stats.entries, stats.total_size);
+    stats
+}
+
 #[derive(Debug, Deserialize)]
-pub struct SampleRequestBody {
-    sample_size: usize,
-    #[allow(dead_code)]
-    method: Option<String>,
+pub struct CacheCapacityRequest {
+    capacity: usize,
+}
+
+// 运行时调整采样缓存的容量（需要管理令牌），缩小时立即淘汰超出的条目；重启后恢复为 CACHE_SIZE_SAMPLES
+pub async fn set_cache_capacity(
+    Extension(state): Extension<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<CacheCapacityRequest>,
+) -> Result<Json<serde_json::Value>> {
+    authorize_admin(&state.config, &headers)?;
+    if request.capacity == 0 {
+        return Err(AppError::BadRequest(
+            "Cache capacity must be greater than 0".to_string(),
+        ));
+    }
+
+    let evicted = state.cache.set_capacity(request.capacity);
+    let stats = state.cache.stats();
+    info!(
+        "Sample cache capacity set to {} bytes, evicted {} entries",
+        stats.capacity, evicted
+    );
+
+    Ok(Json(json!({
+        "evicted": evicted,
+        "stats": stats,
+    })))
+}
+
+// 校验 `Authorization: Bearer <token>`
+fn authorize_admin(config: &Config, headers: &HeaderMap) -> Result<()> {
+    check_admin_token(config, bearer_token(headers))
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+// 校验管理令牌，未配置管理令牌时一律拒绝
+pub fn check_admin_token(config: &Config, provided: Option<&str>) -> Result<()> {
+    let expected = config.admin_token.as_ref().ok_or(AppError::Unauthorized)?;
+    let provided = provided.ok_or(AppError::Unauthorized)?;
+
+    if !tokens_match(expected, provided) {
+        return Err(AppError::Unauthorized);
+    }
+
+    Ok(())
+}
+
+// 逐字节比较全部内容，耗时与第一个不同字节的位置无关
+fn tokens_match(expected: &Secret, provided: &str) -> bool {
+    let expected = expected.expose().as_bytes();
+    let provided = provided.as_bytes();
+    let diff = expected
+        .iter()
+        .zip(provided)
+        .fold(0, |acc, (a, b)| acc | (a ^ b));
+    diff == 0 && expected.len() == provided.len()
+}
+
+// 上传、删除等修改数据的接口的中间件：配置了 API_TOKEN 时要求 `Authorization: Bearer <token>`
+pub async fn require_api_token(
+    Extension(state): Extension<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response> {
+    if let Some(expected) = &state.config.api_token {
+        let provided = bearer_token(request.headers()).ok_or(AppError::Unauthorized)?;
+        if !tokens_match(expected, provided) {
+            return Err(AppError::Unauthorized);
+        }
+    }
+
+    Ok(next.run(request).await)
 }
 
 #[derive(Debug, Serialize)]
 pub struct SampleResponse {
     data: String,
     size: usize,
+    compute_ms: u64,
+    cached: bool,
+    // 请求 class_summary 时附带，class_summary 为 base64 编码的分类 ID
+    #[serde(skip_serializing_if = "Option::is_none")]
+    class_window: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    class_summary: Option<String>,
+}
+
+const MSGPACK_CONTENT_TYPE: &str = "application/msgpack";
+
+// POST /api/sample/:id 支持的响应格式（format 参数）
+const SAMPLE_FORMATS: [&str; 3] = ["json", "binary", "msgpack"];
+
+#[derive(Debug, Deserialize)]
+pub struct SampleFormatQuery {
+    format: Option<String>,
 }
 
+// 默认返回 base64 JSON；format=binary 时直接返回原始字节，省去 base64 约 33% 的膨胀
+// format=msgpack 或 `Accept: application/msgpack` 时返回与 WebSocket 相同编码（struct-map）的完整 SampleResult
 pub async fn sample_file(
     Extension(state): Extension<Arc<AppState>>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
     Path(id): Path<String>,
-    Json(request): Json<SampleRequestBody>,
-) -> Result<Json<SampleResponse>> {
+    axum::extract::Query(query): axum::extract::Query<SampleFormatQuery>,
+    headers: HeaderMap,
+    Json(request): Json<SampleRequest>,
+) -> Result<Response> {
+    if !state.sample_limiter.try_acquire(client.ip()) {
+        return Err(AppError::TooManyRequests("rate limited".to_string()));
+    }
+    // 显式的 format 参数优先于 Accept 头
+    let accepts_msgpack = headers
+        .get_all(axum::http::header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media| media.split(';').next().unwrap_or("").trim() == MSGPACK_CONTENT_TYPE);
+    let format = match query.format.as_deref() {
+        Some(format) => format,
+        None if accepts_msgpack => "msgpack",
+        None => "json",
+    };
+    // 二进制响应只有采样数据本身，放不下分类汇总
+    if format == "binary" && request.class_summary {
+        return Err(AppError::BadRequest(
+            "class_summary is not supported with the binary format".to_string(),
+        ));
+    }
+
+    let result = perform_sampling(&state, &id, &request).await?;
+    if format == "msgpack" {
+        let mut body = Vec::new();
+        result
+            .serialize(&mut rmp_serde::Serializer::new(&mut body).with_struct_map())
+            .map_err(|e| AppError::Internal(e.into()))?;
+        return Ok((
+            [(axum::http::header::CONTENT_TYPE, MSGPACK_CONTENT_TYPE)],
+            body,
+        )
+            .into_response());
+    }
+
+    let data = result.data;
+    let metadata = result.metadata;
+
+    match format {
+        "json" => Ok(Json(SampleResponse {
+            data: base64::engine::general_purpose::STANDARD.encode(&data),
+            size: data.len(),
+            compute_ms: metadata.compute_ms,
+            cached: metadata.cached,
+            class_window: metadata.class_window,
+            class_summary: metadata
+                .class_summary
+                .map(|summary| base64::engine::general_purpose::STANDARD.encode(summary)),
+        })
+        .into_response()),
+        // 二进制响应没有 JSON 外壳，耗时和缓存命中放在响应头里
+        "binary" => Ok((
+            [
+                (
+                    axum::http::header::CONTENT_TYPE,
+                    "application/octet-stream".to_string(),
+                ),
+                (
+                    axum::http::HeaderName::from_static("x-compute-ms"),
+                    metadata.compute_ms.to_string(),
+                ),
+                (
+                    axum::http::HeaderName::from_static("x-cache"),
+                    if metadata.cached { "hit" } else { "miss" }.to_string(),
+                ),
+            ],
+            data,
+        )
+            .into_response()),
+        other => Err(AppError::BadRequest(format!(
+            "Unknown response format: {}",
+            other
+        ))),
+    }
+}
+
+// 执行采样（带缓存），HTTP 和 WebSocket 共用
+#[instrument(skip_all, fields(file_id = %file_id, sample_size = request.sample_size))]
+pub async fn perform_sampling(
+    state: &Arc<AppState>,
+    file_id: &str,
+    request: &SampleRequest,
+) -> Result<SampleResult> {
+    let result = sample_cached(state, file_id, request).await?;
+    state.sample_history.record(
+        file_id,
+        RecentSample {
+            sample_size: request.sample_size,
+            method: request.method.as_deref().unwrap_or("uniform").to_string(),
+        },
+    );
+    if !request.classify && !request.class_summary {
+        return Ok(result);
+    }
+
+    // 分类和分类汇总是对任意采样结果的后处理，缓存中只保存原始字节；
+    // 两者都请求时在同一个线程池任务中完成，汇总按原始字节计算
+    let SampleResult { data, mut metadata } = result;
+    let classify = request.classify;
+    let class_window = request
+        .class_summary
+        .then(|| metadata.window_size.unwrap_or(CLASS_SUMMARY_WINDOW).max(1));
+    let (data, summary) = state
+        .run_on_pool(move || {
+            let summary = class_window.map(|window| analysis::class_summary(&data, window));
+            let data = if classify {
+                analysis::classify(&data).into()
+            } else {
+                data
+            };
+            (data, summary)
+        })
+        .await?;
+    if classify {
+        metadata.encoding = Some("class".to_string());
+    }
+    metadata.class_window = class_window;
+    metadata.class_summary = summary;
+    Ok(SampleResult { data, metadata })
+}
+
+// 离开作用域时置位取消标志；采样正常完成后置位没有影响
+struct CancelOnDrop(Arc<AtomicBool>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+// 没有采样窗口的方法（以及直接返回原文件时）按该字节数汇总分类
+const CLASS_SUMMARY_WINDOW: usize = 256;
+
+async fn sample_cached(
+    state: &Arc<AppState>,
+    file_id: &str,
+    request: &SampleRequest,
+) -> Result<SampleResult> {
+    let sample_size = request.sample_size;
+    let method = request.method.as_deref().unwrap_or("uniform");
+
+    info!("========== Performing Sampling ==========");
+    info!("  Method: {}", method);
+
     // 验证采样大小
-    if request.sample_size > state.config.max_sample_size {
-        return Err(AppError::InvalidSampleSize(request.sample_size));
+    if sample_size > state.config.max_sample_size {
+        return Err(AppError::InvalidSampleSize(sample_size));
+    }
+
+    // 先确认文件仍然存在，已删除文件的残留缓存不能再被返回
+    let file_size = state.file_manager.file_size(file_id).await?;
+    let range = sample_range(request, file_size)?;
+    if let Some(range) = &range {
+        info!("  Range: {}..{}", range.start, range.end);
+    }
+
+    let options = sample_options(file_id, request)?;
+    let sampler = state.samplers.get(Some(method))?;
+
+    // 检查缓存；文件长度在采样后改变（追加写入或被外部截断）时缓存的结果已经过期，重新采样
+    let cache_key = cache::make_key(file_id, sample_size, method, range.as_ref(), &options);
+    if let Some(mut cached) = state.cache.get_current(&cache_key, file_size) {
+        info!("  Cache HIT - returning cached data");
+        info!("  Cached data size: {} bytes", cached.data.len());
+        info!("  First 16 bytes: {:02x?}", &cached.data[..16.min(cached.data.len())]);
+        cached.metadata.compute_ms = 0;
+        cached.metadata.cached = true;
+        return Ok(cached);
+    }
+
+    info!("  Cache MISS - performing new sampling");
+
+    let started = Instant::now();
+
+    // 内存映射文件
+    let mmap = state.file_manager.mmap_file(file_id)?;
+    info!("  File mapped, size: {} bytes", mmap.len());
+
+    // 等待采样的 future 被丢弃（WebSocket 请求被取消、HTTP 客户端断开、服务器关闭）时通知线程池中的采样尽早结束
+    let cancel = Arc::new(AtomicBool::new(false));
+    let _cancel_on_drop = CancelOnDrop(cancel.clone());
+
+    // 采样范围不大于目标大小时直接引用映射，无需经过采样器拷贝
+    let extent = range.clone().unwrap_or(0..mmap.len());
+    sampling::ensure_sampleable(extent.len(), sample_size)?;
+    let mut result = if extent.len() <= sample_size
+        && options.element_size.is_none()
+        && sampler.passthrough_small()
+    {
+        SampleResult::mapped(mmap, extent)
+    } else {
+        // 在采样线程池中执行
+        // 排队等待线程池期间文件可能被截断，切片读取之前再检查一次
+        state
+            .run_on_pool(move || {
+                mmap.ensure_intact()?;
+                match range {
+                    Some(range) => {
+                        sampler.sample_range(&mmap, range, sample_size, &options, &cancel)
+                    }
+                    None => sampler.sample_cancellable(&mmap, sample_size, &options, &cancel),
+                }
+            })
+            .await??
+    };
+    let elapsed = started.elapsed();
+    result.metadata.compute_ms = elapsed.as_millis() as u64;
+    info!(
+        "  Sampling complete in {:?}, result size: {} bytes",
+        elapsed,
+        result.data.len()
+    );
+    info!("  First 16 bytes: {:02x?}", &result.data[..16.min(result.data.len())]);
+
+    // 更新缓存
+    info!("  Data cached with key: {}", cache_key.disk_name());
+    state.cache.put(cache_key, result.clone(), elapsed);
+
+    Ok(result)
+}
+
+// 解析请求的采样范围，要求 range_start < range_end <= 文件大小；覆盖整个文件时返回 None
+fn sample_range(request: &SampleRequest, file_size: usize) -> Result<Option<Range<usize>>> {
+    if request.range_start.is_none() && request.range_end.is_none() {
+        return Ok(None);
+    }
+
+    let start = request.range_start.unwrap_or(0);
+    let end = request.range_end.unwrap_or(file_size);
+    if start >= end || end > file_size {
+        return Err(AppError::BadRequest(format!(
+            "Invalid range {}..{} for file of {} bytes",
+            start, end, file_size
+        )));
+    }
+
+    if start == 0 && end == file_size {
+        return Ok(None);
     }
+    Ok(Some(start..end))
+}
+
+// 校验并收集采样参数
+fn sample_options(file_id: &str, request: &SampleRequest) -> Result<SampleOptions> {
+    let overlap = request.overlap.unwrap_or(0.0);
+    if !(0.0..=0.9).contains(&overlap) {
+        return Err(AppError::BadRequest(format!(
+            "Overlap must be between 0.0 and 0.9, got {}",
+            overlap
+        )));
+    }
+
+    // 是否超过窗口大小由采样器判断，窗口大小取决于采样大小
+    if request.alignment == Some(0) {
+        return Err(AppError::BadRequest(
+            "Alignment must be greater than 0".to_string(),
+        ));
+    }
+
+    // 1 与不指定相同，不单独缓存
+    let element_size = match request.element_size {
+        None | Some(1) => None,
+        Some(size @ (2 | 4 | 8)) => Some(size),
+        Some(size) => {
+            return Err(AppError::BadRequest(format!(
+                "Element size must be 1, 2, 4 or 8, got {}",
+                size
+            )))
+        }
+    };
 
-    // 检查缓存
-    let cache_key = Cache::make_key(&id, request.sample_size);
+    // 网格是否放得下由采样器判断，默认行数取决于采样大小
+    if request.row_width.is_some_and(|width| !width.is_power_of_two()) {
+        return Err(AppError::BadRequest(
+            "Row width must be a power of two".to_string(),
+        ));
+    }
+    if request.rows == Some(0) {
+        return Err(AppError::BadRequest(
+            "Rows must be greater than 0".to_string(),
+        ));
+    }
+
+    Ok(SampleOptions {
+        overlap,
+        alignment: request.alignment,
+        range_start: 0,
+        seed: sampling::golden::seed_for(file_id),
+        element_size,
+        row_width: request.row_width,
+        rows: request.rows,
+    })
+}
 
-    let data = if let Some(cached) = state.cache.get(cache_key) {
-        cached
+// 只返回采样元数据，不传输采样字节
+pub async fn sample_metadata(
+    Extension(state): Extension<Arc<AppState>>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    Path(id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<SampleRequest>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    // 先确认文件存在，再用参数决定 ETag，命中时无需采样；
+    // 随机采样（uniform 及以它为基础的 gradient）每次结果不同，不生成 ETag
+    state.file_manager.file_size(&id).await?;
+    let sampler = state.samplers.get(query.method.as_deref())?;
+    let etag = if sampler.deterministic() {
+        ETag::for_query(
+            &id,
+            &(
+                query.sample_size,
+                &query.method,
+                query.range_start,
+                query.range_end,
+                query.overlap.map(f32::to_bits),
+                query.alignment,
+                query.element_size,
+                query.row_width,
+                query.rows,
+                query.classify,
+                query.class_summary,
+            ),
+        )
+        .map(ETag::weak)
     } else {
-        let mmap = state.file_manager.mmap_file(&id)?;
-        let sampler = UniformSampler;
-        let result = sampler.sample(mmap, request.sample_size)?;
+        None
+    };
+    if let Some(response) = etag::not_modified(&etag, &headers) {
+        return Ok(response);
+    }
+
+    // 304 不需要采样，不计入限流
+    if !state.sample_limiter.try_acquire(client.ip()) {
+        return Err(AppError::TooManyRequests("rate limited".to_string()));
+    }
+    let result = perform_sampling(&state, &id, &query).await?;
+    Ok(etag::with_etag(etag.as_ref(), Json(result.metadata)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ZeroRunsQuery {
+    min_run: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ZeroRun {
+    offset: usize,
+    length: usize,
+}
+
+// 全零区间（填充、稀疏区域），默认只报告不短于 4KB 的区间
+#[instrument(skip_all, fields(file_id = %id))]
+pub async fn zero_runs(
+    Extension(state): Extension<Arc<AppState>>,
+    Path(id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<ZeroRunsQuery>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let min_run = query.min_run.unwrap_or(4096);
+    if min_run == 0 {
+        return Err(AppError::BadRequest(
+            "min_run must be greater than 0".to_string(),
+        ));
+    }
+
+    state.file_manager.file_size(&id).await?;
+    let etag = ETag::for_query(&id, &("zero-runs", min_run));
+    if let Some(response) = etag::not_modified(&etag, &headers) {
+        return Ok(response);
+    }
+
+    let file_manager = state.file_manager.clone();
+    let file_id = id.clone();
+    let runs = state
+        .run_on_pool(move || file_manager.zero_runs(&file_id, min_run))
+        .await??;
+
+    let runs: Vec<ZeroRun> = runs
+        .into_iter()
+        .map(|(offset, length)| ZeroRun { offset, length })
+        .collect();
+
+    let body = Json(json!({
+        "file_id": id,
+        "min_run": min_run,
+        "runs": runs,
+    }));
+    Ok(etag::with_etag(etag.as_ref(), body))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AutocorrQuery {
+    max_lag: Option<usize>,
+    sample: Option<usize>,
+}
+
+// 计算量约为 max_lag × sample，两者都有上限
+const MAX_AUTOCORR_LAG: usize = 1024;
+const MAX_AUTOCORR_SAMPLE: usize = 4 * 1024 * 1024;
+
+// 字节序列的自相关，峰值所在的延迟通常是记录长度或步长，供前端推荐 alignment 等参数
+// 默认延迟 1–256，大文件取约 1MB 的均匀分布的连续块计算
+#[instrument(skip_all, fields(file_id = %id))]
+pub async fn autocorrelation(
+    Extension(state): Extension<Arc<AppState>>,
+    Path(id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<AutocorrQuery>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let max_lag = query.max_lag.unwrap_or(256);
+    let sample = query.sample.unwrap_or(1024 * 1024);
+    if max_lag == 0 || max_lag > MAX_AUTOCORR_LAG {
+        return Err(AppError::BadRequest(format!(
+            "max_lag must be between 1 and {}",
+            MAX_AUTOCORR_LAG
+        )));
+    }
+    if sample == 0 || sample > MAX_AUTOCORR_SAMPLE {
+        return Err(AppError::BadRequest(format!(
+            "sample must be between 1 and {}",
+            MAX_AUTOCORR_SAMPLE
+        )));
+    }
+
+    state.file_manager.file_size(&id).await?;
+    let etag = ETag::for_query(&id, &("autocorr", max_lag, sample));
+    if let Some(response) = etag::not_modified(&etag, &headers) {
+        return Ok(response);
+    }
+
+    let file_manager = state.file_manager.clone();
+    let file_id = id.clone();
+    let values = state
+        .run_on_pool(move || file_manager.autocorrelation(&file_id, max_lag, sample))
+        .await??;
 
-        state.cache.put(cache_key, result.data.clone());
-        result.data
+    // 系数最大的延迟，没有正相关时为 null
+    let peak_lag = values
+        .iter()
+        .enumerate()
+        .filter(|(_, &value)| value > 0.0)
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(index, _)| index + 1);
+
+    let body = Json(json!({
+        "file_id": id,
+        "max_lag": max_lag,
+        "sample": sample,
+        "peak_lag": peak_lag,
+        "values": values,
+    }));
+    Ok(etag::with_etag(etag.as_ref(), body))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SectionsQuery {
+    window_size: Option<usize>,
+    compressed_entropy: Option<f32>,
+    text_entropy: Option<f32>,
+    text_highbit: Option<f32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Section {
+    start: usize,
+    end: usize,
+    kind: &'static str,
+}
+
+// 区段划分的窗口：默认 4KB，大文件放大到 2 的幂使窗口数不超过上限
+const DEFAULT_SECTION_WINDOW: usize = 4096;
+const MIN_SECTION_WINDOW: usize = 256;
+const MAX_SECTION_WINDOWS: usize = 1 << 20;
+
+// 文件结构概览：按窗口熵和高位字节比例划分为 zero、text、code、compressed 区段
+#[instrument(skip_all, fields(file_id = %id))]
+pub async fn file_sections(
+    Extension(state): Extension<Arc<AppState>>,
+    Path(id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<SectionsQuery>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let thresholds = analysis::SectionThresholds {
+        compressed_entropy: query.compressed_entropy.unwrap_or(7.2),
+        text_entropy: query.text_entropy.unwrap_or(5.5),
+        text_highbit: query.text_highbit.unwrap_or(0.05),
+    };
+    if !(0.0..=8.0).contains(&thresholds.compressed_entropy)
+        || !(0.0..=8.0).contains(&thresholds.text_entropy)
+    {
+        return Err(AppError::BadRequest(
+            "Entropy thresholds must be between 0 and 8".to_string(),
+        ));
+    }
+    if !(0.0..=1.0).contains(&thresholds.text_highbit) {
+        return Err(AppError::BadRequest(
+            "text_highbit must be between 0 and 1".to_string(),
+        ));
+    }
+
+    let file_size = state.file_manager.file_size(&id).await?;
+    let window_size = match query.window_size {
+        Some(window_size) if window_size < MIN_SECTION_WINDOW => {
+            return Err(AppError::BadRequest(format!(
+                "window_size must be at least {}",
+                MIN_SECTION_WINDOW
+            )))
+        }
+        Some(window_size) if file_size.div_ceil(window_size) > MAX_SECTION_WINDOWS => {
+            return Err(AppError::BadRequest(format!(
+                "window_size too small: {} windows exceeds the limit of {}",
+                file_size.div_ceil(window_size),
+                MAX_SECTION_WINDOWS
+            )))
+        }
+        Some(window_size) => window_size,
+        None => file_size
+            .div_ceil(MAX_SECTION_WINDOWS)
+            .next_power_of_two()
+            .max(DEFAULT_SECTION_WINDOW),
     };
 
-    Ok(Json(SampleResponse {
-        data: base64::engine::general_purpose::STANDARD.encode(&data),
-        size: data.len(),
+    let etag = ETag::for_query(
+        &id,
+        &(
+            "sections",
+            window_size,
+            thresholds.compressed_entropy.to_bits(),
+            thresholds.text_entropy.to_bits(),
+            thresholds.text_highbit.to_bits(),
+        ),
+    );
+    if let Some(response) = etag::not_modified(&etag, &headers) {
+        return Ok(response);
+    }
+
+    let file_manager = state.file_manager.clone();
+    let file_id = id.clone();
+    let sections = state
+        .run_on_pool(move || file_manager.sections(&file_id, window_size, &thresholds))
+        .await??;
+
+    let sections: Vec<Section> = sections
+        .into_iter()
+        .map(|(start, end, kind)| Section {
+            start,
+            end,
+            kind: kind.name(),
+        })
+        .collect();
+
+    let body = Json(json!({
+        "file_id": id,
+        "window_size": window_size,
+        "sections": sections,
+    }));
+    Ok(etag::with_etag(etag.as_ref(), body))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QuantilesQuery {
+    q: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QuantilesResponse {
+    file_id: String,
+    q: Vec<f32>,
+    values: Vec<u8>,
+}
+
+// 单次请求最多计算的分位数个数
+const MAX_QUANTILES: usize = 256;
+
+// 字节值分位数，q 为逗号分隔的 0–1 之间的小数，默认 0.01,0.99
+// 前端把 [p1, p99] 映射到 [0, 255] 即可拉伸灰度渲染的对比度；分位数由缓存的直方图得出
+#[instrument(skip_all, fields(file_id = %id))]
+pub async fn byte_quantiles(
+    Extension(state): Extension<Arc<AppState>>,
+    Path(id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<QuantilesQuery>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let qs = parse_quantiles(query.q.as_deref().unwrap_or("0.01,0.99"))?;
+
+    state.file_manager.file_size(&id).await?;
+    let bits: Vec<u32> = qs.iter().map(|q| q.to_bits()).collect();
+    let etag = ETag::for_query(&id, &("quantiles", &bits));
+    if let Some(response) = etag::not_modified(&etag, &headers) {
+        return Ok(response);
+    }
+
+    let counts = state.file_histogram(&id).await?;
+    let values = analysis::quantiles_from_histogram(&counts, &qs);
+
+    let body = Json(QuantilesResponse {
+        file_id: id,
+        q: qs,
+        values,
+    });
+    Ok(etag::with_etag(etag.as_ref(), body))
+}
+
+fn parse_quantiles(list: &str) -> Result<Vec<f32>> {
+    let qs = list
+        .split(',')
+        .map(|q| {
+            q.trim()
+                .parse::<f32>()
+                .ok()
+                .filter(|q| (0.0..=1.0).contains(q))
+                .ok_or_else(|| {
+                    AppError::BadRequest(format!("Invalid quantile {:?}, expected 0 to 1", q))
+                })
+        })
+        .collect::<Result<Vec<f32>>>()?;
+    if qs.len() > MAX_QUANTILES {
+        return Err(AppError::BadRequest(format!(
+            "at most {} quantiles per request",
+            MAX_QUANTILES
+        )));
+    }
+    Ok(qs)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ThumbnailQuery {
+    width: Option<usize>,
+    height: Option<usize>,
+    mode: Option<String>,
+}
+
+// 缩略图的最大边长
+const MAX_THUMBNAIL_DIM: usize = 4096;
+
+// 固定尺寸的缩略图：文件按行优先顺序映射到 width×height 个单元格，每个单元格一个字节
+// mode=mean（默认）为字节均值，mode=class 为出现最多的字节分类（与 classify 的编号一致）
+#[instrument(skip_all, fields(file_id = %id))]
+pub async fn thumbnail(
+    Extension(state): Extension<Arc<AppState>>,
+    Path(id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<ThumbnailQuery>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let (width, height) = thumbnail_dims(query.width, query.height)?;
+    let dominant_class = match query.mode.as_deref().unwrap_or("mean") {
+        "mean" => false,
+        "class" => true,
+        other => {
+            return Err(AppError::BadRequest(format!(
+                "Unknown thumbnail mode: {}",
+                other
+            )))
+        }
+    };
+
+    state.file_manager.file_size(&id).await?;
+    let etag = ETag::for_query(&id, &("thumbnail", width, height, dominant_class));
+    if let Some(response) = etag::not_modified(&etag, &headers) {
+        return Ok(response);
+    }
+
+    let file_manager = state.file_manager.clone();
+    let file_id = id.clone();
+    let cells = state
+        .run_on_pool(move || file_manager.thumbnail(&file_id, width * height, dominant_class))
+        .await??;
+
+    let response = (
+        [
+            (
+                axum::http::header::CONTENT_TYPE,
+                "application/octet-stream".to_string(),
+            ),
+            (
+                axum::http::HeaderName::from_static("x-thumbnail-width"),
+                width.to_string(),
+            ),
+            (
+                axum::http::HeaderName::from_static("x-thumbnail-height"),
+                height.to_string(),
+            ),
+        ],
+        cells,
+    );
+    Ok(etag::with_etag(etag.as_ref(), response))
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VerifyStatus {
+    Valid,
+    Corrupted,
+    // 上传时没有记录内容摘要，无法判断
+    Unverifiable,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VerifyResponse {
+    status: VerifyStatus,
+    // 无法判断时为 null
+    valid: Option<bool>,
+    computed: String,
+    recorded: Option<String>,
+}
+
+// 重新计算已存储文件的内容 SHA-1 并与上传时记录的摘要比较，用于发现磁盘上的静默损坏
+// 文件 ID 可能是客户端的指纹而不是内容摘要，因此不与文件 ID 比较
+#[instrument(skip_all, fields(file_id = %id))]
+pub async fn verify_file(
+    Extension(state): Extension<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<VerifyResponse>> {
+    state.file_manager.file_size(&id).await?;
+    let recorded = state.file_manager.recorded_digest(&id).await;
+
+    let file_manager = state.file_manager.clone();
+    let file_id = id.clone();
+    let computed = state
+        .run_on_pool(move || file_manager.content_digest(&file_id))
+        .await??;
+
+    let status = match &recorded {
+        None => VerifyStatus::Unverifiable,
+        Some(recorded) if recorded.eq_ignore_ascii_case(&computed) => VerifyStatus::Valid,
+        Some(recorded) => {
+            tracing::warn!(
+                "Stored content does not match its recorded digest {} (computed {})",
+                recorded,
+                computed
+            );
+            VerifyStatus::Corrupted
+        }
+    };
+    let valid = match status {
+        VerifyStatus::Valid => Some(true),
+        VerifyStatus::Corrupted => Some(false),
+        VerifyStatus::Unverifiable => None,
+    };
+
+    Ok(Json(VerifyResponse {
+        status,
+        valid,
+        computed,
+        recorded,
     }))
 }
 
-pub async fn health_check() -> impl IntoResponse {
-    Json(json!({
-        "status": "healthy",
+#[derive(Debug, Deserialize)]
+pub struct SuggestSampleQuery {
+    target_pixels: Option<usize>,
+}
+
+// 根据渲染区域大小推荐 sample_size 和采样方法，只使用文件大小和类型，不扫描文件内容
+// 默认渲染区域为 1024×1024
+#[instrument(skip_all, fields(file_id = %id))]
+pub async fn suggest_sample(
+    Extension(state): Extension<Arc<AppState>>,
+    Path(id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<SuggestSampleQuery>,
+) -> Result<Json<sampling::Suggestion>> {
+    let target_pixels = query.target_pixels.unwrap_or(1024 * 1024);
+    if target_pixels == 0 {
+        return Err(AppError::BadRequest(
+            "target_pixels must be greater than 0".to_string(),
+        ));
+    }
+
+    let file_size = state.file_manager.file_size(&id).await?;
+    sampling::ensure_sampleable(file_size, target_pixels)?;
+    let file_type = state.file_manager.detect_type(&id)?;
+
+    Ok(Json(sampling::suggest(
+        file_size,
+        file_type.as_deref(),
+        target_pixels,
+        state.config.max_sample_size,
+    )))
+}
+
+// 校验缩略图尺寸，默认 256×256
+fn thumbnail_dims(width: Option<usize>, height: Option<usize>) -> Result<(usize, usize)> {
+    let width = width.unwrap_or(256);
+    let height = height.unwrap_or(256);
+    for (name, value) in [("width", width), ("height", height)] {
+        if !(1..=MAX_THUMBNAIL_DIM).contains(&value) {
+            return Err(AppError::BadRequest(format!(
+                "{} must be between 1 and {}",
+                name, MAX_THUMBNAIL_DIM
+            )));
+        }
+    }
+    Ok((width, height))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RenderQuery {
+    width: Option<usize>,
+    height: Option<usize>,
+    colormap: Option<String>,
+    format: Option<String>,
+}
+
+// 未指定 format 时按 Accept 协商：客户端接受 image/webp（q 不为 0）时返回体积更小的 WebP，否则返回 PNG
+fn render_format(format: Option<&str>, headers: &HeaderMap) -> Result<ImageFormat> {
+    if let Some(name) = format {
+        return ImageFormat::parse(name)
+            .ok_or_else(|| AppError::BadRequest(format!("Unknown image format: {}", name)));
+    }
+
+    let accepts_webp = headers
+        .get_all(axum::http::header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|item| {
+            let mut parts = item.split(';').map(str::trim);
+            parts.next() == Some("image/webp")
+                && !parts.any(|param| {
+                    param
+                        .strip_prefix("q=")
+                        .and_then(|q| q.parse::<f32>().ok())
+                        == Some(0.0)
+                })
+        });
+    Ok(if accepts_webp {
+        ImageFormat::Webp
+    } else {
+        ImageFormat::Png
+    })
+}
+
+// 把缩略图网格按颜色映射渲染为 PNG 或无损 WebP，可以直接用 <img> 显示
+// grayscale 和 viridis 使用字节均值，byte-class 使用出现最多的字节分类；每种格式分别缓存
+#[instrument(skip_all, fields(file_id = %id))]
+pub async fn render_image(
+    Extension(state): Extension<Arc<AppState>>,
+    Path(id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<RenderQuery>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let (width, height) = thumbnail_dims(query.width, query.height)?;
+    let colormap_name = query.colormap.as_deref().unwrap_or("grayscale");
+    let colormap = Colormap::parse(colormap_name).ok_or_else(|| {
+        AppError::BadRequest(format!("Unknown colormap: {}", colormap_name))
+    })?;
+    let format = render_format(query.format.as_deref(), &headers)?;
+
+    let file_size = state.file_manager.file_size(&id).await?;
+    let etag = ETag::for_query(&id, &("render", width, height, colormap, format));
+    if let Some(response) = etag::not_modified(&etag, &headers) {
+        return Ok(response);
+    }
+
+    let cache_key = cache::make_render_key(
+        &id,
+        &format!("render-{}-{}", colormap.name(), format.name()),
+        width,
+        height,
+        file_size,
+    );
+    let image = match state.render_cache.get(&cache_key) {
+        Some(image) => image,
+        None => {
+            let file_manager = state.file_manager.clone();
+            let file_id = id.clone();
+            let started = Instant::now();
+            let image = state
+                .run_on_pool(move || {
+                    let dominant_class = colormap == Colormap::ByteClass;
+                    let cells = file_manager.thumbnail(&file_id, width * height, dominant_class)?;
+                    Ok::<_, AppError>(format.encode(&cells, width, height, colormap))
+                })
+                .await??;
+            state.render_cache.put(cache_key, image.clone(), started.elapsed());
+            image
+        }
+    };
+
+    // 同一 URL 的格式可能随 Accept 变化，提示中间缓存按 Accept 区分
+    let response = (
+        [
+            (axum::http::header::CONTENT_TYPE, format.content_type()),
+            (axum::http::header::VARY, "Accept"),
+        ],
+        image,
+    );
+    Ok(etag::with_etag(etag.as_ref(), response))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StringsQuery {
+    min_len: Option<usize>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExtractedString {
+    offset: usize,
+    text: String,
+}
+
+// 单次请求最多返回的字符串数
+const MAX_STRINGS_LIMIT: usize = 100_000;
+
+// 类似 strings 命令的字符串提取，默认最短 4 个字符、最多 1000 个
+#[instrument(skip_all, fields(file_id = %id))]
+pub async fn extract_strings(
+    Extension(state): Extension<Arc<AppState>>,
+    Path(id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<StringsQuery>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let min_len = query.min_len.unwrap_or(4);
+    let limit = query.limit.unwrap_or(1000);
+    if min_len == 0 {
+        return Err(AppError::BadRequest(
+            "min_len must be greater than 0".to_string(),
+        ));
+    }
+    if limit > MAX_STRINGS_LIMIT {
+        return Err(AppError::BadRequest(format!(
+            "limit must not exceed {}",
+            MAX_STRINGS_LIMIT
+        )));
+    }
+
+    state.file_manager.file_size(&id).await?;
+    let etag = ETag::for_query(&id, &("strings", min_len, limit));
+    if let Some(response) = etag::not_modified(&etag, &headers) {
+        return Ok(response);
+    }
+
+    let file_manager = state.file_manager.clone();
+    let file_id = id.clone();
+    let strings = state
+        .run_on_pool(move || file_manager.extract_strings(&file_id, min_len, limit))
+        .await??;
+
+    let strings: Vec<ExtractedString> = strings
+        .into_iter()
+        .map(|(offset, text)| ExtractedString { offset, text })
+        .collect();
+
+    let body = Json(json!({
+        "file_id": id,
+        "min_len": min_len,
+        "limit": limit,
+        "strings": strings,
+    }));
+    Ok(etag::with_etag(etag.as_ref(), body))
+}
+
+// 可用作存活/就绪探针：确认上传目录可写、各缓存可以加锁读取统计，检查失败时返回 503
+pub async fn health_check(Extension(state): Extension<Arc<AppState>>) -> Response {
+    let disk = match state.file_manager.check_writable().await {
+        Ok(()) => json!({ "status": "ok" }),
+        Err(e) => {
+            tracing::warn!("Health check failed: {}", e);
+            json!({ "status": "error", "error": e.to_string() })
+        }
+    };
+    let healthy = disk["status"] == "ok";
+
+    // 缓存的锁被毒化时会清空后恢复（见 Cache::lock_store），能读到统计即视为正常
+    let (entries, total_size) = [
+        state.cache.stats(),
+        state.histogram_cache.stats(),
+        state.entropy_cache.stats(),
+        state.render_cache.stats(),
+    ]
+    .iter()
+    .fold((0, 0), |(entries, size), stats| {
+        (entries + stats.entries, size + stats.total_size)
+    });
+
+    let body = Json(json!({
+        "status": if healthy { "healthy" } else { "unhealthy" },
         "timestamp": chrono::Utc::now().to_rfc3339(),
+        "checks": {
+            "disk": disk,
+            "cache": { "status": "ok", "entries": entries, "total_size": total_size },
+        },
+    }));
+
+    let status = if healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, body).into_response()
+}
+
+// 服务器支持的功能，前端据此启用或禁用界面控件；
+// 采样方法来自采样器注册表，分析接口来自路由表，限制来自配置，不需要另外维护
+pub async fn capabilities(Extension(state): Extension<Arc<AppState>>) -> Json<serde_json::Value> {
+    let config = &state.config;
+    Json(json!({
+        "sampling_methods": state.samplers.methods(),
+        "analyses": routes::analysis_names(),
+        "ws_commands": websocket::COMMANDS,
+        "limits": {
+            "max_file_size": config.max_file_size,
+            "max_sample_size": config.max_sample_size,
+            "max_windows": config.max_windows,
+            "min_chunk_size": websocket::MIN_CHUNK_SIZE,
+            "max_chunk_size": websocket::MAX_CHUNK_SIZE,
+        },
+        "encodings": {
+            "messages": Encoding::ALL.map(Encoding::name),
+            "sample_formats": SAMPLE_FORMATS,
+            "upload_decompress": Compression::ALL.map(Compression::name),
+            "image_formats": ImageFormat::ALL.map(ImageFormat::name),
+            "colormaps": Colormap::ALL.map(Colormap::name),
+        },
+    }))
+}
+
+// 构建信息：版本号来自 Cargo.toml，提交、构建时间和 feature 由 build.rs 注入
+pub async fn version() -> Json<serde_json::Value> {
+    let features: Vec<&str> = env!("BUILD_FEATURES")
+        .split(',')
+        .filter(|feature| !feature.is_empty())
+        .collect();
+
+    Json(json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_sha": env!("BUILD_GIT_SHA"),
+        "build_time": env!("BUILD_TIME"),
+        "features": features,
     }))
 }
 
@@ -180,10 +1982,339 @@ pub async fn get_metrics(Extension(state): Extension<Arc<AppState>>) -> impl Int
     let cache_stats = state.cache.stats();
 
     Json(json!({
+        "active_connections": state.active_connections.load(Ordering::Relaxed),
+        "uploaded_bytes": state.uploaded_bytes.load(Ordering::Relaxed),
         "cache_usage": {
             "entries": cache_stats.entries,
             "total_size": cache_stats.total_size,
             "capacity": cache_stats.capacity,
+            "hits": cache_stats.hits,
+            "misses": cache_stats.misses,
+            "hit_rate": cache_stats.hit_rate,
         }
     }))
 }
+
+// Prometheus 文本格式的指标
+pub async fn get_prometheus_metrics(
+    Extension(state): Extension<Arc<AppState>>,
+) -> impl IntoResponse {
+    let cache_stats = state.cache.stats();
+
+    let metrics: [(&str, &str, &str, f64); 8] = [
+        (
+            "binaryvis_cache_entries",
+            "gauge",
+            "Number of entries in the sample cache",
+            cache_stats.entries as f64,
+        ),
+        (
+            "binaryvis_cache_size_bytes",
+            "gauge",
+            "Bytes currently held by the sample cache",
+            cache_stats.total_size as f64,
+        ),
+        (
+            "binaryvis_cache_capacity_bytes",
+            "gauge",
+            "Configured sample cache capacity in bytes",
+            cache_stats.capacity as f64,
+        ),
+        (
+            "binaryvis_cache_hits_total",
+            "counter",
+            "Total sample cache hits",
+            cache_stats.hits as f64,
+        ),
+        (
+            "binaryvis_cache_misses_total",
+            "counter",
+            "Total sample cache misses",
+            cache_stats.misses as f64,
+        ),
+        (
+            "binaryvis_cache_hit_ratio",
+            "gauge",
+            "Sample cache hit ratio since startup",
+            cache_stats.hit_rate,
+        ),
+        (
+            "binaryvis_uploaded_bytes_total",
+            "counter",
+            "Total bytes received through uploads",
+            state.uploaded_bytes.load(Ordering::Relaxed) as f64,
+        ),
+        (
+            "binaryvis_active_connections",
+            "gauge",
+            "Currently open WebSocket connections",
+            state.active_connections.load(Ordering::Relaxed) as f64,
+        ),
+    ];
+
+    let mut body = String::new();
+    for (name, kind, help, value) in metrics {
+        let _ = writeln!(body, "# HELP {} {}", name, help);
+        let _ = writeln!(body, "# TYPE {} {}", name, kind);
+        let _ = writeln!(body, "{} {}", name, value);
+    }
+
+    (
+        [(
+            axum::http::header::CONTENT_TYPE,
+            "text/plain; version=0.0.4",
+        )],
+        body,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::server::testing::{
+        body_json, json_request, serve_http, test_data, upload_request,
+        upload_request_with_fields, TestApp,
+    };
+    use axum::{body::Body, http::Request, http::StatusCode};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn sampling_a_deleted_file_returns_404() {
+        let app = TestApp::new();
+        let file_id = app.upload(&test_data(64 * 1024)).await;
+        let sample = || {
+            json_request(
+                "POST",
+                &format!("/api/sample/{}", file_id),
+                json!({ "sample_size": 1024 }),
+            )
+        };
+
+        // 第一次采样把结果放进缓存
+        assert_eq!(app.request(sample()).await.status(), StatusCode::OK);
+        assert_eq!(app.request(sample()).await.status(), StatusCode::OK);
+
+        let delete = Request::delete(format!("/api/files/{}", file_id))
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(app.request(delete).await.status(), StatusCode::OK);
+
+        assert_eq!(app.request(sample()).await.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn decompressed_upload_beyond_max_file_size_returns_413() {
+        let app = TestApp::with_config(|config| config.max_file_size = 1024 * 1024);
+        let upload = |data: &[u8]| {
+            let mut request = upload_request(data);
+            *request.uri_mut() = "/api/upload?decompress=gzip".parse().unwrap();
+            app.request(request)
+        };
+
+        // 8MB 的 0 压缩后只有 8KB
+        let response = upload(include_bytes!("../../testdata/zeros.gz")).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let response = upload(include_bytes!("../../testdata/short.gz")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_json(response).await["size"], 20);
+    }
+
+    #[tokio::test]
+    async fn upload_from_url_enforces_max_file_size() {
+        let app = TestApp::with_config(|config| {
+            config.max_file_size = 1024;
+            config.fetch_allowed_hosts = vec!["127.0.0.1".to_string()];
+        });
+        let (addr, _) = serve_http(|path| {
+            let (head, body) = match path {
+                // 声明的长度超过限制，不必接收响应体
+                "/declared.bin" => ("Content-Length: 4096", vec![0; 4096]),
+                // 未声明长度，接收过程中超过限制
+                "/chunked.bin" => ("Transfer-Encoding: chunked", {
+                    let chunk = [b"400\r\n".as_slice(), &[1; 1024], b"\r\n"].concat();
+                    [chunk.repeat(4), b"0\r\n\r\n".to_vec()].concat()
+                }),
+                _ => ("Content-Length: 1024", vec![2; 1024]),
+            };
+            [format!("HTTP/1.1 200 OK\r\n{}\r\n\r\n", head).into_bytes(), body].concat()
+        })
+        .await;
+        let from_url = |path: &str| {
+            let url = format!("http://{}{}", addr, path);
+            app.request(json_request("POST", "/api/upload/from-url", json!({ "url": url })))
+        };
+
+        for path in ["/declared.bin", "/chunked.bin"] {
+            assert_eq!(
+                from_url(path).await.status(),
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "{}",
+                path
+            );
+        }
+        let response = from_url("/exact.bin").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_json(response).await["size"], 1024);
+    }
+
+    #[tokio::test]
+    async fn client_fingerprint_resolves_to_the_uploaded_file() {
+        let app = TestApp::new();
+        let fingerprint = "5d41402a_65536_1700000000000";
+        let check = || {
+            app.request(
+                Request::get(format!("/api/check?fingerprint={}", fingerprint))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+        assert_eq!(body_json(check().await).await["exists"], false);
+
+        let request =
+            upload_request_with_fields(&[("fingerprint", fingerprint)], &test_data(65536));
+        let response = app.request(request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let file_id = body_json(response).await["file_id"].clone();
+
+        let result = body_json(check().await).await;
+        assert_eq!(result["exists"], true);
+        assert_eq!(result["file_id"], file_id);
+
+        let status = app
+            .request(
+                Request::get(format!("/api/files/{}/status", fingerprint))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await;
+        assert_eq!(status.status(), StatusCode::OK);
+        let status = body_json(status).await;
+        assert_eq!(status["status"], "complete");
+        assert_eq!(status["file_id"], file_id);
+        assert_eq!(status["bytes_written"], 65536);
+
+        // 文件删除后指纹不再命中
+        let delete = Request::delete(format!("/api/files/{}", file_id.as_str().unwrap()))
+            .body(Body::empty())
+            .unwrap();
+        assert!(app.request(delete).await.status().is_success());
+        assert_eq!(body_json(check().await).await["exists"], false);
+    }
+
+    #[tokio::test]
+    async fn random_sample_metadata_has_no_validator() {
+        let app = TestApp::new();
+        let file_id = app.upload(&test_data(1024 * 1024)).await;
+        let metadata = |method: &str| {
+            let uri = format!(
+                "/api/sample/{}/metadata?sample_size=4096&method={}",
+                file_id, method
+            );
+            app.request(
+                Request::get(uri)
+                    .header("if-none-match", "*")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+
+        // 两次不走缓存的 uniform 采样各自选取窗口，都不带 ETag，条件请求也照常采样
+        let mut offsets = Vec::new();
+        for _ in 0..2 {
+            super::clear_cache_now(&app.state);
+            let response = metadata("uniform").await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert!(response.headers().get("etag").is_none());
+            offsets.push(body_json(response).await["offsets"].clone());
+        }
+        assert_ne!(offsets[0], offsets[1]);
+        assert!(metadata("gradient").await.headers().get("etag").is_none());
+
+        // 确定性的采样仍然可以用 ETag 验证
+        assert_eq!(metadata("golden").await.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[tokio::test]
+    async fn analysis_caches_follow_appended_data() {
+        let app = TestApp::new();
+        let file_id = app.upload(&[0u8; 4096]).await;
+        let get = |uri: String| app.request(Request::get(uri).body(Body::empty()).unwrap());
+        let quantiles = || get(format!("/api/files/{}/quantiles?q=0.5", file_id));
+        let render = || get(format!("/api/files/{}/render?width=4&height=4", file_id));
+
+        assert_eq!(body_json(quantiles().await).await["values"], json!([0]));
+        let before = axum::body::to_bytes(render().await.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        // 外部追加数据后，缓存的直方图和图像不再适用
+        let path = app.state.file_manager.stored_path(&file_id).await.unwrap();
+        let mut file = std::fs::OpenOptions::new().append(true).open(path).unwrap();
+        std::io::Write::write_all(&mut file, &[0xff; 3 * 4096]).unwrap();
+
+        assert_eq!(body_json(quantiles().await).await["values"], json!([255]));
+        let after = axum::body::to_bytes(render().await.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_ne!(before, after);
+    }
+
+    #[tokio::test]
+    async fn check_hit_keeps_the_file_from_expiring() {
+        let app = TestApp::new();
+        let file_id = app.upload(&test_data(1024)).await;
+        let path = app.state.file_manager.stored_path(&file_id).await.unwrap();
+        let hour = std::time::Duration::from_secs(3600);
+        let aged = std::time::SystemTime::now() - 2 * hour;
+        std::fs::File::open(&path)
+            .and_then(|file| file.set_modified(aged))
+            .unwrap();
+
+        let check = Request::get(format!("/api/check?fingerprint={}", file_id))
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(body_json(app.request(check).await).await["exists"], true);
+
+        let removed = app.state.file_manager.cleanup_older_than(hour).await;
+        assert!(removed.unwrap().is_empty());
+        assert!(path.exists());
+    }
+
+    #[tokio::test]
+    async fn oversized_body_outside_upload_routes_is_rejected() {
+        let app = TestApp::with_config(|config| config.max_body_size = 4096);
+        // 上传接口单独放宽到 max_file_size，不受全局限制
+        let file_id = app.upload(&test_data(64 * 1024)).await;
+
+        // 合法的 JSON，只是被填充到超过限制
+        let padding = "x".repeat(64 * 1024);
+        let request = json_request(
+            "POST",
+            &format!("/api/sample/{}", file_id),
+            json!({ "sample_size": 1024, "method": "uniform", "padding": padding }),
+        );
+        assert_eq!(
+            app.request(request).await.status(),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+
+        let request = json_request(
+            "POST",
+            &format!("/api/sample/{}", file_id),
+            json!({ "sample_size": 1024 }),
+        );
+        assert_eq!(app.request(request).await.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn empty_upload_returns_400() {
+        let app = TestApp::new();
+        let response = app.request(upload_request(b"")).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let files = app
+            .request(Request::get("/api/files").body(Body::empty()).unwrap())
+            .await;
+        assert_eq!(body_json(files).await["files"], json!([]));
+    }
+}
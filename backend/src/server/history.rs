@@ -0,0 +1,54 @@
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+// 每个文件保留的最近采样参数条数
+pub const RECENT_SAMPLES_PER_FILE: usize = 16;
+
+// 一次采样请求的参数，供前端回到之前的缩放级别
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RecentSample {
+    pub sample_size: usize,
+    pub method: String,
+}
+
+// 按文件 ID 记录最近的采样参数，每个文件最多 RECENT_SAMPLES_PER_FILE 条，
+// 文件被删除时一并删除；只保存在内存中，重启后清空
+#[derive(Default)]
+pub struct SampleHistory {
+    files: Mutex<HashMap<String, VecDeque<RecentSample>>>,
+}
+
+impl SampleHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 记录一次成功的采样；参数相同的旧记录移到最新的位置，不重复保存
+    pub fn record(&self, file_id: &str, sample: RecentSample) {
+        let mut files = self.files.lock().unwrap();
+        let recent = files.entry(file_id.to_string()).or_default();
+        recent.retain(|existing| *existing != sample);
+        if recent.len() >= RECENT_SAMPLES_PER_FILE {
+            recent.pop_front();
+        }
+        recent.push_back(sample);
+    }
+
+    // 最近的在前
+    pub fn recent(&self, file_id: &str) -> Vec<RecentSample> {
+        let files = self.files.lock().unwrap();
+        files
+            .get(file_id)
+            .map(|recent| recent.iter().rev().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn forget(&self, file_id: &str) {
+        self.files.lock().unwrap().remove(file_id);
+    }
+
+    pub fn clear(&self) {
+        self.files.lock().unwrap().clear();
+    }
+}
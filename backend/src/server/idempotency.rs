@@ -0,0 +1,146 @@
+use crate::error::{AppError, Result};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// 记录的幂等键数量超过该值时先清理过期的键，仍然超过时丢弃最早完成的键
+const MAX_TRACKED_KEYS: usize = 10_000;
+// 幂等键的最大长度，只允许可见 ASCII 字符
+const MAX_KEY_LEN: usize = 255;
+
+enum Entry<V> {
+    // 使用该键的请求仍在处理
+    Pending,
+    Done {
+        completed: Instant,
+        file_id: String,
+        value: V,
+    },
+}
+
+// 按 Idempotency-Key 记录已完成上传的结果：重试时直接返回之前的结果，不重新接收和保存文件；
+// 结果保留 ttl，对应的文件被删除时一并删除；只保存在内存中，重启后清空
+pub struct IdempotencyKeys<V> {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, Entry<V>>>,
+}
+
+// 对一个幂等键的处理方式
+pub enum Claim<'a, V: Clone> {
+    // 该键已经完成过，返回之前的结果
+    Replay(V),
+    // 首次使用该键，处理完成后调用 complete 记录结果
+    Started(KeyGuard<'a, V>),
+}
+
+impl<V: Clone> IdempotencyKeys<V> {
+    // ttl 为 0 时不记录任何键
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.ttl.is_zero()
+    }
+
+    // 占用一个幂等键；同一个键的请求仍在处理时返回 Conflict，客户端应稍后重试
+    pub fn claim(&self, key: &str) -> Result<Claim<'_, V>> {
+        if key.is_empty() || key.len() > MAX_KEY_LEN || !key.bytes().all(|b| b.is_ascii_graphic()) {
+            return Err(AppError::BadRequest(format!(
+                "Idempotency-Key must be 1 to {} visible ASCII characters",
+                MAX_KEY_LEN
+            )));
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some(Entry::Pending) => {
+                return Err(AppError::Conflict(format!(
+                    "A request with Idempotency-Key {} is still in progress",
+                    key
+                )))
+            }
+            Some(Entry::Done {
+                completed, value, ..
+            }) if completed.elapsed() < self.ttl => return Ok(Claim::Replay(value.clone())),
+            _ => {}
+        }
+
+        if entries.len() >= MAX_TRACKED_KEYS {
+            self.prune(&mut entries);
+        }
+        entries.insert(key.to_string(), Entry::Pending);
+        Ok(Claim::Started(KeyGuard {
+            keys: self,
+            key: Some(key.to_string()),
+        }))
+    }
+
+    fn prune(&self, entries: &mut HashMap<String, Entry<V>>) {
+        entries.retain(|_, entry| match entry {
+            Entry::Pending => true,
+            Entry::Done { completed, .. } => completed.elapsed() < self.ttl,
+        });
+        if entries.len() < MAX_TRACKED_KEYS {
+            return;
+        }
+        let oldest = entries
+            .iter()
+            .filter_map(|(key, entry)| match entry {
+                Entry::Done { completed, .. } => Some((*completed, key)),
+                Entry::Pending => None,
+            })
+            .min()
+            .map(|(_, key)| key.clone());
+        if let Some(oldest) = oldest {
+            entries.remove(&oldest);
+        }
+    }
+
+    // 文件被删除后，指向它的键不再返回之前的结果
+    pub fn forget_file(&self, file_id: &str) {
+        self.entries.lock().unwrap().retain(|_, entry| match entry {
+            Entry::Done { file_id: id, .. } => id != file_id,
+            Entry::Pending => true,
+        });
+    }
+
+    pub fn clear(&self) {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|_, entry| matches!(entry, Entry::Pending));
+    }
+}
+
+// 占用中的幂等键；没有调用 complete 就被丢弃时（请求失败或被中断）释放该键，允许客户端重试
+pub struct KeyGuard<'a, V: Clone> {
+    keys: &'a IdempotencyKeys<V>,
+    key: Option<String>,
+}
+
+impl<V: Clone> KeyGuard<'_, V> {
+    pub fn complete(mut self, file_id: &str, value: V) {
+        if let Some(key) = self.key.take() {
+            self.keys.entries.lock().unwrap().insert(
+                key,
+                Entry::Done {
+                    completed: Instant::now(),
+                    file_id: file_id.to_string(),
+                    value,
+                },
+            );
+        }
+    }
+}
+
+impl<V: Clone> Drop for KeyGuard<'_, V> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.keys.entries.lock().unwrap().remove(&key);
+        }
+    }
+}
@@ -1,5 +1,11 @@
 pub mod routes;
+pub mod etag;
 pub mod handlers;
+pub mod history;
+pub mod idempotency;
+pub mod rate_limit;
 pub mod websocket;
+#[cfg(test)]
+pub mod testing;
 
-pub use routes::{api_routes, ws_routes};
+pub use routes::app;
@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// 按客户端 IP 记录的令牌桶数量超过该值时，清理长时间未使用的桶
+const MAX_TRACKED_CLIENTS: usize = 4096;
+const IDLE_BUCKET_TTL: Duration = Duration::from_secs(60);
+
+// 令牌桶：每秒补充 rate 个令牌，最多积累 rate 个（允许一秒内的突发），rate 为 0 时不限制
+pub struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: u32) -> Self {
+        Self {
+            rate: rate as f64,
+            tokens: rate as f64,
+            last: Instant::now(),
+        }
+    }
+
+    // 取一个令牌，桶空时返回 false
+    pub fn try_acquire(&mut self) -> bool {
+        if self.rate == 0.0 {
+            return true;
+        }
+
+        let now = Instant::now();
+        let refill = now.duration_since(self.last).as_secs_f64() * self.rate;
+        self.tokens = (self.tokens + refill).min(self.rate);
+        self.last = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+// 按客户端 IP 分桶的限流器，供 HTTP 接口使用（WebSocket 每个连接持有自己的桶）
+pub struct RateLimiter {
+    rate: u32,
+    buckets: Mutex<HashMap<IpAddr, TokenBucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(rate: u32) -> Self {
+        Self {
+            rate,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn try_acquire(&self, client: IpAddr) -> bool {
+        if self.rate == 0 {
+            return true;
+        }
+
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_TRACKED_CLIENTS {
+            buckets.retain(|_, bucket| bucket.last.elapsed() < IDLE_BUCKET_TTL);
+        }
+        buckets
+            .entry(client)
+            .or_insert_with(|| TokenBucket::new(self.rate))
+            .try_acquire()
+    }
+}
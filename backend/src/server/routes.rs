@@ -1,14 +1,140 @@
-use axum::{routing::{delete, get, post}, Router};
+use axum::{
+    extract::DefaultBodyLimit,
+    http::HeaderValue,
+    middleware::from_fn,
+    routing::{delete, get, post, put, MethodRouter},
+    Extension, Router,
+};
+use std::sync::Arc;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+use tower_http::trace::{DefaultMakeSpan, DefaultOnResponse, TraceLayer};
+use tower_http::LatencyUnit;
 
-pub fn api_routes() -> Router {
-    Router::new()
+use super::handlers::{require_api_token, AppState};
+
+// 完整的应用路由：/api 和 /ws 两组接口，加上状态、CORS、请求日志和请求体大小限制
+pub fn app(state: Arc<AppState>) -> anyhow::Result<Router> {
+    let config = &state.config;
+    Ok(Router::new()
+        .nest("/api", api_routes(config.max_file_size))
+        .nest("/ws", ws_routes())
+        .layer(Extension(state.clone()))
+        .layer(cors_layer(&config.allowed_origins)?)
+        // 每个 HTTP 请求一个 span（方法、路径），结束时记录状态码和耗时
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(DefaultMakeSpan::new().level(tracing::Level::INFO))
+                .on_response(
+                    DefaultOnResponse::new()
+                        .level(tracing::Level::INFO)
+                        .latency_unit(LatencyUnit::Millis),
+                ),
+        )
+        // 上传以外的接口只接受小请求体，上传接口在路由上单独放宽到 max_file_size
+        .layer(DefaultBodyLimit::max(config.max_body_size)))
+}
+
+// 按 ALLOWED_ORIGINS 构建 CORS 层：* 为完全放开，为空时不允许跨域
+fn cors_layer(allowed_origins: &[String]) -> anyhow::Result<CorsLayer> {
+    if allowed_origins.iter().any(|origin| origin == "*") {
+        return Ok(CorsLayer::permissive());
+    }
+
+    let origins = allowed_origins
+        .iter()
+        .map(|origin| origin.parse::<HeaderValue>())
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_methods(Any)
+        .allow_headers(Any)
+        .expose_headers(Any))
+}
+
+// max_upload_size 只用于携带文件内容的上传接口，其余接口使用全局的小请求体限制
+pub fn api_routes(max_upload_size: usize) -> Router {
+    // 修改数据的接口在配置了 API_TOKEN 时需要令牌，只读接口保持开放
+    let router = Router::new()
         .route("/check", get(super::handlers::check_fingerprint))
-        .route("/upload", post(super::handlers::upload_file))
+        .route(
+            "/upload",
+            post(super::handlers::upload_file)
+                .route_layer(from_fn(require_api_token))
+                .layer(DefaultBodyLimit::max(max_upload_size)),
+        )
+        .route(
+            "/upload/from-url",
+            post(super::handlers::upload_from_url).route_layer(from_fn(require_api_token)),
+        )
+        .route(
+            "/upload/init",
+            post(super::handlers::init_upload).route_layer(from_fn(require_api_token)),
+        )
+        .route(
+            "/upload/:uid/chunk",
+            put(super::handlers::upload_chunk)
+                .route_layer(from_fn(require_api_token))
+                .layer(DefaultBodyLimit::max(max_upload_size)),
+        )
+        .route(
+            "/upload/:uid/complete",
+            post(super::handlers::complete_upload).route_layer(from_fn(require_api_token)),
+        )
+        .route(
+            "/files",
+            get(super::handlers::list_files).delete(super::handlers::delete_all_files),
+        )
         .route("/files/:id", get(super::handlers::get_file_info))
-        .route("/files/:id", delete(super::handlers::delete_file))
+        .route(
+            "/files/:id",
+            delete(super::handlers::delete_file).route_layer(from_fn(require_api_token)),
+        )
+        .route("/files/info", post(super::handlers::batch_file_info))
+        .route(
+            "/files/delete",
+            post(super::handlers::batch_delete_files).route_layer(from_fn(require_api_token)),
+        )
+        .route("/files/:id/download", get(super::handlers::download_file))
+        .route("/files/:id/status", get(super::handlers::upload_status))
+        .route("/files/:id/recent", get(super::handlers::recent_samples))
+        .route("/files/:id/render.png", get(super::handlers::render_image))
         .route("/sample/:id", post(super::handlers::sample_file))
+        .route("/sample/:id/metadata", get(super::handlers::sample_metadata))
+        .route("/cache/clear", post(super::handlers::clear_cache))
+        .route("/cache/capacity", post(super::handlers::set_cache_capacity))
+        .route("/capabilities", get(super::handlers::capabilities))
         .route("/health", get(super::handlers::health_check))
+        .route("/version", get(super::handlers::version))
         .route("/metrics", get(super::handlers::get_metrics))
+        .route(
+            "/metrics/prometheus",
+            get(super::handlers::get_prometheus_metrics),
+        );
+
+    analysis_routes()
+        .into_iter()
+        .fold(router, |router, (name, route)| {
+            router.route(&format!("/files/:id/{}", name), route)
+        })
+}
+
+// 按文件的只读分析接口 /api/files/:id/<name>；能力查询接口也从这里列出，新的分析接口加在这里即可
+fn analysis_routes() -> Vec<(&'static str, MethodRouter)> {
+    vec![
+        ("zero-runs", get(super::handlers::zero_runs)),
+        ("autocorr", get(super::handlers::autocorrelation)),
+        ("sections", get(super::handlers::file_sections)),
+        ("strings", get(super::handlers::extract_strings)),
+        ("quantiles", get(super::handlers::byte_quantiles)),
+        ("thumbnail", get(super::handlers::thumbnail)),
+        ("render", get(super::handlers::render_image)),
+        ("verify", get(super::handlers::verify_file)),
+        ("suggest-sample", get(super::handlers::suggest_sample)),
+    ]
+}
+
+pub fn analysis_names() -> Vec<&'static str> {
+    analysis_routes().into_iter().map(|(name, _)| name).collect()
 }
 
 pub fn ws_routes() -> Router {
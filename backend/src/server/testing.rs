@@ -0,0 +1,177 @@
+// 接口测试共用的应用实例：上传目录放在系统临时目录下，实例释放时删除
+use crate::config::Config;
+use crate::server::handlers::AppState;
+use axum::{
+    body::{to_bytes, Body},
+    extract::ConnectInfo,
+    http::{Request, Response, StatusCode},
+    Router,
+};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tower::Service;
+use uuid::Uuid;
+
+// multipart 请求体的分隔符
+const BOUNDARY: &str = "binaryvis-test-boundary";
+
+pub struct TestApp {
+    pub state: Arc<AppState>,
+    pub router: Router,
+    dir: PathBuf,
+}
+
+impl TestApp {
+    pub fn new() -> Self {
+        Self::with_config(|_| {})
+    }
+
+    // configure 在默认配置（上传目录已指向临时目录）的基础上修改
+    pub fn with_config(configure: impl FnOnce(&mut Config)) -> Self {
+        let dir = std::env::temp_dir().join(format!("binaryvis-app-{}", Uuid::new_v4().simple()));
+        let mut config = Config {
+            upload_dir: dir.clone(),
+            sampler_threads: 2,
+            ..Default::default()
+        };
+        configure(&mut config);
+
+        let state = Arc::new(AppState::new(config).unwrap());
+        let router = crate::server::app(state.clone()).unwrap();
+        Self { state, router, dir }
+    }
+
+    // 发送一个请求；采样接口按客户端 IP 限流，这里补上 serve 时才有的对端地址
+    // Router 总是就绪，可以不经 poll_ready 直接调用
+    pub async fn request(&self, mut request: Request<Body>) -> Response<Body> {
+        let client = SocketAddr::from(([127, 0, 0, 1], 40000));
+        request.extensions_mut().insert(ConnectInfo(client));
+        self.router.clone().call(request).await.unwrap()
+    }
+
+    // 在本机的随机端口上启动服务器并返回地址，WebSocket 测试需要真实的连接
+    pub async fn serve(&self) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let service = self
+            .router
+            .clone()
+            .into_make_service_with_connect_info::<SocketAddr>();
+        tokio::spawn(async move { axum::serve(listener, service).await });
+        addr
+    }
+
+    // 以 multipart 表单上传 data，返回文件 ID
+    pub async fn upload(&self, data: &[u8]) -> String {
+        let response = self.request(upload_request(data)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        body_json(response).await["file_id"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    }
+}
+
+impl Drop for TestApp {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+// 只含一个文件字段的上传请求
+pub fn upload_request(data: &[u8]) -> Request<Body> {
+    upload_request_with_fields(&[], data)
+}
+
+// 文本字段（如指纹）放在文件字段之前的上传请求
+pub fn upload_request_with_fields(fields: &[(&str, &str)], data: &[u8]) -> Request<Body> {
+    let mut body = Vec::new();
+    for (name, value) in fields {
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                BOUNDARY, name, value
+            )
+            .as_bytes(),
+        );
+    }
+    body.extend_from_slice(
+        format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"test.bin\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n",
+            BOUNDARY
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(data);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", BOUNDARY).as_bytes());
+
+    Request::post("/api/upload")
+        .header(
+            "content-type",
+            format!("multipart/form-data; boundary={}", BOUNDARY),
+        )
+        .body(Body::from(body))
+        .unwrap()
+}
+
+pub fn json_request(method: &str, uri: &str, body: serde_json::Value) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+pub async fn body_json(response: Response<Body>) -> serde_json::Value {
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+// 不全为同一个字节的测试数据
+pub fn test_data(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 31 % 251) as u8).collect()
+}
+
+// 本机随机端口上的简易 HTTP 服务器，供拉取远程文件的测试使用：
+// respond 按请求路径返回完整的原始响应（状态行、头部和响应体），写完后关闭连接；
+// 返回服务器地址和按顺序记录的请求路径
+pub async fn serve_http(
+    respond: impl Fn(&str) -> Vec<u8> + Send + Sync + 'static,
+) -> (SocketAddr, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let paths = Arc::new(Mutex::new(Vec::new()));
+    let respond = Arc::new(respond);
+
+    let recorded = paths.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let (respond, recorded) = (respond.clone(), recorded.clone());
+            tokio::spawn(async move {
+                let mut reader = BufReader::new(stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).await.unwrap();
+                let mut line = String::new();
+                while reader.read_line(&mut line).await.unwrap() > 2 {
+                    line.clear();
+                }
+
+                let path = request_line
+                    .split(' ')
+                    .nth(1)
+                    .unwrap_or_default()
+                    .to_string();
+                recorded.lock().unwrap().push(path.clone());
+                let stream = reader.get_mut();
+                let _ = stream.write_all(&respond(&path)).await;
+                let _ = stream.shutdown().await;
+            });
+        }
+    });
+    (addr, paths)
+}
@@ -1,59 +1,143 @@
-use crate::core::{Cache, Sampler};
+use crate::core::analysis;
+use crate::core::cache::make_analysis_key;
+use crate::core::sampler::SampleResult;
 use crate::error::{AppError, Result};
-use crate::protocol::{ControlMessage, DataMessage, ErrorMessage, Message, MessageType, SampleRequest};
-use crate::sampling::UniformSampler;
-use crate::server::handlers::AppState;
+use crate::protocol::{
+    CancelRequest, ClearCacheRequest, CompleteMessage, ControlMessage, DataMessage, Encoding,
+    EntropyMessage, EntropyRequest, ErrorMessage, FollowRequest, HistogramMessage, Message,
+    MessageType, PrepareMessage, ProgressMessage, SampleMultiRequest, SampleRequest,
+};
+use crate::server::handlers::{check_admin_token, clear_cache_now, perform_sampling, AppState};
+use crate::server::rate_limit::TokenBucket;
 use axum::{
     extract::{
-        ws::{Message as WsMessage, WebSocket, WebSocketUpgrade},
+        ws::{close_code, CloseFrame, Message as WsMessage, WebSocket, WebSocketUpgrade},
         Extension, Path,
     },
     response::Response,
 };
 use futures::{sink::SinkExt, stream::StreamExt};
 use serde::Serialize;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
-use tracing::{error, info};
+use tokio::task::AbortHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, info_span, Instrument};
 use uuid::Uuid;
 
 pub async fn websocket_handler(
     ws: WebSocketUpgrade,
     Extension(state): Extension<Arc<AppState>>,
     Path(file_id): Path<String>,
-) -> Response {
-    ws.on_upgrade(move |socket| handle_socket(socket, state, file_id))
+) -> Result<Response> {
+    // 连接数已满时在升级前拒绝；名额在此时占用，随守卫进入升级后的连接，
+    // 升级失败时守卫随回调一起释放
+    let Some(guard) = ConnectionGuard::acquire(state.clone()) else {
+        return Err(AppError::TooManyRequests(format!(
+            "connection limit of {} reached",
+            state.config.max_connections
+        )));
+    };
+
+    Ok(ws.on_upgrade(move |socket| handle_socket(socket, state, file_id, guard)))
+}
+
+async fn handle_socket(
+    socket: WebSocket,
+    state: Arc<AppState>,
+    file_id: String,
+    guard: ConnectionGuard,
+) {
+    // 连接内的日志（包括各个请求任务）都带上连接 ID 和文件 ID，便于区分并发连接
+    let conn_id = Uuid::new_v4().simple().to_string()[..8].to_string();
+    let span = info_span!("ws", conn = %conn_id, file_id = %file_id);
+    handle_connection(socket, state, file_id)
+        .instrument(span)
+        .await;
+    drop(guard);
 }
 
-async fn handle_socket(socket: WebSocket, state: Arc<AppState>, file_id: String) {
+async fn handle_connection(socket: WebSocket, state: Arc<AppState>, file_id: String) {
     info!("========== WebSocket Connection Established ==========");
-    info!("  File ID: {}", file_id);
+    let started = Instant::now();
 
     let (mut sender, mut receiver) = socket.split();
-    let (tx, mut rx) = mpsc::channel::<Vec<u8>>(100);
+    let (tx, mut rx) = mpsc::channel::<WsMessage>(100);
+
+    // 心跳间隔取空闲超时的三分之一，保证超时前至少发出两次 Ping
+    let idle_timeout = Duration::from_secs(state.config.ws_idle_secs);
+    let ping_period = Duration::from_secs((state.config.ws_idle_secs / 3).max(1));
 
     // 发送任务
-    let send_task = tokio::spawn(async move {
-        while let Some(data) = rx.recv().await {
-            if sender.send(WsMessage::Binary(data)).await.is_err() {
-                break;
+    let send_loop = async move {
+        let mut ping = tokio::time::interval(ping_period);
+        ping.tick().await;
+
+        loop {
+            tokio::select! {
+                frame = rx.recv() => {
+                    let Some(frame) = frame else { break };
+                    let is_close = matches!(frame, WsMessage::Close(_));
+                    if sender.send(frame).await.is_err() || is_close {
+                        break;
+                    }
+                }
+                _ = ping.tick() => {
+                    if sender.send(WsMessage::Ping(Vec::new())).await.is_err() {
+                        break;
+                    }
+                }
             }
         }
-    });
+    };
+    let mut send_task = tokio::spawn(send_loop.in_current_span());
 
     // 接收任务
-    let recv_state = state.clone();
-    let recv_tx = tx.clone();
-    let recv_task = tokio::spawn(async move {
-        while let Some(msg) = receiver.next().await {
+    let conn = Connection {
+        state: state.clone(),
+        file_id,
+        tx: tx.clone(),
+        requests: Arc::new(Mutex::new(HashMap::new())),
+        closed: state.shutdown.child_token(),
+        encoding: Arc::new(Mutex::new(Encoding::default())),
+        sample_bucket: Arc::new(Mutex::new(TokenBucket::new(
+            state.config.sample_rate_limit,
+        ))),
+    };
+    let recv_conn = conn.clone();
+    let recv_loop = async move {
+        loop {
+            // 任何消息（包括 Pong）都会重置空闲计时
+            let next = tokio::select! {
+                next = tokio::time::timeout(idle_timeout, receiver.next()) => next,
+                _ = recv_conn.closed.cancelled() => {
+                    // 服务器关闭时先让进行中的请求发出已有的数据和中断通知，Close 帧之后的消息不会再发送
+                    if recv_conn.state.shutdown.is_cancelled() {
+                        recv_conn.wait_requests(SHUTDOWN_GRACE).await;
+                    }
+                    recv_conn.send_close().await;
+                    break;
+                }
+            };
+            let msg = match next {
+                Ok(Some(msg)) => msg,
+                Ok(None) => break,
+                Err(_) => {
+                    info!("WebSocket idle for {:?}, closing", idle_timeout);
+                    break;
+                }
+            };
+
             match msg {
                 Ok(WsMessage::Binary(data)) => {
-                    if let Err(e) = handle_message(&recv_state, &file_id, data, &recv_tx).await {
-                        error!("Error handling message: {}", e);
-                        // 发送错误消息
-                        let _ = send_error(&recv_tx, e).await;
-                        break; // Fast-fail
-                    }
+                    handle_frame(&recv_conn, &data, Encoding::MessagePack).await;
+                }
+                Ok(WsMessage::Text(text)) => {
+                    handle_frame(&recv_conn, text.as_bytes(), Encoding::Json).await;
                 }
                 Ok(WsMessage::Close(_)) => break,
                 Err(e) => {
@@ -63,31 +147,250 @@ async fn handle_socket(socket: WebSocket, state: Arc<AppState>, file_id: String)
                 _ => {}
             }
         }
-    });
+    };
+    let mut recv_task = tokio::spawn(recv_loop.in_current_span());
 
-    // 等待任务完成
+    // 任一任务结束即终止另一个，确保连接占用的资源随之释放
     tokio::select! {
-        _ = send_task => {},
-        _ = recv_task => {},
+        _ = &mut send_task => recv_task.abort(),
+        _ = &mut recv_task => {
+            // 给发送任务一点时间把已排队的消息和 Close 帧发出去
+            let _ = tokio::time::timeout(Duration::from_secs(1), &mut send_task).await;
+            send_task.abort();
+        }
+    }
+    conn.cancel_requests(None);
+    info!("WebSocket connection closed after {:?}", started.elapsed());
+}
+
+// 活跃连接计数守卫：丢弃时（包括 panic 和升级失败）自动减一
+struct ConnectionGuard {
+    state: Arc<AppState>,
+}
+
+impl ConnectionGuard {
+    // 未达到上限时原子地占用一个名额，并发的升级请求不会一起越过上限
+    fn acquire(state: Arc<AppState>) -> Option<Self> {
+        let max = state.config.max_connections;
+        state
+            .active_connections
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |active| {
+                (active < max).then_some(active + 1)
+            })
+            .ok()?;
+        Some(Self { state })
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.state.active_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// 服务器关闭时等待进行中的请求收尾的最长时间
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(2);
+
+// 发送通道中为非数据消息保留的位置数
+const DATA_HEADROOM: usize = 8;
+
+// 单个 WebSocket 连接的上下文
+#[derive(Clone)]
+struct Connection {
+    state: Arc<AppState>,
+    file_id: String,
+    tx: mpsc::Sender<WsMessage>,
+    // 进行中的采样请求，按请求 ID 索引
+    requests: Arc<Mutex<HashMap<String, AbortHandle>>>,
+    // 发生致命错误时取消，用于关闭连接
+    closed: CancellationToken,
+    // 发给客户端的消息编码，由客户端的控制消息协商
+    encoding: Arc<Mutex<Encoding>>,
+    // 本连接的采样请求限流
+    sample_bucket: Arc<Mutex<TokenBucket>>,
+}
+
+impl Connection {
+    // 按协商的编码序列化并发送一条消息
+    async fn send<T: Serialize>(&self, type_field: MessageType, payload: &T) -> Result<()> {
+        let encoding = *self.encoding.lock().unwrap();
+        let frame = encode_frame(encoding, type_field, payload)?;
+
+        self.tx
+            .send(frame)
+            .await
+            .map_err(|_| AppError::ConnectionClosed)
+    }
+
+    // 发送大块数据帧：通道还有余量时直接发送，接近满时等待空出 DATA_HEADROOM 个位置再发送，
+    // 既不给快速的客户端增加延迟，也给同一连接上其他请求的控制、错误消息留出位置
+    async fn send_data<T: Serialize>(&self, payload: &T) -> Result<()> {
+        let encoding = *self.encoding.lock().unwrap();
+        let frame = encode_frame(encoding, MessageType::Data, payload)?;
+
+        if self.tx.capacity() > DATA_HEADROOM {
+            return self
+                .tx
+                .send(frame)
+                .await
+                .map_err(|_| AppError::ConnectionClosed);
+        }
+
+        // 多余的许可随迭代器一起释放
+        let mut permits = self
+            .tx
+            .reserve_many(DATA_HEADROOM + 1)
+            .await
+            .map_err(|_| AppError::ConnectionClosed)?;
+        if let Some(permit) = permits.next() {
+            permit.send(frame);
+        }
+        Ok(())
+    }
+
+    // 发送 Close 帧：服务器关闭时使用 1001（Going Away），否则为 1011（内部错误）
+    async fn send_close(&self) {
+        let frame = if self.state.shutdown.is_cancelled() {
+            CloseFrame {
+                code: close_code::AWAY,
+                reason: "Server shutting down".into(),
+            }
+        } else {
+            CloseFrame {
+                code: close_code::ERROR,
+                reason: "Fatal error".into(),
+            }
+        };
+
+        let _ = self.tx.send(WsMessage::Close(Some(frame))).await;
+    }
+
+    // 发送错误消息，仅致命错误才断开连接；request_id 为出错请求的 ID，消息无法解析时为 None
+    async fn report_error(&self, request_id: Option<&str>, e: AppError) {
+        error!("Error handling message: {}", e);
+        let fatal = e.is_fatal();
+        let _ = send_error(self, request_id, e).await;
+        if fatal {
+            self.closed.cancel();
+        }
+    }
+
+    // 以独立任务执行请求并流式发送，多个请求可以交错进行，也都可以被取消
+    // 请求 ID 与进行中的请求重复时拒绝：两个任务的消息无法区分，取消也只能找到其中一个
+    fn spawn_request<F, Fut>(&self, command: &str, request_id: String, task: F) -> Result<()>
+    where
+        F: FnOnce(Connection, String) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        // 持有锁直到登记完成，避免任务先结束导致登记残留
+        let mut requests = self.requests.lock().unwrap();
+        if requests.contains_key(&request_id) {
+            return Err(AppError::Conflict(format!(
+                "Request {} is already in progress",
+                request_id
+            )));
+        }
+
+        let conn = self.clone();
+        let task_request_id = request_id.clone();
+        let span = info_span!("request", id = %request_id, command);
+        let handle = tokio::spawn(
+            async move {
+                let started = Instant::now();
+                let result = task(conn.clone(), task_request_id.clone()).await;
+
+                match result {
+                    Ok(()) => info!("Request finished in {:?}", started.elapsed()),
+                    Err(e) => conn.report_error(Some(&task_request_id), e).await,
+                }
+                // 只删除自己的登记：本请求已被取消时，同一 ID 可能已经登记了新的请求
+                let mut requests = conn.requests.lock().unwrap();
+                if requests
+                    .get(&task_request_id)
+                    .is_some_and(|handle| handle.id() == tokio::task::id())
+                {
+                    requests.remove(&task_request_id);
+                }
+            }
+            .instrument(span),
+        );
+
+        requests.insert(request_id, handle.abort_handle());
+        Ok(())
+    }
+
+    // 等待进行中的请求全部结束，最多等待 timeout
+    async fn wait_requests(&self, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+        while !self.requests.lock().unwrap().is_empty() && Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    // 取消指定请求，未指定时取消全部
+    fn cancel_requests(&self, request_id: Option<&str>) {
+        let mut requests = self.requests.lock().unwrap();
+        match request_id {
+            Some(id) => {
+                if let Some(handle) = requests.remove(id) {
+                    handle.abort();
+                }
+            }
+            None => {
+                for (_, handle) in requests.drain() {
+                    handle.abort();
+                }
+            }
+        }
+    }
+}
+
+// 支持的控制命令，与 handle_message 中的分发一致，GET /api/capabilities 据此列出
+pub const COMMANDS: &[&str] = &[
+    "hello",
+    "sample",
+    "sample_multi",
+    "entropy",
+    "histogram",
+    "cancel",
+    "follow",
+    "clear_cache",
+];
+
+// 处理一帧请求（二进制帧为 MessagePack，文本帧为 JSON），出错时向客户端发送带请求 ID 的错误消息
+async fn handle_frame(conn: &Connection, data: &[u8], frame_encoding: Encoding) {
+    let message: Message = match frame_encoding.decode(data) {
+        Ok(message) => message,
+        Err(e) => return conn.report_error(None, e).await,
+    };
+
+    let request_id = message.id.clone();
+    if let Err(e) = handle_message(conn, message, frame_encoding).await {
+        conn.report_error(Some(&request_id), e).await;
     }
 }
 
 async fn handle_message(
-    state: &Arc<AppState>,
-    file_id: &str,
-    data: Vec<u8>,
-    tx: &mpsc::Sender<Vec<u8>>,
+    conn: &Connection,
+    message: Message,
+    frame_encoding: Encoding,
 ) -> Result<()> {
-    // 解析消息
-    let message: Message = rmp_serde::from_slice(&data).map_err(|_| AppError::InvalidMessage)?;
-
     match message.type_field {
         MessageType::Control => {
-            let control: ControlMessage =
-                rmp_serde::from_slice(&message.payload).map_err(|_| AppError::InvalidMessage)?;
+            let control: ControlMessage = frame_encoding.decode(&message.payload)?;
+
+            // 客户端可以通过控制消息切换回复使用的编码
+            if let Some(encoding) = control.encoding.as_deref() {
+                *conn.encoding.lock().unwrap() = Encoding::parse(encoding)?;
+            }
 
             match control.command.as_str() {
+                // 仅用于协商编码
+                "hello" => {}
                 "sample" => {
+                    if !conn.sample_bucket.lock().unwrap().try_acquire() {
+                        return Err(AppError::TooManyRequests("rate limited".to_string()));
+                    }
                     let params = control.params.ok_or(AppError::BadRequest(
                         "Missing sample parameters".to_string(),
                     ))?;
@@ -95,11 +398,128 @@ async fn handle_message(
                     let request: SampleRequest = serde_json::from_value(params)
                         .map_err(|e| AppError::BadRequest(e.to_string()))?;
 
-                    // 执行采样
-                    let sample = perform_sampling(state, file_id, request.sample_size).await?;
+                    let chunk_size = clamp_chunk_size(request.chunk_size);
+
+                    // 采样和分块发送在独立任务中进行
+                    conn.spawn_request(
+                        &control.command,
+                        message.id,
+                        move |conn, request_id| async move {
+                            let sample = sample_until_shutdown(&conn, &request).await?;
+                            stream_sample(&conn, &request_id, sample, chunk_size, None).await
+                        },
+                    )?;
+                }
+                // 一次请求多个采样大小，按从小到大的顺序依次发送
+                "sample_multi" => {
+                    let params = control.params.ok_or(AppError::BadRequest(
+                        "Missing sample parameters".to_string(),
+                    ))?;
+                    let request: SampleMultiRequest = serde_json::from_value(params)
+                        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+                    let mut sample_sizes = request.sample_sizes.clone();
+                    sample_sizes.sort_unstable();
+                    sample_sizes.dedup();
+                    if sample_sizes.is_empty() || sample_sizes.len() > MAX_MULTI_SAMPLE_SIZES {
+                        return Err(AppError::BadRequest(format!(
+                            "sample_sizes must contain 1 to {} sizes",
+                            MAX_MULTI_SAMPLE_SIZES
+                        )));
+                    }
+                    // 每个采样大小按一次采样请求计入限流
+                    {
+                        let mut bucket = conn.sample_bucket.lock().unwrap();
+                        if !sample_sizes.iter().all(|_| bucket.try_acquire()) {
+                            return Err(AppError::TooManyRequests("rate limited".to_string()));
+                        }
+                    }
+
+                    let chunk_size = clamp_chunk_size(request.chunk_size);
+                    conn.spawn_request(
+                        &control.command,
+                        message.id,
+                        move |conn, request_id| async move {
+                            stream_sample_multi(
+                                &conn,
+                                &request_id,
+                                &request,
+                                sample_sizes,
+                                chunk_size,
+                            )
+                            .await
+                        },
+                    )?;
+                }
+                // 按窗口计算熵分布，每算完一个区域就发送这一段结果
+                "entropy" => {
+                    let request: EntropyRequest = control
+                        .params
+                        .map(serde_json::from_value)
+                        .transpose()
+                        .map_err(|e| AppError::BadRequest(e.to_string()))?
+                        .unwrap_or_default();
+
+                    conn.spawn_request(
+                        &control.command,
+                        message.id,
+                        move |conn, request_id| async move {
+                            stream_entropy(&conn, &request_id, request).await
+                        },
+                    )?;
+                }
+                // 字节直方图，每算完一个区域就发送当前的累计结果
+                "histogram" => {
+                    conn.spawn_request(&control.command, message.id, |conn, request_id| async move {
+                        stream_histogram(&conn, &request_id).await
+                    })?;
+                }
+                "cancel" => {
+                    let request: CancelRequest = control
+                        .params
+                        .map(serde_json::from_value)
+                        .transpose()
+                        .map_err(|e| AppError::BadRequest(e.to_string()))?
+                        .unwrap_or_default();
+
+                    conn.cancel_requests(request.request_id.as_deref());
+                }
+                // 持续发送文件新追加的数据，直到客户端取消
+                "follow" => {
+                    let request: FollowRequest = control
+                        .params
+                        .map(serde_json::from_value)
+                        .transpose()
+                        .map_err(|e| AppError::BadRequest(e.to_string()))?
+                        .unwrap_or_default();
+
+                    conn.spawn_request(
+                        &control.command,
+                        message.id,
+                        move |conn, request_id| async move {
+                            stream_follow(&conn, &request_id, request).await
+                        },
+                    )?;
+                }
+                // 调试用：清空缓存并回复清空前的统计
+                "clear_cache" => {
+                    let request: ClearCacheRequest = control
+                        .params
+                        .map(serde_json::from_value)
+                        .transpose()
+                        .map_err(|e| AppError::BadRequest(e.to_string()))?
+                        .unwrap_or_default();
+
+                    check_admin_token(&conn.state.config, request.token.as_deref())?;
+                    let stats = serde_json::to_value(clear_cache_now(&conn.state))
+                        .map_err(|e| AppError::Internal(e.into()))?;
 
-                    // 分块发送
-                    stream_sample(tx, sample).await?;
+                    let reply = ControlMessage {
+                        command: "clear_cache".to_string(),
+                        params: Some(stats),
+                        encoding: None,
+                    };
+                    conn.send(MessageType::Control, &reply).await?;
                 }
                 _ => {
                     return Err(AppError::BadRequest(format!(
@@ -117,121 +537,700 @@ async fn handle_message(
     Ok(())
 }
 
-async fn perform_sampling(
-    state: &Arc<AppState>,
-    file_id: &str,
-    sample_size: usize,
-) -> Result<Vec<u8>> {
-    info!("========== Performing Sampling ==========");
-    info!("  File ID: {}", file_id);
-    info!("  Sample size: {}", sample_size);
+// 采样数据分块发送的块大小：默认 256KB，客户端可在 16KB–4MB 内指定
+const DEFAULT_CHUNK_SIZE: usize = 256 * 1024;
+pub const MIN_CHUNK_SIZE: usize = 16 * 1024;
+pub const MAX_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+// 块大小限制在合理范围内，过小的帧开销太大，过大的帧占用过多内存
+fn clamp_chunk_size(chunk_size: Option<usize>) -> usize {
+    chunk_size
+        .unwrap_or(DEFAULT_CHUNK_SIZE)
+        .clamp(MIN_CHUNK_SIZE, MAX_CHUNK_SIZE)
+}
+
+// sample_multi 单次请求最多的采样大小个数
+const MAX_MULTI_SAMPLE_SIZES: usize = 8;
+
+// 依次采样并发送每个大小（已去重、升序），每个大小都走采样缓存；
+// 某个大小已经返回了整个采样范围时，更大的大小结果相同，直接复用
+async fn stream_sample_multi(
+    conn: &Connection,
+    request_id: &str,
+    request: &SampleMultiRequest,
+    sample_sizes: Vec<usize>,
+    chunk_size: usize,
+) -> Result<()> {
+    let mut full: Option<SampleResult> = None;
+    for sample_size in sample_sizes {
+        let sample = match &full {
+            Some(sample) => sample.clone(),
+            None => {
+                let sample = sample_until_shutdown(conn, &request.request_for(sample_size)).await?;
+                // 采样结果可能略小于请求的大小（如 uniform 按窗口取整），不能据此判断；
+                // 只有直接返回了整个采样范围的结果才与更大的大小相同
+                if sample.metadata.method == "full" {
+                    full = Some(sample.clone());
+                }
+                sample
+            }
+        };
+        stream_sample(conn, request_id, sample, chunk_size, Some(sample_size)).await?;
+    }
+    Ok(())
+}
+
+// 采样计算期间服务器开始关闭时不再等待结果，返回 ServerShuttingDown 告知客户端
+async fn sample_until_shutdown(conn: &Connection, request: &SampleRequest) -> Result<SampleResult> {
+    tokio::select! {
+        sample = perform_sampling(&conn.state, &conn.file_id, request) => sample,
+        _ = conn.state.shutdown.cancelled() => Err(AppError::ServerShuttingDown),
+    }
+}
+
+// sample_size 只在 sample_multi 请求中设置，用于标记数据和完成消息所属的采样大小
+// 服务器关闭时停止发送剩余的块，已发出的数据以 partial 的完成消息收尾，再返回 ServerShuttingDown
+async fn stream_sample(
+    conn: &Connection,
+    request_id: &str,
+    sample: SampleResult,
+    chunk_size: usize,
+    sample_size: Option<usize>,
+) -> Result<()> {
+    let total = sample.data.len();
+    let prepare = PrepareMessage {
+        request_id: request_id.to_string(),
+        total_bytes: total,
+        chunk_count: total.div_ceil(chunk_size),
+        chunk_size,
+        sample_size,
+    };
+    conn.send(MessageType::Prepare, &prepare).await?;
+
+    let mut offset = 0;
+    let mut partial = false;
+    while offset < total {
+        // 连接关闭或服务器关闭时停止发送
+        if conn.state.shutdown.is_cancelled() {
+            partial = true;
+            break;
+        }
+        if conn.closed.is_cancelled() {
+            return Ok(());
+        }
+
+        let end = (offset + chunk_size).min(total);
+        let chunk = &sample.data[offset..end];
+
+        let data_msg = DataMessage {
+            request_id: request_id.to_string(),
+            offset,
+            total,
+            chunk: chunk.to_vec(),
+            sample_size,
+        };
+
+        conn.send_data(&data_msg).await?;
+
+        offset = end;
+    }
 
-    // 验证采样大小
-    if sample_size > state.config.max_sample_size {
-        return Err(AppError::InvalidSampleSize(sample_size));
+    // 明确告知客户端该请求已结束，不必靠 offset + len == total 推断
+    let complete = CompleteMessage {
+        request_id: request_id.to_string(),
+        total: offset,
+        method: sample.metadata.method,
+        original_size: sample.metadata.original_size,
+        compute_ms: sample.metadata.compute_ms,
+        cached: sample.metadata.cached,
+        class_window: sample.metadata.class_window,
+        class_summary: sample.metadata.class_summary,
+        sample_size,
+        partial,
+    };
+    conn.send(MessageType::Complete, &complete).await?;
+    if partial {
+        return Err(AppError::ServerShuttingDown);
     }
+    Ok(())
+}
+
+// 跟随模式检查文件长度的间隔
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(500);
 
-    // 检查缓存
-    let cache_key = Cache::make_key(file_id, sample_size);
-    if let Some(cached) = state.cache.get(cache_key) {
-        info!("  Cache HIT - returning cached data");
-        info!("  Cached data size: {} bytes", cached.len());
-        info!("  First 16 bytes: {:02x?}", &cached[..16.min(cached.len())]);
-        return Ok(cached);
+// 定期检查文件长度，把新追加的字节作为数据消息发送，total 为发送时的文件长度
+async fn stream_follow(conn: &Connection, request_id: &str, request: FollowRequest) -> Result<()> {
+    let file_manager = &conn.state.file_manager;
+    let chunk_size = clamp_chunk_size(request.chunk_size);
+
+    let len = file_manager.current_len(&conn.file_id).await?;
+    let mut offset = request.offset.unwrap_or(len);
+    if offset > len {
+        return Err(AppError::BadRequest(format!(
+            "offset {} exceeds file size {}",
+            offset, len
+        )));
     }
 
-    info!("  Cache MISS - performing new sampling");
+    let mut poll = tokio::time::interval(FOLLOW_POLL_INTERVAL);
+    loop {
+        poll.tick().await;
+        if conn.closed.is_cancelled() {
+            return Ok(());
+        }
 
-    // 内存映射文件
-    let mmap = state.file_manager.mmap_file(file_id)?;
-    info!("  File mapped, size: {} bytes", mmap.len());
+        let len = file_manager.current_len(&conn.file_id).await?;
+        if len < offset {
+            return Err(AppError::BadRequest(format!(
+                "File truncated to {} bytes while following at offset {}",
+                len, offset
+            )));
+        }
 
-    // 执行采样
-    let sampler = UniformSampler;
-    let result = sampler.sample(mmap, sample_size)?;
-    info!("  Sampling complete, result size: {} bytes", result.data.len());
-    info!("  First 16 bytes: {:02x?}", &result.data[..16.min(result.data.len())]);
+        while offset < len {
+            let end = (offset + chunk_size).min(len);
+            let chunk = file_manager
+                .read_range(&conn.file_id, offset, end - offset)
+                .await?;
 
-    // 更新缓存
-    state.cache.put(cache_key, result.data.clone());
-    info!("  Data cached with key: {}", cache_key);
+            let data_msg = DataMessage {
+                request_id: request_id.to_string(),
+                offset,
+                total: len,
+                chunk,
+                sample_size: None,
+            };
+            conn.send_data(&data_msg).await?;
 
-    Ok(result.data)
+            offset = end;
+        }
+    }
 }
 
-async fn stream_sample(tx: &mpsc::Sender<Vec<u8>>, sample: Vec<u8>) -> Result<()> {
-    const CHUNK_SIZE: usize = 256 * 1024; // 256KB per chunk
+// 分析任务每次在线程池中处理的区域大小，处理完一个区域发送一次结果和进度
+const ANALYSIS_REGION: usize = 64 * 1024 * 1024;
 
-    let total = sample.len();
-    let mut offset = 0;
+const DEFAULT_ENTROPY_WINDOW: usize = 64 * 1024;
+const MIN_ENTROPY_WINDOW: usize = 256;
+const MAX_ENTROPY_WINDOWS: usize = 1 << 20;
 
-    while offset < total {
-        let end = (offset + CHUNK_SIZE).min(total);
-        let chunk = &sample[offset..end];
+async fn stream_entropy(
+    conn: &Connection,
+    request_id: &str,
+    request: EntropyRequest,
+) -> Result<()> {
+    let state = &conn.state;
+    let window_size = request.window_size.unwrap_or(DEFAULT_ENTROPY_WINDOW);
+    if window_size < MIN_ENTROPY_WINDOW {
+        return Err(AppError::BadRequest(format!(
+            "window_size must be at least {}",
+            MIN_ENTROPY_WINDOW
+        )));
+    }
 
-        let data_msg = DataMessage {
+    let with_highbit = match request.metric.as_deref().unwrap_or("entropy") {
+        "entropy" => false,
+        "highbit" => true,
+        other => {
+            return Err(AppError::BadRequest(format!(
+                "Unknown entropy metric: {}",
+                other
+            )))
+        }
+    };
+
+    let file_size = state.file_manager.file_size(&conn.file_id).await?;
+    let total = file_size.div_ceil(window_size);
+    if total > MAX_ENTROPY_WINDOWS {
+        return Err(AppError::BadRequest(format!(
+            "window_size too small: {} windows exceeds the limit of {}",
+            total, MAX_ENTROPY_WINDOWS
+        )));
+    }
+
+    // 区域按窗口对齐，保证每个窗口都完整落在一个区域内
+    let windows_per_region = (ANALYSIS_REGION / window_size).max(1);
+    let region_size = windows_per_region * window_size;
+
+    let entropy_message =
+        |offset: usize, values: Vec<f32>, highbit: Option<Vec<f32>>| EntropyMessage {
+            request_id: request_id.to_string(),
             offset,
             total,
-            chunk: chunk.to_vec(),
+            window_size,
+            values,
+            highbit,
         };
 
-        // 使用 Map 格式序列化,与外层 Message 保持一致
-        let mut payload = Vec::new();
-        data_msg.serialize(&mut rmp_serde::Serializer::new(&mut payload).with_struct_map())
-            .map_err(|e| AppError::Internal(e.into()))?;
+    // 熵和高位字节比例分别缓存，只请求熵时不必计算比例
+    let key = make_analysis_key(&conn.file_id, "entropy", window_size, file_size);
+    let highbit_key = make_analysis_key(&conn.file_id, "highbit", window_size, file_size);
+    let cached = state.entropy_cache.get(&key).and_then(|values| {
+        if !with_highbit {
+            return Some((values, None));
+        }
+        let highbit = state.entropy_cache.get(&highbit_key)?;
+        Some((values, Some(highbit)))
+    });
+    if let Some((values, highbit)) = cached {
+        for (index, batch) in values.chunks(windows_per_region).enumerate() {
+            let offset = index * windows_per_region;
+            let highbit = highbit
+                .as_ref()
+                .map(|highbit| highbit[offset..offset + batch.len()].to_vec());
+            let message = entropy_message(offset, batch.to_vec(), highbit);
+            conn.send(MessageType::Data, &message).await?;
+        }
+        return send_progress(conn, request_id, file_size, file_size).await;
+    }
 
-        let message = Message {
-            type_field: MessageType::Data,
-            id: Uuid::new_v4().to_string(),
-            timestamp: chrono::Utc::now().timestamp() as u64,
-            payload,
-        };
+    let mmap = state.file_manager.mmap_file(&conn.file_id)?;
+    let mut values = Vec::with_capacity(total);
+    let mut highbit_values = Vec::with_capacity(if with_highbit { total } else { 0 });
+    // 只统计计算耗时，不含发送时等待客户端的时间
+    let mut compute = Duration::ZERO;
+    let mut start = 0;
+    loop {
+        // 连接关闭或服务器关闭时停止计算
+        if conn.closed.is_cancelled() {
+            return Ok(());
+        }
 
-        // 使用命名格式序列化，而不是数组格式
-        let mut packed = Vec::new();
-        message.serialize(&mut rmp_serde::Serializer::new(&mut packed).with_struct_map())
-            .map_err(|e| AppError::Internal(e.into()))?;
+        let end = (start + region_size).min(file_size);
+        let region = mmap.clone();
+        let computed = Instant::now();
+        // 分段计算期间文件可能被外部截断，每段切片之前检查
+        let (batch, highbit) = state
+            .run_on_pool(move || {
+                region.ensure_intact()?;
+                Ok::<_, AppError>(analysis::entropy_profile(
+                    &region[start..end],
+                    window_size,
+                    with_highbit,
+                ))
+            })
+            .await??;
+        compute += computed.elapsed();
 
-        tx.send(packed)
-            .await
-            .map_err(|_| AppError::ConnectionClosed)?;
+        let message = entropy_message(values.len(), batch.clone(), highbit.clone());
+        conn.send(MessageType::Data, &message).await?;
+        values.extend(batch);
+        highbit_values.extend(highbit.into_iter().flatten());
+        send_progress(conn, request_id, end, file_size).await?;
 
-        offset = end;
+        start = end;
+        if start >= file_size {
+            break;
+        }
+    }
+
+    state.entropy_cache.put(key, values, compute);
+    if with_highbit {
+        state.entropy_cache.put(highbit_key, highbit_values, compute);
+    }
+    Ok(())
+}
 
-        // 小延迟避免拥塞
-        tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+async fn stream_histogram(conn: &Connection, request_id: &str) -> Result<()> {
+    let state = &conn.state;
+    let file_size = state.file_manager.file_size(&conn.file_id).await?;
+
+    let histogram_message = |counts: &[u64; 256]| HistogramMessage {
+        request_id: request_id.to_string(),
+        counts: counts.to_vec(),
+        entropy: analysis::entropy_from_histogram(counts),
+    };
+
+    let key = make_analysis_key(&conn.file_id, "histogram", 0, file_size);
+    if let Some(counts) = state.histogram_cache.get(&key) {
+        let message = histogram_message(&counts);
+        conn.send(MessageType::Data, &message).await?;
+        return send_progress(conn, request_id, file_size, file_size).await;
+    }
+
+    let mmap = state.file_manager.mmap_file(&conn.file_id)?;
+    let mut counts = [0u64; 256];
+    let mut compute = Duration::ZERO;
+    let mut start = 0;
+    loop {
+        if conn.closed.is_cancelled() {
+            return Ok(());
+        }
+
+        let end = (start + ANALYSIS_REGION).min(file_size);
+        let region = mmap.clone();
+        let computed = Instant::now();
+        let partial = state
+            .run_on_pool(move || {
+                region.ensure_intact()?;
+                Ok::<_, AppError>(analysis::histogram(&region[start..end]))
+            })
+            .await??;
+        compute += computed.elapsed();
+        for (count, value) in counts.iter_mut().zip(partial) {
+            *count += value;
+        }
+
+        // 发送到目前为止的累计计数
+        let message = histogram_message(&counts);
+        conn.send(MessageType::Data, &message).await?;
+        send_progress(conn, request_id, end, file_size).await?;
+
+        start = end;
+        if start >= file_size {
+            break;
+        }
     }
 
+    state.histogram_cache.put(key, counts, compute);
     Ok(())
 }
 
-async fn send_error(tx: &mpsc::Sender<Vec<u8>>, error: AppError) -> Result<()> {
+async fn send_progress(
+    conn: &Connection,
+    request_id: &str,
+    processed: usize,
+    total: usize,
+) -> Result<()> {
+    let progress = ProgressMessage {
+        request_id: request_id.to_string(),
+        processed,
+        total,
+    };
+
+    conn.send(MessageType::Progress, &progress).await
+}
+
+async fn send_error(conn: &Connection, request_id: Option<&str>, error: AppError) -> Result<()> {
     let error_msg = ErrorMessage {
-        code: 500,
+        request_id: request_id.map(str::to_string),
+        code: error.ws_code(),
         message: error.to_string(),
-        details: None,
+        details: Some(serde_json::json!({ "kind": error.kind() })),
     };
 
-    // 使用 Map 格式序列化,与外层 Message 保持一致
-    let mut payload = Vec::new();
-    error_msg.serialize(&mut rmp_serde::Serializer::new(&mut payload).with_struct_map())
-        .map_err(|e| AppError::Internal(e.into()))?;
+    conn.send(MessageType::Error, &error_msg).await
+}
 
-    let message = Message {
-        type_field: MessageType::Error,
-        id: Uuid::new_v4().to_string(),
-        timestamp: chrono::Utc::now().timestamp() as u64,
-        payload,
-    };
+// 构造一帧消息
+fn encode_frame<T: Serialize>(
+    encoding: Encoding,
+    type_field: MessageType,
+    payload: &T,
+) -> Result<WsMessage> {
+    let id = Uuid::new_v4().to_string();
+    let timestamp = chrono::Utc::now().timestamp() as u64;
 
-    // 使用命名格式序列化
-    let mut packed = Vec::new();
-    message.serialize(&mut rmp_serde::Serializer::new(&mut packed).with_struct_map())
-        .map_err(|e| AppError::Internal(e.into()))?;
+    match encoding {
+        Encoding::MessagePack => {
+            // 使用 Map 格式序列化,与外层 Message 保持一致
+            let mut packed_payload = Vec::new();
+            payload
+                .serialize(&mut rmp_serde::Serializer::new(&mut packed_payload).with_struct_map())
+                .map_err(|e| AppError::Internal(e.into()))?;
 
-    tx.send(packed)
-        .await
-        .map_err(|_| AppError::ConnectionClosed)?;
+            let message = Message {
+                type_field,
+                id,
+                timestamp,
+                payload: packed_payload,
+            };
 
-    Ok(())
+            // 使用命名格式序列化，而不是数组格式
+            let mut packed = Vec::new();
+            message
+                .serialize(&mut rmp_serde::Serializer::new(&mut packed).with_struct_map())
+                .map_err(|e| AppError::Internal(e.into()))?;
+
+            Ok(WsMessage::Binary(packed))
+        }
+        Encoding::Json => {
+            // JSON 模式下 payload 直接内嵌为对象，便于在浏览器控制台阅读
+            let message = serde_json::json!({
+                "type": type_field,
+                "id": id,
+                "timestamp": timestamp,
+                "payload": payload,
+            });
+
+            Ok(WsMessage::Text(message.to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::{ControlMessage, Message, MessageType};
+    use crate::server::testing::{test_data, TestApp};
+    use axum::{body::Body, http::Request, http::StatusCode};
+    use futures::{SinkExt, StreamExt};
+    use serde::Serialize;
+    use serde_json::{json, Value};
+    use std::net::SocketAddr;
+    use std::sync::atomic::Ordering;
+    use std::time::{Duration, Instant};
+    use tokio::net::TcpStream;
+    use tokio_tungstenite::tungstenite::Message as Frame;
+    use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+    // 测试用的客户端：请求以 MessagePack 发送，并要求服务器以 JSON 回复，便于断言
+    struct Client {
+        ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    }
+
+    impl Client {
+        async fn connect(addr: SocketAddr, file_id: &str) -> Self {
+            let url = format!("ws://{}/ws/{}", addr, file_id);
+            let (ws, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+            Self { ws }
+        }
+
+        async fn send(&mut self, id: &str, command: &str, params: Value) {
+            let control = ControlMessage {
+                command: command.to_string(),
+                params: Some(params),
+                encoding: Some("json".to_string()),
+            };
+            let message = Message {
+                type_field: MessageType::Control,
+                id: id.to_string(),
+                timestamp: 0,
+                payload: to_msgpack(&control),
+            };
+            self.ws
+                .send(Frame::Binary(to_msgpack(&message)))
+                .await
+                .unwrap();
+        }
+
+        // 下一条 JSON 消息，跳过心跳
+        async fn recv(&mut self) -> Value {
+            loop {
+                let frame = tokio::time::timeout(Duration::from_secs(10), self.ws.next())
+                    .await
+                    .expect("timed out waiting for a message")
+                    .expect("connection closed")
+                    .unwrap();
+                match frame {
+                    Frame::Text(text) => return serde_json::from_str(&text).unwrap(),
+                    Frame::Ping(_) | Frame::Pong(_) => continue,
+                    other => panic!("unexpected frame {:?}", other),
+                }
+            }
+        }
+
+        // 读取消息直到收到指定请求的完成消息或错误，返回途中的全部消息
+        async fn recv_until_done(&mut self, request_id: &str) -> Vec<Value> {
+            let mut messages = Vec::new();
+            loop {
+                let message = self.recv().await;
+                let done = matches!(message["type"].as_str(), Some("complete" | "error"))
+                    && message["payload"]["request_id"] == request_id;
+                messages.push(message);
+                if done {
+                    return messages;
+                }
+            }
+        }
+    }
+
+    fn to_msgpack<T: Serialize>(value: &T) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        value
+            .serialize(&mut rmp_serde::Serializer::new(&mut bytes).with_struct_map())
+            .unwrap();
+        bytes
+    }
+
+    #[tokio::test]
+    async fn oversized_sample_request_reports_400_and_keeps_connection() {
+        let app = TestApp::with_config(|config| config.max_sample_size = 4096);
+        let file_id = app.upload(&test_data(64 * 1024)).await;
+        let mut client = Client::connect(app.serve().await, &file_id).await;
+
+        client
+            .send("big", "sample", json!({ "sample_size": 8192 }))
+            .await;
+        let error = client.recv().await;
+        assert_eq!(error["type"], "error");
+        assert_eq!(error["payload"]["request_id"], "big");
+        assert_eq!(error["payload"]["code"], 400);
+        assert_eq!(error["payload"]["details"]["kind"], "invalid_sample_size");
+
+        // 同一连接上的下一个请求照常完成
+        client
+            .send("small", "sample", json!({ "sample_size": 1024 }))
+            .await;
+        let messages = client.recv_until_done("small").await;
+        let complete = messages.last().unwrap();
+        assert_eq!(complete["type"], "complete");
+        assert_eq!(complete["payload"]["total"], 1024);
+    }
+
+    #[tokio::test]
+    async fn deleting_a_file_mid_stream_completes_the_stream() {
+        let app = TestApp::new();
+        let data = test_data(4 * 1024 * 1024);
+        let file_id = app.upload(&data).await;
+        let mut client = Client::connect(app.serve().await, &file_id).await;
+
+        // 采样大小不小于文件时直接返回整个文件的映射，按最小的块发送，删除时还有大量块未发出
+        let params = json!({ "sample_size": data.len(), "chunk_size": 16 * 1024 });
+        client.send("full", "sample", params).await;
+        assert_eq!(client.recv().await["type"], "prepare");
+        let first = client.recv().await;
+        assert_eq!(first["type"], "data");
+
+        let delete = Request::delete(format!("/api/files/{}", file_id))
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(app.request(delete).await.status(), StatusCode::OK);
+
+        let mut received = Vec::new();
+        let mut messages = vec![first];
+        messages.extend(client.recv_until_done("full").await);
+        let complete = messages.pop().unwrap();
+        for message in messages {
+            assert_eq!(message["type"], "data");
+            let payload = &message["payload"];
+            assert_eq!(payload["offset"], received.len());
+            let chunk: Vec<u8> = serde_json::from_value(payload["chunk"].clone()).unwrap();
+            received.extend(chunk);
+        }
+        assert_eq!(complete["type"], "complete");
+        assert_eq!(complete["payload"]["method"], "full");
+        assert_eq!(complete["payload"]["partial"], false);
+        assert!(
+            received == data,
+            "streamed bytes differ from the deleted file"
+        );
+
+        // 删除之后的新请求得到 404
+        client
+            .send("again", "sample", json!({ "sample_size": 1024 }))
+            .await;
+        let error = client.recv().await;
+        assert_eq!(error["payload"]["code"], 404);
+    }
+
+    #[tokio::test]
+    async fn duplicate_request_id_is_rejected_while_in_progress() {
+        let app = TestApp::new();
+        let file_id = app.upload(&test_data(64 * 1024)).await;
+        let mut client = Client::connect(app.serve().await, &file_id).await;
+
+        // follow 在取消之前一直进行
+        client.send("dup", "follow", json!({})).await;
+        client.send("dup", "follow", json!({})).await;
+        let error = client.recv().await;
+        assert_eq!(error["type"], "error");
+        assert_eq!(error["payload"]["request_id"], "dup");
+        assert_eq!(error["payload"]["code"], 409);
+
+        // 取消之后可以重新使用这个 ID
+        client
+            .send("stop", "cancel", json!({ "request_id": "dup" }))
+            .await;
+        client.send("dup", "histogram", json!({})).await;
+        loop {
+            let message = client.recv().await;
+            assert_ne!(message["type"], "error");
+            let payload = &message["payload"];
+            if message["type"] == "progress" && payload["processed"] == payload["total"] {
+                break;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn unparseable_frame_error_has_no_request_id() {
+        let app = TestApp::new();
+        let file_id = app.upload(&test_data(1024)).await;
+        let mut client = Client::connect(app.serve().await, &file_id).await;
+
+        client.send("hello", "hello", json!({})).await;
+        client
+            .ws
+            .send(Frame::Text("not json".into()))
+            .await
+            .unwrap();
+        let error = client.recv().await;
+        assert_eq!(error["type"], "error");
+        assert_eq!(error["payload"]["details"]["kind"], "invalid_message");
+        assert!(error["payload"].get("request_id").is_none());
+    }
+
+    #[tokio::test]
+    async fn shutdown_error_names_the_interrupted_request() {
+        let app = TestApp::new();
+        let data = test_data(4 * 1024 * 1024);
+        let file_id = app.upload(&data).await;
+        let mut client = Client::connect(app.serve().await, &file_id).await;
+
+        let params = json!({ "sample_size": data.len(), "chunk_size": 16 * 1024 });
+        client.send("full", "sample", params).await;
+        assert_eq!(client.recv().await["type"], "prepare");
+        assert_eq!(client.recv().await["type"], "data");
+        app.state.shutdown.cancel();
+
+        // 已发出的数据以 partial 的完成消息收尾，随后是该请求的关闭错误
+        let complete = client.recv_until_done("full").await.pop().unwrap();
+        assert_eq!(complete["type"], "complete");
+        assert_eq!(complete["payload"]["request_id"], "full");
+        assert_eq!(complete["payload"]["partial"], true);
+        let error = client.recv().await;
+        assert_eq!(error["type"], "error");
+        assert_eq!(error["payload"]["request_id"], "full");
+        assert_eq!(error["payload"]["code"], 503);
+        assert_eq!(error["payload"]["details"]["kind"], "server_shutting_down");
+    }
+
+    #[tokio::test]
+    async fn concurrent_upgrades_respect_the_connection_limit() {
+        let app = TestApp::with_config(|config| config.max_connections = 2);
+        let file_id = app.upload(&test_data(1024)).await;
+        let addr = app.serve().await;
+
+        // 同时发起的握手只有两个能占到名额
+        let url = format!("ws://{}/ws/{}", addr, file_id);
+        let attempts = (0..8).map(|_| tokio_tungstenite::connect_async(url.clone()));
+        let results = futures::future::join_all(attempts).await;
+        let (accepted, rejected): (Vec<_>, Vec<_>) = results.into_iter().partition(|r| r.is_ok());
+        assert_eq!(accepted.len(), 2);
+        for result in rejected {
+            match result {
+                Err(tokio_tungstenite::tungstenite::Error::Http(response)) => {
+                    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS)
+                }
+                other => panic!("unexpected handshake result {:?}", other.map(|_| ())),
+            }
+        }
+        assert_eq!(app.state.active_connections.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn idle_timeout_releases_the_connection_slot() {
+        let app = TestApp::with_config(|config| {
+            config.max_connections = 1;
+            config.ws_idle_secs = 1;
+        });
+        let file_id = app.upload(&test_data(1024)).await;
+        let addr = app.serve().await;
+
+        // 不读取也不回应心跳的客户端占着唯一的名额
+        let _idle = Client::connect(addr, &file_id).await;
+        let url = format!("ws://{}/ws/{}", addr, file_id);
+        assert!(tokio_tungstenite::connect_async(url.clone()).await.is_err());
+
+        // 空闲超时关闭连接后名额归还
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while app.state.active_connections.load(Ordering::Relaxed) > 0 {
+            assert!(
+                Instant::now() < deadline,
+                "idle connection was not released"
+            );
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert!(tokio_tungstenite::connect_async(url).await.is_ok());
+    }
 }
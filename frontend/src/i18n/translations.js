@@ -38,7 +38,10 @@ export const translations = {
     'panel.fps': 'FPS:',
     'panel.pointCount': '点数:',
     'panel.progress': '进度:',
+    'panel.sampling': '采样:',
     'panel.fileSize': '大小:',
+    'panel.sampledIn': '耗时 {ms} ms',
+    'panel.fromCache': '来自缓存',
 
     // 控制台日志
     'log.selectingFile': '选择文件',
@@ -103,7 +106,10 @@ export const translations = {
     'panel.fps': 'FPS:',
     'panel.pointCount': 'Points:',
     'panel.progress': 'Progress:',
+    'panel.sampling': 'Sampling:',
     'panel.fileSize': 'Size:',
+    'panel.sampledIn': 'sampled in {ms} ms',
+    'panel.fromCache': 'served from cache',
 
     // Console logs
     'log.selectingFile': 'Selecting file',
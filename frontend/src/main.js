@@ -117,6 +117,27 @@ class BinaryVisApp {
     console.log('BinaryVis initialized');
   }
 
+  // 轮询上传进度，返回停止轮询的函数
+  pollUploadProgress(file, fingerprint) {
+    const timer = setInterval(async () => {
+      try {
+        const response = await fetch(
+          `${CONFIG.API_URL}/files/${encodeURIComponent(fingerprint)}/status`
+        );
+        if (!response.ok) return;
+
+        const status = await response.json();
+        if (status.status === 'uploading') {
+          this.controlPanel.updateFileInfo(file, true, status.bytes_written);
+        }
+      } catch (error) {
+        // 进度只用于显示，查询失败时忽略
+      }
+    }, 500);
+
+    return () => clearInterval(timer);
+  }
+
   // 处理文件选择
   async handleFileSelect(file) {
     try {
@@ -170,14 +191,21 @@ class BinaryVisApp {
 
       // 文件不存在，需要上传
       console.log('  File does not exist, uploading...');
+      // 指纹放在文件前面，服务器才能边接收边写盘并汇报进度
       const formData = new FormData();
-      formData.append('file', file);
       formData.append('fingerprint', fingerprint);
+      formData.append('file', file);
 
-      const uploadResponse = await fetch(`${CONFIG.API_URL}/upload`, {
-        method: 'POST',
-        body: formData,
-      });
+      const stopProgress = this.pollUploadProgress(file, fingerprint);
+      let uploadResponse;
+      try {
+        uploadResponse = await fetch(`${CONFIG.API_URL}/upload`, {
+          method: 'POST',
+          body: formData,
+        });
+      } finally {
+        stopProgress();
+      }
 
       if (!uploadResponse.ok) {
         const error = await uploadResponse.json();
@@ -241,6 +269,12 @@ class BinaryVisApp {
       this.wsClient = new WebSocketClient(wsUrl);
       window.wsClient = this.wsClient;
 
+      // 采样完成后服务器先告知总大小，在第一块数据到达前分配好缓冲区
+      this.wsClient.on('prepare', (payload) => {
+        console.log('Initializing buffer with size:', payload.total_bytes);
+        this.dataManager.initialize(payload.total_bytes);
+      });
+
       // 注册数据消息处理器
       this.wsClient.on('data', (payload) => {
         // 初始化缓冲区
@@ -255,19 +289,20 @@ class BinaryVisApp {
           : new Uint8Array(payload.chunk);
 
         // 添加数据块
-        const complete = this.dataManager.addChunk(
-          payload.offset,
-          chunk
-        );
+        this.dataManager.addChunk(payload.offset, chunk);
 
         // 更新进度
         this.controlPanel.updateStatus({
           progress: this.dataManager.getProgress(),
         });
+      });
 
-        if (complete) {
-          console.log('Data transfer complete');
-        }
+      // 服务器发完最后一块后发送完成消息
+      this.wsClient.on('complete', (payload) => {
+        console.log(
+          `Data transfer complete: ${payload.total} bytes sampled from ${payload.original_size} (${payload.method})`
+        );
+        this.controlPanel.updateStatus({ sample: payload });
       });
 
       await this.wsClient.connect();
@@ -93,6 +93,7 @@ export class ControlPanel {
             <div>${i18n.t('panel.fps')} <span id="fps">0</span></div>
             <div>${i18n.t('panel.pointCount')} <span id="point-count">0</span></div>
             <div>${i18n.t('panel.progress')} <span id="progress">0%</span></div>
+            <div>${i18n.t('panel.sampling')} <span id="sample-timing">-</span></div>
           </div>
         </div>
       </div>
@@ -228,15 +229,20 @@ export class ControlPanel {
   }
 
   // 更新文件信息
-  updateFileInfo(file, uploading = false) {
+  updateFileInfo(file, uploading = false, bytesWritten = null) {
     const fileInfo = document.getElementById('file-info');
     const sizeMB = (file.size / 1024 / 1024).toFixed(2);
 
     if (uploading) {
+      // 有服务器汇报的进度时显示百分比
+      const progress =
+        bytesWritten !== null && file.size > 0
+          ? ` ${Math.min(100, (bytesWritten / file.size) * 100).toFixed(0)}%`
+          : '';
       fileInfo.innerHTML = `
         <div><strong>${file.name}</strong></div>
         <div>${i18n.t('panel.fileSize')} ${sizeMB} MB</div>
-        <div style="color: #ffa500;">${i18n.t('file.uploading')}</div>
+        <div style="color: #ffa500;">${i18n.t('file.uploading')}${progress}</div>
       `;
     } else {
       fileInfo.innerHTML = `
@@ -260,6 +266,12 @@ export class ControlPanel {
       document.getElementById('progress').textContent =
         (status.progress * 100).toFixed(1) + '%';
     }
+    if (status.sample !== undefined) {
+      // 服务器汇报的采样耗时，命中缓存时显示来自缓存
+      document.getElementById('sample-timing').textContent = status.sample.cached
+        ? i18n.t('panel.fromCache')
+        : i18n.t('panel.sampledIn', { ms: status.sample.compute_ms });
+    }
   }
 
   // 设置按钮状态